@@ -1,10 +1,17 @@
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
-use syn::Lit;
-use syn::{Expr, ExprLit, FnArg, ItemFn, Pat, PatType, Type, parse_macro_input};
+use syn::parse::ParseStream;
+use syn::{Attribute, Lit};
+use syn::{
+    Expr, ExprArray, ExprLit, FnArg, ItemFn, Pat, PatType, ReturnType, Signature, Type,
+    parse_macro_input,
+};
 
 /// A procedural macro that generates a tool with parameter schema from a function signature
 ///
+/// The function must take `&mut RunContext` as its first parameter; the rest become the
+/// tool's parameters.
+///
 /// Usage:
 /// ```ignore
 ///
@@ -31,10 +38,43 @@ use syn::{Expr, ExprLit, FnArg, ItemFn, Pat, PatType, Type, parse_macro_input};
 ///     result.to_string()
 /// }
 /// ```
+///
+/// Functions that can fail may return `AgentResult<String>` (or `Result<String, AgentError>`)
+/// instead, and their errors are returned from the tool:
+/// ```ignore
+/// #[tool_fn(name = "divide", description = "Divide two numbers")]
+/// fn divide(_context: &mut RunContext, a: f64, b: f64) -> AgentResult<String> {
+///     if b == 0.0 {
+///         return Err(AgentError::InvalidInput("Division by zero".into()));
+///     }
+///     Ok((a / b).to_string())
+/// }
+/// ```
+///
+/// String parameters can be restricted to a fixed set of values with `#[param(enum = [...])]`,
+/// which adds an `enum` constraint to the generated schema and rejects other values with a
+/// validation error:
+/// ```ignore
+/// #[tool_fn(name = "calculator", description = "A simple calculator")]
+/// fn calculator(
+///     _context: &mut RunContext,
+///     a: f64,
+///     b: f64,
+///     #[param(enum = ["add", "subtract", "multiply", "divide"])] operation: String,
+/// ) -> String {
+///     // ...
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn tool_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Parse the function definition
-    let input_fn = parse_macro_input!(item as ItemFn);
+    let mut input_fn = parse_macro_input!(item as ItemFn);
+
+    // Extract parameter information from function signature
+    let params = match extract_params(&mut input_fn) {
+        Ok(params) => params,
+        Err(err) => return err.to_compile_error().into(),
+    };
     let fn_name = &input_fn.sig.ident;
     let fn_name_str = fn_name.to_string();
 
@@ -55,82 +95,133 @@ pub fn tool_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
                 {
                     tool_name = lit_str.value();
                 }
-            } else if name_value.path.is_ident("description") {
-                if let Expr::Lit(ExprLit {
+            } else if name_value.path.is_ident("description")
+                && let Expr::Lit(ExprLit {
                     lit: Lit::Str(lit_str),
                     ..
                 }) = &name_value.value
-                {
-                    tool_description = lit_str.value();
-                }
+            {
+                tool_description = lit_str.value();
             }
         }
     }
 
-    // Extract parameter information from function signature
-    let params = extract_params(&input_fn);
-
     // Generate the tool function name (append _tool to the original function name)
     let tool_fn_name = format_ident!("{}_tool", fn_name);
 
     // Generate parameter extraction and conversion code
-    let param_extractions = params.iter().map(|(name, type_name)| {
+    //
+    // Missing or mistyped arguments are reported as validation errors naming the field.
+    let param_extractions = params.iter().map(|param| {
+        let name = &param.name;
         let param_name = format_ident!("{}", name);
-        match type_name.as_str() {
-            "i32" => quote! {
-                let #param_name = params[#name].as_i64()
-                    .ok_or_else(|| AgentError::InvalidInput(format!("Missing or invalid parameter: {}", #name)))?
-                    as i32;
-            },
-            "i64" => quote! {
-                let #param_name = params[#name].as_i64()
-                    .ok_or_else(|| AgentError::InvalidInput(format!("Missing or invalid parameter: {}", #name)))?;
-            },
-            "u32" | "u64" => quote! {
-                let #param_name = params[#name].as_u64()
-                    .ok_or_else(|| AgentError::InvalidInput(format!("Missing or invalid parameter: {}", #name)))?
-                    as u32;
-            },
-            "f32" | "f64" => quote! {
-                let #param_name = params[#name].as_f64()
-                    .ok_or_else(|| AgentError::InvalidInput(format!("Missing or invalid parameter: {}", #name)))?
-                    as f64;
-            },
-            "String" => quote! {
-                let #param_name = params[#name].as_str()
-                    .ok_or_else(|| AgentError::InvalidInput(format!("Missing or invalid parameter: {}", #name)))?
-                    .to_string();
-            },
-            "&str" => quote! {
-                let #param_name = params[#name].as_str()
-                    .ok_or_else(|| AgentError::InvalidInput(format!("Missing or invalid parameter: {}", #name)))?;
-            },
-            "bool" => quote! {
-                let #param_name = params[#name].as_bool()
-                    .ok_or_else(|| AgentError::InvalidInput(format!("Missing or invalid parameter: {}", #name)))?;
-            },
-            _ => quote! {
-                let #param_name = serde_json::from_value::<#param_name>(params[#name].clone())
-                    .map_err(|e| AgentError::InvalidInput(format!("Invalid parameter {}: {}", #name, e)))?;
-            },
+        let ty = &param.ty;
+        let validation_error = |message: proc_macro2::TokenStream| {
+            quote! {
+                AgentError::ValidationError {
+                    field: #name.to_string(),
+                    message: #message,
+                }
+            }
+        };
+        let missing = validation_error(quote! { "is required".to_string() });
+        let expected = |expected: &str| {
+            let message = format!("expected {}", expected);
+            validation_error(quote! { #message.to_string() })
+        };
+
+        // Reject strings outside the allowed values, if any were given
+        let check_enum = param.enum_values.as_ref().map(|values| {
+            let invalid = expected(&format!("one of: {}", values.join(", ")));
+            quote! {
+                if ![#(#values),*].contains(&value) {
+                    return Err(#invalid);
+                }
+            }
+        });
+
+        let conversion = match param.type_name.as_str() {
+            "i32" | "i64" => {
+                let invalid = expected("integer");
+                quote! { value.as_i64().ok_or_else(|| #invalid)? as #ty }
+            }
+            "u32" | "u64" => {
+                let invalid = expected("non-negative integer");
+                quote! { value.as_u64().ok_or_else(|| #invalid)? as #ty }
+            }
+            "f32" | "f64" => {
+                let invalid = expected("number");
+                quote! { value.as_f64().ok_or_else(|| #invalid)? as #ty }
+            }
+            "String" => {
+                let invalid = expected("string");
+                quote! {{
+                    let value = value.as_str().ok_or_else(|| #invalid)?;
+                    #check_enum
+                    value.to_string()
+                }}
+            }
+            "&str" => {
+                let invalid = expected("string");
+                quote! {{
+                    let value = value.as_str().ok_or_else(|| #invalid)?;
+                    #check_enum
+                    value
+                }}
+            }
+            "bool" => {
+                let invalid = expected("boolean");
+                quote! { value.as_bool().ok_or_else(|| #invalid)? }
+            }
+            _ => {
+                let invalid = validation_error(quote! { e.to_string() });
+                quote! { serde_json::from_value::<#ty>(value.clone()).map_err(|e| #invalid)? }
+            }
+        };
+
+        quote! {
+            let #param_name = {
+                let value = params
+                    .get(#name)
+                    .filter(|value| !value.is_null())
+                    .ok_or_else(|| #missing)?;
+                #conversion
+            };
         }
     });
 
     // Collect parameter names for the function call
-    let param_names = params.iter().map(|(name, _)| format_ident!("{}", name));
+    let param_names = params.iter().map(|param| format_ident!("{}", param.name));
+
+    // Propagate the errors of functions that return a `Result`
+    let propagate = returns_result(&input_fn).then(|| quote! { ? });
 
     // Generate the schema properties
-    let schema_properties = params.iter().map(|(name, type_name)| {
-        let type_str = match type_name.as_str() {
+    let schema_properties = params.iter().map(|param| {
+        let name = &param.name;
+        let type_str = match param.type_name.as_str() {
             "i32" | "i64" | "u32" | "u64" | "f32" | "f64" => "number",
             "String" | "&str" => "string",
             "bool" => "boolean",
             _ => "object",
         };
 
+        // Restrict the property to the allowed values, if any were given
+        let enum_constraint = param.enum_values.as_ref().map(|values| {
+            quote! {
+                property.insert(
+                    "enum".to_string(),
+                    serde_json::Value::Array(vec![
+                        #(serde_json::Value::String(#values.to_string())),*
+                    ]),
+                );
+            }
+        });
+
         quote! {
             let mut property = serde_json::Map::new();
             property.insert("type".to_string(), serde_json::Value::String(#type_str.to_string()));
+            #enum_constraint
             properties.insert(#name.to_string(), serde_json::Value::Object(property));
             required.push(serde_json::Value::String(#name.to_string()));
         }
@@ -160,11 +251,12 @@ pub fn tool_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
                     #(#param_extractions)*
 
                     // Call the function with parsed parameters
-                    let result = #fn_name(context, #(#param_names),*);
+                    let result = #fn_name(context, #(#param_names),*)#propagate;
 
                     Ok(ToolResult {
                         tool_name: #tool_name.to_string(),
                         output: result,
+                        ..Default::default()
                     })
                 })
             )
@@ -189,30 +281,120 @@ pub fn tool_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
     expanded.into()
 }
 
+// A tool parameter taken from the function signature
+struct Param {
+    name: String,
+    type_name: String,
+    ty: Type,
+    enum_values: Option<Vec<String>>,
+}
+
 // Helper function to extract parameter info from a function
-fn extract_params(input_fn: &ItemFn) -> Vec<(String, String)> {
+//
+// The run context comes first and isn't a tool parameter. `#[param(...)]` attributes are
+// removed from the signature, since they are only meaningful to this macro.
+fn extract_params(input_fn: &mut ItemFn) -> syn::Result<Vec<Param>> {
+    check_context_param(&input_fn.sig)?;
     let mut params = Vec::new();
 
-    for arg in &input_fn.sig.inputs {
-        if let FnArg::Typed(PatType { pat, ty, .. }) = arg {
-            if let Pat::Ident(pat_ident) = &**pat {
-                let param_name = pat_ident.ident.to_string();
-                let param_type = get_type_name(ty);
-
-                // Skip the context parameter
-                if param_name != "context" && !param_type.contains("RunContext") {
-                    params.push((param_name, param_type));
+    for arg in input_fn.sig.inputs.iter_mut().skip(1) {
+        if let FnArg::Typed(PatType { attrs, pat, ty, .. }) = arg {
+            let mut enum_values = None;
+            for attr in attrs.iter().filter(|attr| attr.path().is_ident("param")) {
+                if !is_string_type(ty) {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "`enum` values can only be given for `String` or `&str` parameters",
+                    ));
                 }
+                enum_values = Some(parse_param_enum(attr)?);
+            }
+            attrs.retain(|attr| !attr.path().is_ident("param"));
+
+            if let Pat::Ident(pat_ident) = &**pat {
+                params.push(Param {
+                    name: pat_ident.ident.to_string(),
+                    type_name: get_type_name(ty),
+                    ty: (**ty).clone(),
+                    enum_values,
+                });
             }
         }
     }
 
-    params
+    Ok(params)
+}
+
+// Helper function to check that a tool function takes `&mut RunContext` as its first parameter
+fn check_context_param(sig: &Signature) -> syn::Result<()> {
+    let is_context = |arg: &FnArg| match arg {
+        FnArg::Typed(PatType { ty, .. }) => matches!(
+            &**ty,
+            Type::Reference(type_ref)
+                if type_ref.mutability.is_some() && get_type_name(ty) == "RunContext"
+        ),
+        FnArg::Receiver(_) => false,
+    };
+
+    match sig.inputs.first() {
+        Some(arg) if is_context(arg) => Ok(()),
+        first => {
+            let message = format!(
+                "the first parameter of a tool function must be the run context, \
+                 e.g. `fn {}(context: &mut RunContext, ...)`",
+                sig.ident
+            );
+            Err(match first {
+                Some(arg) => syn::Error::new_spanned(arg, message),
+                None => syn::Error::new_spanned(&sig.ident, message),
+            })
+        }
+    }
+}
+
+// Helper function to parse `#[param(enum = ["a", "b"])]` into its allowed values
+fn parse_param_enum(attr: &Attribute) -> syn::Result<Vec<String>> {
+    attr.parse_args_with(|input: ParseStream| {
+        input.parse::<syn::Token![enum]>()?;
+        input.parse::<syn::Token![=]>()?;
+        let values: ExprArray = input.parse()?;
+
+        values
+            .elems
+            .iter()
+            .map(|value| match value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(lit_str),
+                    ..
+                }) => Ok(lit_str.value()),
+                _ => Err(syn::Error::new_spanned(
+                    value,
+                    "enum values must be string literals",
+                )),
+            })
+            .collect()
+    })
+}
+
+// Helper function to check whether a parameter is a `String` or `&str`
+fn is_string_type(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(type_ref) => get_type_name(&type_ref.elem) == "str",
+        _ => get_type_name(ty) == "String",
+    }
+}
+
+// Helper function to check whether a function returns `Result` or `AgentResult`
+fn returns_result(input_fn: &ItemFn) -> bool {
+    match &input_fn.sig.output {
+        ReturnType::Type(_, ty) => matches!(get_type_name(ty).as_str(), "Result" | "AgentResult"),
+        ReturnType::Default => false,
+    }
 }
 
 // Helper function to get the name of a type
-fn get_type_name(ty: &Box<Type>) -> String {
-    match ty.as_ref() {
+fn get_type_name(ty: &Type) -> String {
+    match ty {
         Type::Path(type_path) => {
             if let Some(segment) = type_path.path.segments.last() {
                 segment.ident.to_string()
@@ -1,20 +1,120 @@
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "schemars")]
+use async_openai::types::{ResponseFormat, ResponseFormatJsonSchema};
+use async_trait::async_trait;
+use futures::channel::mpsc::{self, UnboundedSender};
+use futures::future;
+use futures::{FutureExt, Stream, StreamExt};
+#[cfg(feature = "schemars")]
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::Instrument;
 
 use crate::error::{AgentError, AgentResult};
-use crate::openai::Model;
-use crate::tool::Tool;
-use crate::types::{Context, RunContext};
+use crate::openai::{Model, ModelParams, Step, StepChunk, relax_json};
+use crate::prompt::PromptTemplate;
+use crate::session::SessionStore;
+use crate::tool::{
+    IdentityFormatter, OutputFormatter, TOOL_CALL_DENIED, Tool, ToolCall, ToolMetrics, ToolResult,
+    ToolSet, execute_tool_call, find_tool, parse_params,
+};
+use crate::types::{Context, Message, RunContext};
+
+/// Progress events emitted while an agent run is in flight
+#[derive(Debug)]
+pub enum RunEvent {
+    /// Assistant text produced by the model
+    MessageDelta { content: String },
+    /// A tool is about to be executed with the given arguments
+    ToolStarted { name: String, args: String },
+    /// A tool finished executing
+    ToolCompleted { name: String, output: String },
+    /// Running totals, sent after each model turn
+    ///
+    /// `tokens` counts what the model reported using so far; turns whose model doesn't report
+    /// usage add nothing. `tool_calls` counts the calls the model has asked for so far.
+    UsageUpdate { tokens: u64, tool_calls: usize },
+    /// The run finished with the final response
+    Completed { text: String },
+    /// The run stopped because of an error
+    Failed { error: AgentError },
+}
+
+/// A failed run together with everything the run had produced before it failed
+///
+/// Resume it by fixing the cause and passing `context` to [`Agent::run_with_context`].
+#[derive(Debug, thiserror::Error)]
+#[error("{error}")]
+pub struct RunFailure {
+    /// Why the run failed
+    pub error: AgentError,
+    /// The run's messages and data up to the failure
+    pub context: RunContext,
+}
+
+/// Computes the system prompt from the run's context
+pub type DynamicInstructionsFn = Box<dyn Fn(&Context) -> String + Send + Sync>;
+
+type SharedInstructionsFn = Arc<dyn Fn(&Context) -> String + Send + Sync>;
+
+/// Decides whether a call to a tool that requires approval may run
+pub type ApprovalHandler = Arc<dyn Fn(&ToolCall) -> bool + Send + Sync>;
+
+/// How many times `run_typed` asks the model to correct an answer that doesn't parse
+#[cfg(feature = "schemars")]
+const TYPED_ANSWER_RETRIES: usize = 2;
 
 /// An agent that can use tools and interact with a language model
+///
+/// Cloning is cheap: the model, tools and dynamic instructions are shared between clones.
+#[derive(Clone)]
 pub struct Agent {
     /// The name of the agent
     name: String,
     /// The instructions for the agent (system prompt)
     instructions: Option<String>,
+    /// Instructions computed from the context at run time, taking precedence over `instructions`
+    dynamic_instructions: Option<SharedInstructionsFn>,
+    /// Instructions rendered from the context at run time, taking precedence over `instructions`
+    instructions_template: Option<PromptTemplate>,
+    /// Whether the instructions are sent with the `developer` role instead of `system`
+    developer_instructions: bool,
+    /// Few-shot user/assistant message pairs placed after the instructions
+    examples: Vec<(String, String)>,
     /// The model to use for generating responses
     model: Arc<dyn Model>,
     /// The tools available to the agent
     tools: Vec<Arc<dyn Tool>>,
+    /// The most characters of any tool's output passed on to the model
+    max_tool_output_chars: Option<usize>,
+    /// Renders tool output before it's added to the conversation
+    output_formatter: Arc<dyn OutputFormatter>,
+    /// How many times per run a tool failure is fed back to the model instead of failing the run
+    tool_error_retries: usize,
+    /// Whether malformed tool arguments are repaired before the tool sees them
+    lenient_tool_args: bool,
+    /// Approves calls to tools that require it; without one, those calls are denied
+    approval_handler: Option<ApprovalHandler>,
+    /// Whether the latest user message is checked with the model's moderation before the run
+    moderate_input: bool,
+    /// The start of the answer, sent as an assistant message for the model to continue
+    assistant_prefill: Option<String>,
+    /// The maximum number of model calls per run
+    max_turns: Option<usize>,
+    /// Whether the last turn allowed by `max_turns` is offered no tools, so the model answers
+    force_final_on_limit: bool,
+    /// Receives a copy of every message appended during a run
+    message_sink: Option<tokio::sync::mpsc::Sender<Message>>,
+    /// Context values present in every run unless the caller sets them
+    default_context: Context,
+    /// Sampling overrides passed to the model on every call
+    model_params: ModelParams,
+    /// Latency of every tool call made by this agent and its clones
+    tool_metrics: Arc<Mutex<ToolMetrics>>,
 }
 
 impl Agent {
@@ -28,28 +128,685 @@ impl Agent {
         Self {
             name: name.into(),
             instructions,
+            dynamic_instructions: None,
+            instructions_template: None,
+            developer_instructions: false,
+            examples: Vec::new(),
             model,
             tools,
+            max_tool_output_chars: None,
+            output_formatter: Arc::new(IdentityFormatter),
+            tool_error_retries: 0,
+            lenient_tool_args: false,
+            approval_handler: None,
+            moderate_input: false,
+            assistant_prefill: None,
+            max_turns: None,
+            force_final_on_limit: false,
+            message_sink: None,
+            default_context: Context::new(),
+            model_params: ModelParams::default(),
+            tool_metrics: Arc::default(),
         }
     }
 
     /// Run the agent with the given input
     pub async fn run(&self, input: impl Into<String>, context: Context) -> AgentResult<String> {
         let mut run_context = RunContext::new(context);
+        run_context.add_message("user", input);
+        self.execute(&mut run_context, 0, None, false, true, &self.model_params)
+            .await
+    }
 
-        // Add system instructions if provided
-        if let Some(instructions) = &self.instructions {
-            run_context.add_message("system", instructions);
+    /// Run the agent and parse its answer into `T`, asking the model for JSON matching `T`'s schema
+    ///
+    /// An answer that doesn't parse is sent back to the model with the error, up to twice,
+    /// before the run fails with an `InvalidInput` error. Only the input is moderated, not
+    /// the requests for a corrected answer.
+    #[cfg(feature = "schemars")]
+    pub async fn run_typed<T: schemars::JsonSchema + DeserializeOwned>(
+        &self,
+        input: impl Into<String>,
+        context: Context,
+    ) -> AgentResult<T> {
+        let params = ModelParams {
+            response_format: Some(typed_response_format::<T>()),
+            ..self.model_params.clone()
+        };
+        let mut run_context = RunContext::new(context);
+        run_context.add_message("user", input);
+        let mut history = 0;
+        let mut retries_left = TYPED_ANSWER_RETRIES;
+        loop {
+            let moderate = retries_left == TYPED_ANSWER_RETRIES;
+            let answer = self
+                .execute(&mut run_context, history, None, false, moderate, &params)
+                .await?;
+            match serde_json::from_str(&answer) {
+                Ok(value) => return Ok(value),
+                Err(e) if retries_left > 0 => {
+                    tracing::debug!(error = %e, "typed answer didn't parse, asking again");
+                    retries_left -= 1;
+                    history = run_context.messages.len();
+                    run_context.add_message(
+                        "user",
+                        format!(
+                            "Your last response could not be parsed: {}. Reply with only the corrected JSON.",
+                            e
+                        ),
+                    );
+                }
+                Err(e) => {
+                    return Err(AgentError::InvalidInput(format!(
+                        "Answer doesn't match the expected type: {}",
+                        e
+                    )));
+                }
+            }
         }
+    }
 
-        // Add user input
+    /// Run the agent with the given input, giving up once `deadline` passes
+    ///
+    /// A run still going at the deadline is dropped and fails with a `ModelError`.
+    pub async fn run_with_deadline(
+        &self,
+        input: impl Into<String>,
+        context: Context,
+        deadline: Instant,
+    ) -> AgentResult<String> {
+        tokio::time::timeout_at(deadline, self.run(input, context))
+            .await
+            .unwrap_or_else(|_| Err(AgentError::ModelError("run deadline exceeded".into())))
+    }
+
+    /// Run the agent with the given input, returning the run context along with the outcome
+    ///
+    /// Unlike [`Agent::run`], a failure keeps the messages from the turns that completed.
+    pub async fn run_resumable(
+        &self,
+        input: impl Into<String>,
+        context: Context,
+    ) -> Result<(String, RunContext), RunFailure> {
+        let mut run_context = RunContext::new(context);
+        run_context.add_message("user", input);
+        match self
+            .execute(&mut run_context, 0, None, false, true, &self.model_params)
+            .await
+        {
+            Ok(response) => Ok((response, run_context)),
+            Err(error) => Err(RunFailure {
+                error,
+                context: run_context,
+            }),
+        }
+    }
+
+    /// Continue the conversation stored under `session_id`, saving it again afterwards
+    ///
+    /// A session the store doesn't know yet starts with an empty context. The session is only
+    /// saved when the run succeeds.
+    pub async fn run_session(
+        &self,
+        session_id: &str,
+        input: impl Into<String>,
+        store: &dyn SessionStore,
+    ) -> AgentResult<String> {
+        let mut run_context = match store.load(session_id).await {
+            Some(run_context) => run_context,
+            None => RunContext::new(Context::new()),
+        };
+        let history = run_context.messages.len();
         run_context.add_message("user", input);
 
+        let response = self
+            .execute(
+                &mut run_context,
+                history,
+                None,
+                false,
+                true,
+                &self.model_params,
+            )
+            .await?;
+        store.save(session_id, run_context).await;
+        Ok(response)
+    }
+
+    /// Run the agent on many independent inputs, at most `concurrency` at a time
+    ///
+    /// The results are in the same order as the inputs.
+    pub async fn run_batch(
+        &self,
+        inputs: Vec<(String, Context)>,
+        concurrency: usize,
+    ) -> Vec<AgentResult<String>> {
+        let mut results: Vec<(usize, AgentResult<String>)> = futures::stream::iter(
+            inputs.into_iter().enumerate(),
+        )
+        .map(|(index, (input, context))| async move { (index, self.run(input, context).await) })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Run the agent on a conversation history, e.g. to resume an earlier chat
+    pub async fn run_with_messages(
+        &self,
+        messages: Vec<Message>,
+        context: Context,
+    ) -> AgentResult<String> {
+        let mut run_context = RunContext::new(context);
+        run_context.messages = messages;
+        self.run_with_context(&mut run_context).await
+    }
+
+    /// Run the agent on an existing run context
+    ///
+    /// The system instructions are added if the history doesn't start with a system message,
+    /// and the model's final response is appended so the context can be continued later.
+    /// Messages already in the context are treated as history and not sent to the message sink.
+    pub async fn run_with_context(&self, run_context: &mut RunContext) -> AgentResult<String> {
+        let history = run_context.messages.len();
+        self.execute(run_context, history, None, false, true, &self.model_params)
+            .await
+    }
+
+    /// Run the agent with the given input, streaming progress events as they happen
+    ///
+    /// The stream always ends with either [`RunEvent::Completed`] or [`RunEvent::Failed`].
+    pub fn run_events(
+        &self,
+        input: impl Into<String>,
+        context: Context,
+    ) -> impl Stream<Item = RunEvent> + '_ {
+        self.events(input, context, false)
+    }
+
+    /// Run the agent with the given input, streaming the text of its answer as it's generated
+    ///
+    /// Turns in which the model calls tools run to completion, tools included, before the
+    /// next turn starts; only the model's text is streamed. A failed run ends the stream with
+    /// its error.
+    pub fn run_stream(
+        &self,
+        input: impl Into<String>,
+        context: Context,
+    ) -> impl Stream<Item = AgentResult<String>> + '_ {
+        self.events(input, context, true).filter_map(|event| {
+            future::ready(match event {
+                RunEvent::MessageDelta { content } => Some(Ok(content)),
+                RunEvent::Failed { error } => Some(Err(error)),
+                _ => None,
+            })
+        })
+    }
+
+    /// Run the agent, sending progress events to the returned stream
+    ///
+    /// With `stream_message`, the model's text is sent in deltas as the model streams it.
+    fn events(
+        &self,
+        input: impl Into<String>,
+        context: Context,
+        stream_message: bool,
+    ) -> impl Stream<Item = RunEvent> + '_ {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut run_context = RunContext::new(context);
+        run_context.add_message("user", input);
+
+        let run = async move {
+            let result = self
+                .execute(
+                    &mut run_context,
+                    0,
+                    Some(&sender),
+                    stream_message,
+                    true,
+                    &self.model_params,
+                )
+                .await;
+            let event = match result {
+                Ok(text) => RunEvent::Completed { text },
+                Err(error) => RunEvent::Failed { error },
+            };
+            let _ = sender.unbounded_send(event);
+        };
+
+        // Drive the run alongside the receiver so events are yielded as soon as they are sent
+        let run = run.into_stream().filter_map(|_| future::ready(None));
+        futures::stream::select(receiver, run)
+    }
+
+    /// Run the agent loop on a prepared context
+    ///
+    /// The agent's default context is merged under the run's context first. The first `history`
+    /// messages predate this run; everything appended after them is forwarded to the message sink.
+    /// The last user message is moderated if `moderate` is set and the agent moderates input.
+    async fn execute(
+        &self,
+        run_context: &mut RunContext,
+        history: usize,
+        events: Option<&UnboundedSender<RunEvent>>,
+        stream_message: bool,
+        moderate: bool,
+        params: &ModelParams,
+    ) -> AgentResult<String> {
+        self.merge_default_context(run_context);
+
+        let span = tracing::info_span!("agent_run", agent = %self.name);
+        async move {
+            let mut forwarded = history;
+            let result = match self.add_instructions(run_context) {
+                Ok(added) => {
+                    let examples = self.add_examples(run_context);
+                    if history > 0 {
+                        // The instructions and examples went in front of the history
+                        let inserted = if added { 0..examples.end } else { examples };
+                        for message in &run_context.messages[inserted] {
+                            self.forward_message(message).await;
+                            forwarded += 1;
+                        }
+                    }
+                    self.run_loop(
+                        run_context,
+                        &mut forwarded,
+                        events,
+                        stream_message,
+                        moderate,
+                        params,
+                    )
+                    .await
+                }
+                Err(error) => Err(error),
+            };
+            self.forward_messages(run_context, &mut forwarded).await;
+            if let Err(error) = &result {
+                tracing::warn!(%error, "agent run failed");
+            }
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Build the request the model would be sent for `input`, without calling the model
+    ///
+    /// The context is prepared just like for [`Agent::run`], so the request includes the
+    /// instructions, the tool definitions and the agent's sampling overrides. Fails with a
+    /// `ModelError` if the model can't preview its requests.
+    pub fn dry_run(
+        &self,
+        input: impl Into<String>,
+        context: Context,
+    ) -> AgentResult<serde_json::Value> {
+        let mut run_context = RunContext::new(context);
+        run_context.add_message("user", input);
+        self.merge_default_context(&mut run_context);
+        self.add_instructions(&mut run_context)?;
+        self.add_examples(&mut run_context);
+        if let Some(prefill) = &self.assistant_prefill {
+            run_context.add_message("assistant", prefill.clone());
+        }
+
+        let tools: Vec<&dyn Tool> = self.tools.iter().map(|tool| tool.as_ref()).collect();
+        self.model
+            .preview_request(&run_context, &tools, &self.model_params)
+    }
+
+    /// Fill in the agent's default context under the run's own values
+    fn merge_default_context(&self, run_context: &mut RunContext) {
+        for (key, value) in &self.default_context.data {
+            run_context
+                .context
+                .data
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+
+    /// Send the messages appended since the last call to the message sink
+    async fn forward_messages(&self, run_context: &RunContext, forwarded: &mut usize) {
+        for message in &run_context.messages[*forwarded..] {
+            self.forward_message(message).await;
+        }
+        *forwarded = run_context.messages.len();
+    }
+
+    async fn forward_message(&self, message: &Message) {
+        if let Some(message_sink) = &self.message_sink {
+            // A closed receiver only means nobody is listening anymore
+            let _ = message_sink.send(message.clone()).await;
+        }
+    }
+
+    /// Put the instructions in front of the history unless it already has them
+    ///
+    /// They are added as a system message, or a developer message if the agent is set to use
+    /// developer instructions. Returns whether a message was added, or a `ContextError` if the
+    /// instructions template refers to a key missing from the context.
+    fn add_instructions(&self, run_context: &mut RunContext) -> AgentResult<bool> {
+        let instructions = match (&self.dynamic_instructions, &self.instructions_template) {
+            (Some(dynamic_instructions), _) => Some(dynamic_instructions(&run_context.context)),
+            (None, Some(template)) => Some(template.render(&run_context.context)?),
+            (None, None) => self.instructions.clone(),
+        };
+
+        // Hidden messages aren't sent, so they don't count as instructions
+        if let Some(instructions) = instructions
+            && run_context
+                .messages
+                .iter()
+                .find(|message| !message.hidden)
+                .is_none_or(|message| !matches!(message.role.as_str(), "system" | "developer"))
+        {
+            run_context.messages.insert(
+                0,
+                Message {
+                    role: self.instructions_role().into(),
+                    content: instructions,
+                    ..Default::default()
+                },
+            );
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Put the few-shot examples after the instructions unless the history already has them
+    ///
+    /// Returns the positions of the messages added.
+    fn add_examples(&self, run_context: &mut RunContext) -> Range<usize> {
+        let start = run_context
+            .messages
+            .iter()
+            .position(|message| !message.hidden)
+            .filter(|&i| {
+                matches!(
+                    run_context.messages[i].role.as_str(),
+                    "system" | "developer"
+                )
+            })
+            .map_or(0, |i| i + 1);
+        if self.examples.is_empty() || run_context.messages.iter().any(|message| message.example) {
+            return start..start;
+        }
+
+        let examples = self.examples.iter().flat_map(|(user, assistant)| {
+            [Message::user(user), Message::assistant(assistant)].map(|message| Message {
+                example: true,
+                ..message
+            })
+        });
+        run_context.messages.splice(start..start, examples);
+        start..start + 2 * self.examples.len()
+    }
+
+    /// The role instructions and tool follow-ups are sent with
+    fn instructions_role(&self) -> &'static str {
+        if self.developer_instructions {
+            "developer"
+        } else {
+            "system"
+        }
+    }
+
+    /// Generate a turn with the model's streaming, emitting its text as it arrives
+    async fn stream_turn(
+        &self,
+        run_context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+        emit: &impl Fn(RunEvent),
+    ) -> AgentResult<Step> {
+        let mut chunks = self.model.stream_step(run_context, tools, params).await?;
+        let mut content = String::new();
+        // The answer starts with the prefill, so it streams ahead of the model's text
+        let mut prefill = self.assistant_prefill.clone();
+        while let Some(chunk) = chunks.next().await {
+            match chunk? {
+                StepChunk::Content(delta) => {
+                    if let Some(prefill) = prefill.take() {
+                        emit(RunEvent::MessageDelta { content: prefill });
+                    }
+                    content.push_str(&delta);
+                    emit(RunEvent::MessageDelta { content: delta });
+                }
+                StepChunk::Usage(tokens) => run_context.tokens_used += tokens,
+                StepChunk::ToolCalls(tool_calls) => return Ok(Step::ToolCalls(tool_calls)),
+            }
+        }
+        if let Some(prefill) = prefill {
+            emit(RunEvent::MessageDelta { content: prefill });
+        }
+        Ok(Step::Message(content))
+    }
+
+    /// Ask the model for turns until it produces a final message, executing requested tools
+    async fn run_loop(
+        &self,
+        run_context: &mut RunContext,
+        forwarded: &mut usize,
+        events: Option<&UnboundedSender<RunEvent>>,
+        stream_message: bool,
+        moderate: bool,
+        params: &ModelParams,
+    ) -> AgentResult<String> {
+        let emit = |event: RunEvent| {
+            if let Some(events) = events {
+                let _ = events.unbounded_send(event);
+            }
+        };
+
+        // Screen what the user sent, not hidden bookkeeping
+        if moderate
+            && self.moderate_input
+            && let Some(input) = run_context
+                .messages
+                .iter()
+                .rfind(|message| message.role == "user" && !message.hidden)
+        {
+            let moderation = self.model.moderate(&input.content).await?;
+            if moderation.flagged {
+                return Err(AgentError::GuardrailError(format!(
+                    "input flagged by moderation ({})",
+                    moderation.categories.join(", ")
+                )));
+            }
+        }
+
         // Convert tools to slice of references
         let tools: Vec<&dyn Tool> = self.tools.iter().map(|tool| tool.as_ref()).collect();
+        let mut retries_left = self.tool_error_retries;
+        let mut turns = 0;
+        let mut tool_calls_requested = 0;
+
+        loop {
+            self.forward_messages(run_context, forwarded).await;
 
-        // Generate response
-        self.model.generate_response(&mut run_context, &tools).await
+            if self.max_turns.is_some_and(|max_turns| turns >= max_turns) {
+                return Err(AgentError::ModelError("max turns exceeded".into()));
+            }
+            turns += 1;
+            let turn_tools: &[&dyn Tool] =
+                if self.force_final_on_limit && self.max_turns == Some(turns) {
+                    tracing::debug!(turn = turns, "last turn, offering no tools");
+                    &[]
+                } else {
+                    &tools
+                };
+
+            let span = tracing::info_span!("agent_turn", turn = turns);
+            let prefill_at = run_context.messages.len();
+            if let Some(prefill) = &self.assistant_prefill {
+                run_context.add_message("assistant", prefill.clone());
+            }
+            let step = if stream_message {
+                self.stream_turn(run_context, turn_tools, params, &emit)
+                    .instrument(span)
+                    .await
+            } else {
+                self.model
+                    .generate_step_with_params(run_context, turn_tools, params)
+                    .instrument(span)
+                    .await
+            };
+            if self.assistant_prefill.is_some() && prefill_at < run_context.messages.len() {
+                run_context.messages.remove(prefill_at);
+            }
+            let step = step?;
+            if let Step::ToolCalls(tool_calls) = &step {
+                tool_calls_requested += tool_calls.len();
+            }
+            emit(RunEvent::UsageUpdate {
+                tokens: run_context.tokens_used,
+                tool_calls: tool_calls_requested,
+            });
+            match step {
+                Step::Message(content) => {
+                    let content = match &self.assistant_prefill {
+                        Some(prefill) => format!("{}{}", prefill, content),
+                        None => content,
+                    };
+                    if !stream_message {
+                        emit(RunEvent::MessageDelta {
+                            content: content.clone(),
+                        });
+                    }
+                    run_context.add_message("assistant", content.clone());
+                    return Ok(content);
+                }
+                Step::ToolCalls(tool_calls) => {
+                    run_context.add_tool_calls(tool_calls.clone());
+                    // Held back until every call is answered, as tool messages must come first
+                    let mut tool_content = Vec::new();
+                    let mut follow_ups = Vec::new();
+                    for mut tool_call in tool_calls {
+                        let tool = match find_tool(&tools, &tool_call.name) {
+                            Ok(tool) => tool,
+                            // Let the model see that it has no such tool and pick another
+                            Err(error) if retries_left > 0 => {
+                                tracing::debug!(turn = turns, tool = %tool_call.name, "unknown tool called");
+                                retries_left -= 1;
+                                run_context.add_tool_result(ToolResult {
+                                    tool_name: tool_call.name.clone(),
+                                    output: error.to_string(),
+                                    call_id: Some(tool_call.id.clone()),
+                                    success: false,
+                                    ..Default::default()
+                                });
+                                continue;
+                            }
+                            Err(error) => return Err(error),
+                        };
+                        if self.lenient_tool_args
+                            && let Some(arguments) = relax_json(&tool_call.arguments)
+                        {
+                            tool_call.arguments = arguments;
+                        }
+                        if tool.requires_approval()
+                            && !self
+                                .approval_handler
+                                .as_ref()
+                                .is_some_and(|approve| approve(&tool_call))
+                        {
+                            tracing::debug!(turn = turns, tool = %tool_call.name, "tool call denied");
+                            run_context.add_tool_result(ToolResult {
+                                tool_name: tool_call.name.clone(),
+                                output: TOOL_CALL_DENIED.to_string(),
+                                call_id: Some(tool_call.id.clone()),
+                                success: false,
+                                ..Default::default()
+                            });
+                            continue;
+                        }
+                        // Field values are only computed when a subscriber wants the event
+                        tracing::debug!(
+                            turn = turns,
+                            tool = %tool_call.name,
+                            arguments = truncate(&tool_call.arguments, MAX_TRACED_ARGUMENTS),
+                            "tool started"
+                        );
+                        emit(RunEvent::ToolStarted {
+                            name: tool_call.name.clone(),
+                            args: tool_call.arguments.clone(),
+                        });
+
+                        let started = std::time::Instant::now();
+                        let result = execute_tool_call(tool, run_context, &tool_call).await;
+                        self.tool_metrics
+                            .lock()
+                            .unwrap()
+                            .record(&tool_call.name, started.elapsed());
+                        let mut result = match result {
+                            Ok(result) => result,
+                            // Let the model see what was wrong with its arguments and try again
+                            Err(error) if retries_left > 0 && is_argument_error(&error) => {
+                                tracing::debug!(turn = turns, tool = %tool_call.name, %error, "tool arguments rejected");
+                                retries_left -= 1;
+                                run_context.add_tool_result(ToolResult {
+                                    tool_name: tool_call.name.clone(),
+                                    output: error.to_string(),
+                                    call_id: Some(tool_call.id.clone()),
+                                    success: false,
+                                    ..Default::default()
+                                });
+                                continue;
+                            }
+                            Err(error) => return Err(error),
+                        };
+                        if let Some(max_chars) = self.max_tool_output_chars {
+                            result.truncate_output(max_chars);
+                        }
+                        result.output = self.output_formatter.format(&result);
+                        tracing::debug!(
+                            turn = turns,
+                            tool = %result.tool_name,
+                            output_len = result.output.len(),
+                            "tool completed"
+                        );
+                        emit(RunEvent::ToolCompleted {
+                            name: result.tool_name.clone(),
+                            output: result.output.clone(),
+                        });
+                        if !result.parts.is_empty() {
+                            tool_content.push(Message::tool_content(
+                                &result.tool_name,
+                                std::mem::take(&mut result.parts),
+                            ));
+                        }
+                        follow_ups.extend(result.follow_up.clone());
+                        run_context.add_tool_result(result);
+                    }
+                    run_context.messages.extend(tool_content);
+                    for follow_up in follow_ups {
+                        run_context.add_message(self.instructions_role(), follow_up);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Create a variant of this agent that uses a different model, e.g. for A/B testing
+    ///
+    /// Everything else, including the tools, is shared with this agent.
+    pub fn with_model(&self, model: Arc<dyn Model>) -> Agent {
+        Agent {
+            model,
+            ..self.clone()
+        }
+    }
+
+    /// Release the agent's sinks so their receivers see the end of the stream
+    ///
+    /// Messages already sent stay buffered in the channel. A sink shared with clones of this
+    /// agent only closes once every clone has shut down or been dropped. Later runs still work,
+    /// they just aren't forwarded anywhere.
+    pub async fn shutdown(&mut self) {
+        self.message_sink = None;
     }
 
     /// Get the name of the agent
@@ -66,14 +823,310 @@ impl Agent {
     pub fn tools(&self) -> &[Arc<dyn Tool>] {
         &self.tools
     }
+
+    /// Get a snapshot of how long each tool's calls have taken
+    ///
+    /// Clones of the agent, including those made by [`Agent::with_model`], share these metrics.
+    pub fn tool_metrics(&self) -> ToolMetrics {
+        self.tool_metrics.lock().unwrap().clone()
+    }
+
+    /// Offer the agent to other agents as a tool that runs it on the `input` argument
+    ///
+    /// The tool is named after the agent. See [`AgentTool`] for how each call is kept apart
+    /// from the caller's run. Returns a `ConfigurationError` if the agent's name isn't a valid
+    /// tool name; use [`Agent::as_named_tool`] to pick one that is.
+    pub fn as_tool(self, description: impl Into<String>) -> AgentResult<AgentTool> {
+        let name = self.name.clone();
+        self.as_named_tool(name, description)
+    }
+
+    /// Offer the agent to other agents as a tool called `name`
+    ///
+    /// Tool names may only contain letters, digits, underscores and dashes, at most 64 of
+    /// them; any other name returns a `ConfigurationError`.
+    pub fn as_named_tool(
+        self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> AgentResult<AgentTool> {
+        let name = name.into();
+        if !is_valid_tool_name(&name) {
+            return Err(AgentError::ConfigurationError(format!(
+                "Invalid tool name: {:?}",
+                name
+            )));
+        }
+        Ok(AgentTool {
+            name,
+            agent: self,
+            description: description.into(),
+        })
+    }
+
+    /// Look up one of the agent's tools by name
+    pub fn tool_by_name(&self, name: &str) -> Option<&Arc<dyn Tool>> {
+        self.tools.iter().find(|tool| tool.name() == name)
+    }
+
+    /// Describe the agent's declarative settings, for saving and rebuilding it
+    ///
+    /// The model and tools are recorded by name. Dynamic instructions, instruction templates,
+    /// handlers and sinks aren't data and are left out.
+    pub fn to_config(&self) -> AgentConfig {
+        AgentConfig {
+            name: self.name.clone(),
+            instructions: self.instructions.clone(),
+            developer_instructions: self.developer_instructions,
+            examples: self.examples.clone(),
+            model: self.model.model_name().map(str::to_string),
+            tools: self
+                .tools
+                .iter()
+                .map(|tool| tool.name().to_string())
+                .collect(),
+            temperature: self.model_params.temperature,
+            max_turns: self.max_turns,
+            force_final_on_limit: self.force_final_on_limit,
+            max_tool_output_chars: self.max_tool_output_chars,
+            tool_error_retries: self.tool_error_retries,
+            lenient_tool_args: self.lenient_tool_args,
+            moderate_input: self.moderate_input,
+            assistant_prefill: self.assistant_prefill.clone(),
+        }
+    }
+
+    /// Describe the agent's tools as a JSON array of `{name, description, parameters}` objects
+    pub fn tools_manifest(&self) -> serde_json::Value {
+        self.tools
+            .iter()
+            .map(|tool| {
+                serde_json::json!({
+                    "name": tool.name(),
+                    "description": tool.description(),
+                    "parameters": tool.parameters_schema(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// How much of a tool call's arguments is recorded in tracing events
+const MAX_TRACED_ARGUMENTS: usize = 256;
+
+/// Whether the API accepts `name` for a tool: letters, digits, underscores and dashes, at most
+/// 64 of them
+fn is_valid_tool_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+/// A JSON schema response format for `T`, named after the type
+#[cfg(feature = "schemars")]
+fn typed_response_format<T: schemars::JsonSchema>() -> ResponseFormat {
+    // The API only accepts letters, digits, underscores and dashes, at most 64 of them
+    let name: String = T::schema_name()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+        .take(64)
+        .collect();
+    ResponseFormat::JsonSchema {
+        json_schema: ResponseFormatJsonSchema {
+            description: None,
+            name,
+            schema: Some(crate::tool::schema_for::<T>()),
+            strict: None,
+        },
+    }
+}
+
+/// Cut `text` to at most `max` bytes without splitting a character
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Whether a tool failed because the model sent bad arguments
+fn is_argument_error(error: &AgentError) -> bool {
+    matches!(
+        error,
+        AgentError::InvalidInput(_)
+            | AgentError::ValidationError { .. }
+            | AgentError::SerializationError(_)
+    )
+}
+
+impl std::fmt::Debug for Agent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tools: Vec<&str> = self.tools.iter().map(|tool| tool.name()).collect();
+        f.debug_struct("Agent")
+            .field("name", &self.name)
+            .field("instructions", &self.instructions)
+            .field("model", &self.model.model_name())
+            .field("tools", &tools)
+            .field("max_turns", &self.max_turns)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An agent used as a tool by another agent, created with [`Agent::as_tool`]
+///
+/// Each call runs the agent on a run context of its own: it starts from a copy of the
+/// caller's context data and an empty message list. Only the final answer flows back, as
+/// the tool's output; the sub-agent's messages and any data it changes stay in its own run.
+pub struct AgentTool {
+    name: String,
+    agent: Agent,
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct AgentToolParams {
+    input: String,
+}
+
+impl std::fmt::Debug for AgentTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentTool")
+            .field("name", &self.name)
+            .field("agent", &self.agent)
+            .field("description", &self.description)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Tool for AgentTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "input": {"type": "string", "description": "The task for the agent"}
+            },
+            "required": ["input"]
+        })
+    }
+
+    async fn execute(&self, context: &mut RunContext, params: &str) -> AgentResult<ToolResult> {
+        let params: AgentToolParams = parse_params(params)?;
+        // Only the data is shared, and as a copy, so the sub-run can't touch the caller's
+        let output = self
+            .agent
+            .run(params.input, context.context.clone())
+            .await?;
+        Ok(ToolResult {
+            tool_name: self.name.clone(),
+            output,
+            ..Default::default()
+        })
+    }
+}
+
+/// Check the shape the API requires of a tool's parameters schema, so a bad schema fails at
+/// build time rather than on the first request
+fn schema_problem(schema: &serde_json::Value) -> Option<String> {
+    let Some(schema) = schema.as_object() else {
+        return Some("expected a JSON object".into());
+    };
+    if schema.get("type").and_then(|t| t.as_str()) != Some("object") {
+        return Some(r#"expected "type": "object""#.into());
+    }
+    if let Some(properties) = schema.get("properties")
+        && !properties.is_object()
+    {
+        return Some(r#""properties" must be an object"#.into());
+    }
+    if let Some(required) = schema.get("required")
+        && !required
+            .as_array()
+            .is_some_and(|required| required.iter().all(|name| name.is_string()))
+    {
+        return Some(r#""required" must be an array of strings"#.into());
+    }
+    None
+}
+
+/// An agent's declarative settings, with its model and tools referred to by name
+///
+/// Serializes to JSON for storage; missing fields take their defaults when deserializing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    /// The name of the agent
+    pub name: String,
+    /// The static instructions for the agent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// Whether the instructions are sent with the `developer` role instead of `system`
+    pub developer_instructions: bool,
+    /// Few-shot user/assistant message pairs
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<(String, String)>,
+    /// The name of the model, as reported by `Model::model_name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The names of the agent's tools, in order
+    pub tools: Vec<String>,
+    /// Sampling temperature overriding the model's own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// The maximum number of model calls per run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<usize>,
+    /// Whether the last turn allowed by `max_turns` is offered no tools
+    pub force_final_on_limit: bool,
+    /// The most characters of any tool's output passed on to the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tool_output_chars: Option<usize>,
+    /// How many invalid tool calls are reported back to the model per run
+    pub tool_error_retries: usize,
+    /// Whether almost-JSON tool arguments are repaired before parsing
+    pub lenient_tool_args: bool,
+    /// Whether the latest user message is moderated before the run
+    pub moderate_input: bool,
+    /// The start of every answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assistant_prefill: Option<String>,
 }
 
 /// Builder for creating agents
 pub struct AgentBuilder {
     name: String,
     instructions: Option<String>,
+    dynamic_instructions: Option<DynamicInstructionsFn>,
+    instructions_template: Option<PromptTemplate>,
+    developer_instructions: bool,
+    examples: Vec<(String, String)>,
     model: Option<Arc<dyn Model>>,
     tools: Vec<Arc<dyn Tool>>,
+    max_tool_output_chars: Option<usize>,
+    output_formatter: Option<Arc<dyn OutputFormatter>>,
+    tool_error_retries: usize,
+    lenient_tool_args: bool,
+    approval_handler: Option<ApprovalHandler>,
+    moderate_input: bool,
+    assistant_prefill: Option<String>,
+    max_turns: Option<usize>,
+    force_final_on_limit: bool,
+    message_sink: Option<tokio::sync::mpsc::Sender<Message>>,
+    default_context: Context,
+    model_params: ModelParams,
 }
 
 impl AgentBuilder {
@@ -82,9 +1135,62 @@ impl AgentBuilder {
         Self {
             name: name.into(),
             instructions: None,
+            dynamic_instructions: None,
+            instructions_template: None,
+            developer_instructions: false,
+            examples: Vec::new(),
             model: None,
             tools: Vec::new(),
+            max_tool_output_chars: None,
+            output_formatter: None,
+            tool_error_retries: 0,
+            lenient_tool_args: false,
+            approval_handler: None,
+            moderate_input: false,
+            assistant_prefill: None,
+            max_turns: None,
+            force_final_on_limit: false,
+            message_sink: None,
+            default_context: Context::new(),
+            model_params: ModelParams::default(),
+        }
+    }
+
+    /// Start a builder from a saved config, looking up its model and tools by name
+    ///
+    /// `models` maps model names to models. Returns a `ConfigurationError` if a named model or
+    /// tool isn't registered. Without a model name in the config, set one on the builder.
+    pub fn from_config(
+        config: AgentConfig,
+        models: &HashMap<String, Arc<dyn Model>>,
+        tools: &ToolSet,
+    ) -> AgentResult<Self> {
+        let mut builder = Self::new(config.name);
+        if let Some(model) = config.model {
+            let model = models.get(&model).ok_or_else(|| {
+                AgentError::ConfigurationError(format!("Unknown model: {}", model))
+            })?;
+            builder = builder.model(model.clone());
         }
+        for name in &config.tools {
+            let tool = tools
+                .get(name)
+                .ok_or_else(|| AgentError::ConfigurationError(format!("Unknown tool: {}", name)))?;
+            builder = builder.add_tool(tool.clone());
+        }
+
+        builder.instructions = config.instructions;
+        builder.developer_instructions = config.developer_instructions;
+        builder.examples = config.examples;
+        builder.model_params.temperature = config.temperature;
+        builder.max_turns = config.max_turns;
+        builder.force_final_on_limit = config.force_final_on_limit;
+        builder.max_tool_output_chars = config.max_tool_output_chars;
+        builder.tool_error_retries = config.tool_error_retries;
+        builder.lenient_tool_args = config.lenient_tool_args;
+        builder.moderate_input = config.moderate_input;
+        builder.assistant_prefill = config.assistant_prefill;
+        Ok(builder)
     }
 
     /// Set the instructions for the agent
@@ -93,23 +1199,222 @@ impl AgentBuilder {
         self
     }
 
+    /// Append an instruction segment, e.g. to layer a task prompt over a base policy
+    ///
+    /// Segments are joined with blank lines into a single system message, in the order they
+    /// were added. `instructions` replaces everything added so far.
+    pub fn instruction(mut self, segment: impl Into<String>) -> Self {
+        let segment = segment.into();
+        self.instructions = Some(match self.instructions.take() {
+            Some(instructions) => format!("{}\n\n{}", instructions, segment),
+            None => segment,
+        });
+        self
+    }
+
+    /// Compute the instructions from the run's context, overriding any static instructions
+    pub fn dynamic_instructions(mut self, instructions: DynamicInstructionsFn) -> Self {
+        self.dynamic_instructions = Some(instructions);
+        self
+    }
+
+    /// Render the instructions from a template filled in from the run's context
+    ///
+    /// Overrides any static instructions; `dynamic_instructions` takes precedence over it.
+    pub fn instructions_template(mut self, template: PromptTemplate) -> Self {
+        self.instructions_template = Some(template);
+        self
+    }
+
+    /// Send the instructions with the `developer` role, which newer models prefer to `system`
+    pub fn developer_instructions(mut self, developer: bool) -> Self {
+        self.developer_instructions = developer;
+        self
+    }
+
     /// Set the model for the agent
     pub fn model(mut self, model: Arc<dyn Model>) -> Self {
         self.model = Some(model);
         self
     }
 
+    /// Add a few-shot example of a user message and the answer the agent should give
+    ///
+    /// Examples go after the instructions and before the conversation, in the order added,
+    /// and are flagged as examples so they can be told apart from the real messages.
+    pub fn add_example(mut self, user: impl Into<String>, assistant: impl Into<String>) -> Self {
+        self.examples.push((user.into(), assistant.into()));
+        self
+    }
+
     /// Add a tool to the agent
     pub fn add_tool(mut self, tool: Arc<dyn Tool>) -> Self {
         self.tools.push(tool);
         self
     }
 
+    /// Add every tool in a tool set
+    pub fn add_tool_set(mut self, tool_set: ToolSet) -> Self {
+        self.tools.extend(tool_set);
+        self
+    }
+
+    /// Add only the tools `predicate` accepts, e.g. to leave out tools tagged `destructive`
+    pub fn add_tools_where(
+        mut self,
+        tools: impl IntoIterator<Item = Arc<dyn Tool>>,
+        predicate: impl Fn(&dyn Tool) -> bool,
+    ) -> Self {
+        self.tools
+            .extend(tools.into_iter().filter(|tool| predicate(tool.as_ref())));
+        self
+    }
+
+    /// Truncate every tool's output to at most `max_chars` characters before the model sees it
+    ///
+    /// Applies on top of any limit the tool sets itself.
+    pub fn max_tool_output_chars(mut self, max_chars: usize) -> Self {
+        self.max_tool_output_chars = Some(max_chars);
+        self
+    }
+
+    /// Format tool output before it's added to the conversation
+    ///
+    /// The formatter sees the output after any truncation. Without one, output is passed through
+    /// unchanged.
+    pub fn output_formatter(mut self, formatter: Arc<dyn OutputFormatter>) -> Self {
+        self.output_formatter = Some(formatter);
+        self
+    }
+
+    /// Feed up to `retries` invalid tool calls back to the model per run
+    ///
+    /// Calls to unknown tools and calls with invalid arguments are appended as failed tool
+    /// messages so the model can correct them. Without retries, the first one ends the run.
+    pub fn tool_error_retries(mut self, retries: usize) -> Self {
+        self.tool_error_retries = retries;
+        self
+    }
+
+    /// Repair tool arguments that aren't quite JSON before passing them to the tool
+    ///
+    /// Comments, trailing commas and single-quoted strings are fixed. Arguments that are still
+    /// invalid once repaired are passed on unchanged, so the tool reports the error as usual.
+    pub fn lenient_tool_args(mut self, lenient: bool) -> Self {
+        self.lenient_tool_args = lenient;
+        self
+    }
+
+    /// Ask `handler` before running any tool that requires approval
+    ///
+    /// Denied calls aren't executed; the model is told the call was denied instead. Without a
+    /// handler, every call to such a tool is denied.
+    pub fn approval_handler(mut self, handler: ApprovalHandler) -> Self {
+        self.approval_handler = Some(handler);
+        self
+    }
+
+    /// Screen the latest user message with the model's moderation check before each run
+    ///
+    /// A flagged message fails the run with a `GuardrailError` before the model sees it.
+    pub fn moderate_input(mut self, moderate: bool) -> Self {
+        self.moderate_input = moderate;
+        self
+    }
+
+    /// Start every answer with `prefill`, steering the model to continue from it
+    ///
+    /// The prefill is sent as a final assistant message on each model call and is removed from
+    /// the history afterwards; the answer is the prefill followed by the model's completion.
+    pub fn assistant_prefill(mut self, prefill: impl Into<String>) -> Self {
+        self.assistant_prefill = Some(prefill.into());
+        self
+    }
+
+    /// Cap the number of model calls in a single run
+    ///
+    /// A run that needs more turns fails with a `ModelError`, whatever the reason for the extra
+    /// turns, which bounds the cost of models that keep calling tools.
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = Some(max_turns);
+        self
+    }
+
+    /// Offer the model no tools on the last turn `max_turns` allows, nudging it to answer
+    ///
+    /// Without this, a model that keeps calling tools fails the run once the turns run out.
+    pub fn force_final_on_limit(mut self, force_final: bool) -> Self {
+        self.force_final_on_limit = force_final;
+        self
+    }
+
+    /// Send a copy of every message appended during a run to `sender`, e.g. to persist it
+    ///
+    /// Messages are sent as soon as the loop gets to them, including tool messages. The run waits
+    /// for room in the channel, so drain it concurrently or give it enough capacity for a run.
+    pub fn message_sink(mut self, sender: tokio::sync::mpsc::Sender<Message>) -> Self {
+        self.message_sink = Some(sender);
+        self
+    }
+
+    /// Set context values every run starts with, e.g. endpoints or a tenant id
+    ///
+    /// The context passed to a run is merged over these defaults, so the caller wins on conflicts.
+    pub fn default_context(mut self, context: Context) -> Self {
+        self.default_context = context;
+        self
+    }
+
+    /// Sample with this temperature regardless of the model's own setting
+    ///
+    /// Lets agents sharing one model instance sample differently.
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.model_params.temperature = Some(temperature);
+        self
+    }
+
     /// Build the agent
     pub fn build(self) -> AgentResult<Agent> {
         let model = self
             .model
             .ok_or_else(|| AgentError::ConfigurationError("Model not set".into()))?;
-        Ok(Agent::new(self.name, self.instructions, model, self.tools))
+
+        // Tools are looked up by name, so a duplicate would shadow the later tool
+        let mut names = HashSet::new();
+        for tool in &self.tools {
+            if !names.insert(tool.name()) {
+                return Err(AgentError::ConfigurationError(format!(
+                    "Duplicate tool name: {}",
+                    tool.name()
+                )));
+            }
+            if let Some(problem) = schema_problem(&tool.parameters_schema()) {
+                return Err(AgentError::ConfigurationError(format!(
+                    "Invalid parameters schema for tool {}: {}",
+                    tool.name(),
+                    problem
+                )));
+            }
+        }
+        let mut agent = Agent::new(self.name, self.instructions, model, self.tools);
+        agent.dynamic_instructions = self.dynamic_instructions.map(Arc::from);
+        agent.instructions_template = self.instructions_template;
+        agent.developer_instructions = self.developer_instructions;
+        agent.examples = self.examples;
+        agent.max_tool_output_chars = self.max_tool_output_chars;
+        if let Some(output_formatter) = self.output_formatter {
+            agent.output_formatter = output_formatter;
+        }
+        agent.tool_error_retries = self.tool_error_retries;
+        agent.lenient_tool_args = self.lenient_tool_args;
+        agent.approval_handler = self.approval_handler;
+        agent.moderate_input = self.moderate_input;
+        agent.assistant_prefill = self.assistant_prefill;
+        agent.max_turns = self.max_turns;
+        agent.force_final_on_limit = self.force_final_on_limit;
+        agent.message_sink = self.message_sink;
+        agent.default_context = self.default_context;
+        agent.model_params = self.model_params;
+        Ok(agent)
     }
 }
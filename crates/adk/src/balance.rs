@@ -0,0 +1,177 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_openai::error::OpenAIError;
+use async_trait::async_trait;
+
+use crate::error::{AgentError, AgentResult};
+use crate::openai::{Model, ModelParams, ModerationResult, Step, StepStream};
+use crate::tool::Tool;
+use crate::types::RunContext;
+
+/// A model that spreads calls over several models in turn, e.g. one per API key
+///
+/// Each call goes to the next model in round-robin order. With a cooldown set, a model whose
+/// call fails because its key is invalid or out of quota is skipped until the cooldown ends;
+/// the failed call still returns its error.
+pub struct LoadBalancedModel {
+    models: Vec<Arc<dyn Model>>,
+    next: AtomicUsize,
+    cooldown: Option<Duration>,
+    /// When each model may be used again after failing with a key error
+    benched_until: Mutex<Vec<Option<Instant>>>,
+}
+
+impl LoadBalancedModel {
+    /// Balance over `models`; an empty list returns a `ConfigurationError`
+    pub fn new(models: Vec<Arc<dyn Model>>) -> AgentResult<Self> {
+        if models.is_empty() {
+            return Err(AgentError::ConfigurationError(
+                "Load balancing needs at least one model".into(),
+            ));
+        }
+        Ok(Self {
+            benched_until: Mutex::new(vec![None; models.len()]),
+            models,
+            next: AtomicUsize::new(0),
+            cooldown: None,
+        })
+    }
+
+    /// Skip a model for `cooldown` after its key is rejected or runs out of quota
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// Pick the next model in turn that isn't cooling down, or the next one if all are
+    fn pick(&self) -> (usize, &dyn Model) {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let benched_until = self.benched_until.lock().unwrap();
+        let now = Instant::now();
+        let index = (0..self.models.len())
+            .map(|offset| (start + offset) % self.models.len())
+            .find(|&index| benched_until[index].is_none_or(|until| until <= now))
+            .unwrap_or(start % self.models.len());
+        if index != start % self.models.len() {
+            // Carry on from the model used, so the others keep their turns
+            self.next.store(index + 1, Ordering::Relaxed);
+        }
+        (index, self.models[index].as_ref())
+    }
+
+    /// Bench the model if the call failed because of its key
+    fn observe<T>(&self, index: usize, result: &AgentResult<T>) {
+        if let (Some(cooldown), Err(error)) = (self.cooldown, result)
+            && is_key_error(error)
+        {
+            tracing::warn!(model = index, %error, "key rejected, skipping model for a while");
+            self.benched_until.lock().unwrap()[index] = Some(Instant::now() + cooldown);
+        }
+    }
+}
+
+/// Whether a call failed because its API key is invalid or out of quota
+fn is_key_error(error: &AgentError) -> bool {
+    let openai_error = match error {
+        AgentError::OpenAIError(error) => Some(error),
+        AgentError::ModelErrorWithSource { source, .. } => source.downcast_ref::<OpenAIError>(),
+        _ => None,
+    };
+    match openai_error {
+        Some(OpenAIError::ApiError(error)) => [&error.r#type, &error.code]
+            .into_iter()
+            .flatten()
+            .any(|value| matches!(value.as_str(), "invalid_api_key" | "insufficient_quota")),
+        _ => false,
+    }
+}
+
+impl std::fmt::Debug for LoadBalancedModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoadBalancedModel")
+            .field("models", &self.models.len())
+            .field("cooldown", &self.cooldown)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Model for LoadBalancedModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> AgentResult<String> {
+        let (index, model) = self.pick();
+        let response = model.generate_response(context, tools).await;
+        self.observe(index, &response);
+        response
+    }
+
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> AgentResult<Step> {
+        let (index, model) = self.pick();
+        let step = model.generate_step(context, tools).await;
+        self.observe(index, &step);
+        step
+    }
+
+    async fn generate_step_with_params(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<Step> {
+        let (index, model) = self.pick();
+        let step = model
+            .generate_step_with_params(context, tools, params)
+            .await;
+        self.observe(index, &step);
+        step
+    }
+
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<StepStream> {
+        let (index, model) = self.pick();
+        let stream = model.stream_step(context, tools, params).await;
+        self.observe(index, &stream);
+        stream
+    }
+
+    /// Previews with the first model, as every model is expected to build the same request
+    fn preview_request(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<serde_json::Value> {
+        self.models[0].preview_request(context, tools, params)
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.models[0].model_name()
+    }
+
+    async fn list_models(&self) -> AgentResult<Vec<String>> {
+        let (index, model) = self.pick();
+        let models = model.list_models().await;
+        self.observe(index, &models);
+        models
+    }
+
+    async fn moderate(&self, text: &str) -> AgentResult<ModerationResult> {
+        let (index, model) = self.pick();
+        let moderation = model.moderate(text).await;
+        self.observe(index, &moderation);
+        moderation
+    }
+}
@@ -1,30 +1,164 @@
+use async_openai::error::OpenAIError;
 use thiserror::Error;
 
+/// An underlying error kept as the source of an [`AgentError`]
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
 #[derive(Error, Debug)]
 pub enum AgentError {
     #[error("Model error: {0}")]
     ModelError(String),
 
+    /// A model error caused by another error, which `source` returns
+    #[error("Model error: {message}")]
+    ModelErrorWithSource {
+        message: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Model refused: {0}")]
+    Refusal(String),
+
     #[error("Tool execution error: {0}")]
     ToolError(String),
 
+    /// A tool error caused by another error, which `source` returns
+    #[error("Tool execution error: {message}")]
+    ToolErrorWithSource {
+        message: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Tool not found: {0}")]
+    ToolNotFound(String),
+
     #[error("Invalid input: {0}")]
     InvalidInput(String),
 
+    #[error("Validation error: field `{field}` {message}")]
+    ValidationError { field: String, message: String },
+
     #[error("Context error: {0}")]
     ContextError(String),
 
     #[error("Configuration error: {0}")]
     ConfigurationError(String),
 
+    #[error("Guardrail triggered: {0}")]
+    GuardrailError(String),
+
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
     #[error("OpenAI API error: {0}")]
-    OpenAIError(#[from] async_openai::error::OpenAIError),
+    OpenAIError(#[from] OpenAIError),
 
     #[error("Internal error: {0}")]
     InternalError(String),
 }
 
+/// Stable classification of an [`AgentError`], independent of its message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Model,
+    Refusal,
+    Tool,
+    ToolNotFound,
+    InvalidInput,
+    Context,
+    Configuration,
+    Guardrail,
+    Serialization,
+    Api,
+    Internal,
+}
+
+impl AgentError {
+    /// Create a model error that keeps `source` as its cause
+    pub fn model_error(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        AgentError::ModelErrorWithSource {
+            message: message.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Create a tool error that keeps `source` as its cause
+    pub fn tool_error(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        AgentError::ToolErrorWithSource {
+            message: message.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Get the kind of error, e.g. to map it to a status code
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            AgentError::ModelError(_) | AgentError::ModelErrorWithSource { .. } => ErrorKind::Model,
+            AgentError::Refusal(_) => ErrorKind::Refusal,
+            AgentError::ToolError(_) | AgentError::ToolErrorWithSource { .. } => ErrorKind::Tool,
+            AgentError::ToolNotFound(_) => ErrorKind::ToolNotFound,
+            AgentError::InvalidInput(_) | AgentError::ValidationError { .. } => {
+                ErrorKind::InvalidInput
+            }
+            AgentError::ContextError(_) => ErrorKind::Context,
+            AgentError::ConfigurationError(_) => ErrorKind::Configuration,
+            AgentError::GuardrailError(_) => ErrorKind::Guardrail,
+            AgentError::SerializationError(_) => ErrorKind::Serialization,
+            AgentError::OpenAIError(_) => ErrorKind::Api,
+            AgentError::InternalError(_) => ErrorKind::Internal,
+        }
+    }
+
+    /// Whether the same request may succeed if tried again later
+    ///
+    /// Network errors, rate limits and server-side API errors are retryable, including when
+    /// they are the source of a model error. Errors caused by the input, the configuration or
+    /// a rejected API key are not, and neither are model errors without a source.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AgentError::ModelErrorWithSource { source, .. } => {
+                if let Some(error) = source.downcast_ref::<OpenAIError>() {
+                    is_transient(error)
+                } else if let Some(error) = source.downcast_ref::<std::io::Error>() {
+                    matches!(
+                        error.kind(),
+                        std::io::ErrorKind::ConnectionReset
+                            | std::io::ErrorKind::ConnectionAborted
+                            | std::io::ErrorKind::TimedOut
+                            | std::io::ErrorKind::Interrupted
+                    )
+                } else {
+                    false
+                }
+            }
+            AgentError::OpenAIError(error) => is_transient(error),
+            _ => false,
+        }
+    }
+}
+
+/// Whether an OpenAI client error is likely to go away on retry
+///
+/// Server errors come back without a type or code, as the client doesn't parse their body.
+pub(crate) fn is_transient(error: &OpenAIError) -> bool {
+    match error {
+        OpenAIError::Reqwest(_) | OpenAIError::StreamError(_) => true,
+        OpenAIError::ApiError(error) if error.r#type.is_none() && error.code.is_none() => true,
+        OpenAIError::ApiError(error) => {
+            let is_transient = |value: &Option<String>| {
+                value.as_deref().is_some_and(|value| {
+                    matches!(
+                        value,
+                        "rate_limit_exceeded" | "server_error" | "service_unavailable"
+                    )
+                })
+            };
+            is_transient(&error.r#type) || is_transient(&error.code)
+        }
+        _ => false,
+    }
+}
+
 pub type AgentResult<T> = Result<T, AgentError>;
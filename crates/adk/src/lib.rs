@@ -2,8 +2,16 @@
 pub use adk_macros::*;
 
 pub mod agent;
+pub mod balance;
 pub mod error;
+pub mod logging;
 pub mod openai;
+#[cfg(feature = "otel")]
+mod otel;
+pub mod prompt;
+pub mod replay;
+pub mod retry;
+pub mod session;
 pub mod tool;
 pub mod types;
 
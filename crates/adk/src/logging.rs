@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+
+use crate::error::{AgentError, AgentResult};
+use crate::openai::{Model, ModelParams, ModerationResult, Step, StepChunk, StepStream};
+use crate::tool::Tool;
+use crate::types::{Message, RunContext};
+
+/// Receives the messages sent to a model and what the model returned
+pub type ModelLogFn = Box<dyn Fn(&[Message], Result<&Step, &AgentError>) + Send + Sync>;
+
+/// A model that passes calls through to another model and logs each prompt and response
+///
+/// Responses from `generate_response` are logged as [`Step::Message`]. A streamed turn is
+/// logged once, as the step its chunks add up to, when its stream ends.
+pub struct LoggingModel {
+    inner: Arc<dyn Model>,
+    log: Arc<ModelLogFn>,
+}
+
+impl LoggingModel {
+    pub fn new(inner: Arc<dyn Model>, log: ModelLogFn) -> Self {
+        Self {
+            inner,
+            log: Arc::new(log),
+        }
+    }
+
+    /// Log through `tracing` debug events instead of a closure
+    pub fn with_tracing(inner: Arc<dyn Model>) -> Self {
+        Self::new(
+            inner,
+            Box::new(|messages, response| {
+                tracing::debug!(?messages, ?response, "model call");
+            }),
+        )
+    }
+}
+
+#[async_trait]
+impl Model for LoggingModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> AgentResult<String> {
+        let messages = context.messages.clone();
+        let response = self.inner.generate_response(context, tools).await;
+        match &response {
+            Ok(content) => (self.log)(&messages, Ok(&Step::Message(content.clone()))),
+            Err(error) => (self.log)(&messages, Err(error)),
+        }
+        response
+    }
+
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> AgentResult<Step> {
+        let messages = context.messages.clone();
+        let step = self.inner.generate_step(context, tools).await;
+        (self.log)(&messages, step.as_ref());
+        step
+    }
+
+    async fn generate_step_with_params(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<Step> {
+        let messages = context.messages.clone();
+        let step = self
+            .inner
+            .generate_step_with_params(context, tools, params)
+            .await;
+        (self.log)(&messages, step.as_ref());
+        step
+    }
+
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<StepStream> {
+        let messages = context.messages.clone();
+        let stream = match self.inner.stream_step(context, tools, params).await {
+            Ok(stream) => stream,
+            Err(error) => {
+                (self.log)(&messages, Err(&error));
+                return Err(error);
+            }
+        };
+        let logged = LoggedStream {
+            stream,
+            content: String::new(),
+            messages,
+            log: self.log.clone(),
+        };
+        Ok(futures::stream::unfold(Some(logged), LoggedStream::next).boxed())
+    }
+
+    fn preview_request(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<serde_json::Value> {
+        self.inner.preview_request(context, tools, params)
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    async fn list_models(&self) -> AgentResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    async fn moderate(&self, text: &str) -> AgentResult<ModerationResult> {
+        self.inner.moderate(text).await
+    }
+}
+
+/// A streamed turn being passed through, with the message content seen so far
+struct LoggedStream {
+    stream: StepStream,
+    content: String,
+    messages: Vec<Message>,
+    log: Arc<ModelLogFn>,
+}
+
+impl LoggedStream {
+    async fn next(state: Option<Self>) -> Option<(AgentResult<StepChunk>, Option<Self>)> {
+        let mut logged = state?;
+        match logged.stream.next().await {
+            Some(Ok(chunk)) => {
+                match &chunk {
+                    StepChunk::Content(delta) => logged.content.push_str(delta),
+                    StepChunk::Usage(_) => {}
+                    // Tool calls are the last chunk, and callers stop reading once they have them
+                    StepChunk::ToolCalls(tool_calls) => {
+                        let step = Step::ToolCalls(tool_calls.clone());
+                        (logged.log)(&logged.messages, Ok(&step));
+                        return Some((Ok(chunk), None));
+                    }
+                }
+                Some((Ok(chunk), Some(logged)))
+            }
+            Some(Err(error)) => {
+                (logged.log)(&logged.messages, Err(&error));
+                Some((Err(error), None))
+            }
+            None => {
+                let step = Step::Message(logged.content);
+                (logged.log)(&logged.messages, Ok(&step));
+                None
+            }
+        }
+    }
+}
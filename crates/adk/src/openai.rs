@@ -1,18 +1,196 @@
 use async_openai::{
     Client,
     config::OpenAIConfig,
+    error::OpenAIError,
     types::{
+        ChatCompletionFunctionCall, ChatCompletionMessageToolCall,
+        ChatCompletionMessageToolCallChunk, ChatCompletionNamedToolChoice,
         ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+        ChatCompletionRequestDeveloperMessage, ChatCompletionRequestDeveloperMessageContent,
         ChatCompletionRequestFunctionMessage, ChatCompletionRequestMessage,
+        ChatCompletionRequestMessageContentPartImage, ChatCompletionRequestMessageContentPartText,
         ChatCompletionRequestSystemMessage, ChatCompletionRequestSystemMessageContent,
+        ChatCompletionRequestToolMessage, ChatCompletionRequestToolMessageContent,
         ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
-        ChatCompletionTool, ChatCompletionToolChoiceOption, ChatCompletionToolType,
-        CreateChatCompletionRequest, FunctionObject,
+        ChatCompletionRequestUserMessageContentPart, ChatCompletionResponseMessage,
+        ChatCompletionStreamOptions, ChatCompletionTool, ChatCompletionToolChoiceOption,
+        ChatCompletionToolType, CompletionUsage, CreateChatCompletionRequest,
+        CreateChatCompletionResponse, CreateChatCompletionStreamResponse, CreateEmbeddingRequest,
+        CreateModerationRequest, EmbeddingInput, FunctionCall, FunctionName, FunctionObject,
+        ImageUrl, ModerationInput, ResponseFormat, Stop,
     },
 };
+// Only used for the legacy functions API
+#[allow(deprecated)]
+use async_openai::types::ChatCompletionFunctions;
 use async_trait::async_trait;
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::Instrument;
 
-use crate::{error::AgentError, tool::Tool, types::RunContext};
+use crate::{
+    error::{AgentError, AgentResult},
+    retry::{Backoff, ExponentialBackoff},
+    tool::{TOOL_CALL_DENIED, Tool, ToolCall, ToolResult, execute_tool_call, find_tool},
+    types::{ContentPart, Message, RunContext},
+};
+
+/// A single model turn: either a final message or a request to call tools
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// The model produced a message for the user
+    Message(String),
+    /// The model wants these tools to be called before it continues
+    ToolCalls(Vec<ToolCall>),
+}
+
+/// A piece of a streamed model turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepChunk {
+    /// Part of the message for the user
+    Content(String),
+    /// Every tool call of the turn, sent once they are complete
+    ToolCalls(Vec<ToolCall>),
+    /// Tokens the turn used, prompt and completion together, sent ahead of any tool calls
+    Usage(u64),
+}
+
+/// The chunks of a streamed model turn
+pub type StepStream = BoxStream<'static, AgentResult<StepChunk>>;
+
+/// A streamed turn being read, with what it keeps until the stream is done
+struct StreamState {
+    stream: BoxStream<'static, Result<CreateChatCompletionStreamResponse, OpenAIError>>,
+    tool_calls: ToolCallAccumulator,
+    refusal: String,
+    usage: Option<CompletionUsage>,
+    /// Chunks to hand out before reading on
+    pending: VecDeque<AgentResult<StepChunk>>,
+    finished: bool,
+    /// The slot under the concurrency limit, released once the stream is done
+    permit: Option<OwnedSemaphorePermit>,
+    #[cfg(feature = "otel")]
+    span: Option<opentelemetry::global::BoxedSpan>,
+}
+
+impl StreamState {
+    async fn next_chunk(&mut self) -> Option<AgentResult<StepChunk>> {
+        loop {
+            if let Some(chunk) = self.pending.pop_front() {
+                return Some(chunk);
+            }
+            if self.finished {
+                return None;
+            }
+            match self.stream.next().await {
+                Some(Ok(response)) => self.read(response),
+                Some(Err(e)) => {
+                    let error =
+                        AgentError::model_error(format!("Failed to stream response: {}", e), e);
+                    self.finish(Some(error));
+                }
+                None => self.finish(None),
+            }
+        }
+    }
+
+    /// Take in one response of the stream, queueing the content it carries
+    fn read(&mut self, response: CreateChatCompletionStreamResponse) {
+        if let Some(usage) = response.usage {
+            self.usage = Some(usage);
+        }
+        let Some(choice) = response.choices.into_iter().next() else {
+            return;
+        };
+        for chunk in choice.delta.tool_calls.iter().flatten() {
+            self.tool_calls.push(chunk);
+        }
+        if let Some(refusal) = choice.delta.refusal {
+            self.refusal.push_str(&refusal);
+        }
+        if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+            self.pending.push_back(Ok(StepChunk::Content(content)));
+        }
+    }
+
+    /// Queue how the turn ended: with an error or refusal, or with its usage and tool calls
+    fn finish(&mut self, error: Option<AgentError>) {
+        self.finished = true;
+        self.permit = None;
+        let error = error.or_else(|| {
+            (!self.refusal.is_empty())
+                .then(|| AgentError::Refusal(std::mem::take(&mut self.refusal)))
+        });
+        #[cfg(feature = "otel")]
+        if let Some(span) = self.span.take() {
+            let outcome = match &error {
+                Some(error) => Err(error),
+                None => Ok(self.usage.as_ref()),
+            };
+            crate::otel::end_stream_span(span, outcome);
+        }
+
+        if let Some(error) = error {
+            self.pending.push_back(Err(error));
+            return;
+        }
+        if let Some(usage) = &self.usage {
+            self.pending
+                .push_back(Ok(StepChunk::Usage(u64::from(usage.total_tokens))));
+        }
+        if !self.tool_calls.is_empty() {
+            let tool_calls = std::mem::take(&mut self.tool_calls).finish();
+            self.pending.push_back(Ok(StepChunk::ToolCalls(tool_calls)));
+        }
+    }
+}
+
+/// Stream a step that was generated in one go as a single chunk
+fn single_chunk(step: Step) -> StepStream {
+    let chunk = match step {
+        Step::Message(content) => StepChunk::Content(content),
+        Step::ToolCalls(tool_calls) => StepChunk::ToolCalls(tool_calls),
+    };
+    Box::pin(futures::stream::once(async move { Ok(chunk) }))
+}
+
+/// The verdict of a moderation check
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModerationResult {
+    /// Whether the text breaks the content policy
+    pub flagged: bool,
+    /// The categories the text was flagged for, such as `harassment` or `self-harm`
+    pub categories: Vec<String>,
+}
+
+/// The parts of a moderation response that make up a [`ModerationResult`]
+#[derive(Deserialize)]
+struct ModerationResponse {
+    results: Vec<RawModeration>,
+}
+
+#[derive(Deserialize)]
+struct RawModeration {
+    flagged: bool,
+    #[serde(default)]
+    categories: BTreeMap<String, bool>,
+}
+
+/// Per-call overrides for a model's own settings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelParams {
+    /// Sampling temperature to use instead of the model's
+    pub temperature: Option<f32>,
+    /// Output format to ask for instead of the model's
+    pub response_format: Option<ResponseFormat>,
+}
 
 /// Trait for language models that can be used by agents
 #[async_trait]
@@ -23,64 +201,675 @@ pub trait Model: Send + Sync {
         context: &mut RunContext,
         tools: &[&dyn Tool],
     ) -> Result<String, AgentError>;
+
+    /// Generate a single turn without executing any tools
+    ///
+    /// Models that don't report tool calls can rely on the default, which treats the
+    /// whole response as the final message.
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<Step, AgentError> {
+        self.generate_response(context, tools)
+            .await
+            .map(Step::Message)
+    }
+
+    /// Generate a single turn with per-call parameter overrides
+    ///
+    /// The default ignores `params`; models with sampling settings should honor them.
+    async fn generate_step_with_params(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        _params: &ModelParams,
+    ) -> Result<Step, AgentError> {
+        self.generate_step(context, tools).await
+    }
+
+    /// Generate a single turn, streaming the message as the model produces it
+    ///
+    /// The default generates the whole step and yields it as a single chunk.
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<StepStream> {
+        Ok(single_chunk(
+            self.generate_step_with_params(context, tools, params)
+                .await?,
+        ))
+    }
+
+    /// Build the request the next turn would send, as JSON, without calling the model
+    ///
+    /// Models that can't show their requests return a `ModelError`, which is the default.
+    fn preview_request(
+        &self,
+        _context: &RunContext,
+        _tools: &[&dyn Tool],
+        _params: &ModelParams,
+    ) -> Result<serde_json::Value, AgentError> {
+        Err(AgentError::ModelError(
+            "this model doesn't support previewing requests".into(),
+        ))
+    }
+
+    /// The name of the underlying model, if it has one
+    fn model_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// List the models the endpoint offers
+    ///
+    /// The default lists just this model's own name, or nothing if it has none.
+    async fn list_models(&self) -> AgentResult<Vec<String>> {
+        Ok(self.model_name().map(String::from).into_iter().collect())
+    }
+
+    /// Check text against the provider's content policy
+    ///
+    /// The default fails with a `ConfigurationError`, as most models have no moderation endpoint.
+    async fn moderate(&self, _text: &str) -> AgentResult<ModerationResult> {
+        Err(AgentError::ConfigurationError(
+            "Moderation is not supported by this model".into(),
+        ))
+    }
 }
 
+/// Forwards to the shared model, so `Arc<dyn Model>` can be used wherever a model is expected
+#[async_trait]
+impl<T: Model + ?Sized> Model for Arc<T> {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        (**self).generate_response(context, tools).await
+    }
+
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<Step, AgentError> {
+        (**self).generate_step(context, tools).await
+    }
+
+    async fn generate_step_with_params(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> Result<Step, AgentError> {
+        (**self)
+            .generate_step_with_params(context, tools, params)
+            .await
+    }
+
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<StepStream> {
+        (**self).stream_step(context, tools, params).await
+    }
+
+    fn preview_request(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> Result<serde_json::Value, AgentError> {
+        (**self).preview_request(context, tools, params)
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        (**self).model_name()
+    }
+
+    async fn list_models(&self) -> AgentResult<Vec<String>> {
+        (**self).list_models().await
+    }
+
+    async fn moderate(&self, text: &str) -> AgentResult<ModerationResult> {
+        (**self).moderate(text).await
+    }
+}
+
+/// Forwards to the borrowed model
+#[async_trait]
+impl<T: Model + ?Sized> Model for &T {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        (**self).generate_response(context, tools).await
+    }
+
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<Step, AgentError> {
+        (**self).generate_step(context, tools).await
+    }
+
+    async fn generate_step_with_params(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> Result<Step, AgentError> {
+        (**self)
+            .generate_step_with_params(context, tools, params)
+            .await
+    }
+
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<StepStream> {
+        (**self).stream_step(context, tools, params).await
+    }
+
+    fn preview_request(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> Result<serde_json::Value, AgentError> {
+        (**self).preview_request(context, tools, params)
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        (**self).model_name()
+    }
+
+    async fn list_models(&self) -> AgentResult<Vec<String>> {
+        (**self).list_models().await
+    }
+
+    async fn moderate(&self, text: &str) -> AgentResult<ModerationResult> {
+        (**self).moderate(text).await
+    }
+}
+
+type FnModelFn = Box<
+    dyn for<'a> Fn(&'a mut RunContext, &'a [&'a dyn Tool]) -> BoxFuture<'a, AgentResult<String>>
+        + Send
+        + Sync,
+>;
+
+/// A model backed by a closure, for tests and stubs
+///
+/// The closure returns a boxed future, so bodies are written as
+/// `|context, tools| Box::pin(async move { ... })`. Its result is always the final message;
+/// it can't request tool calls.
+pub struct FnModel {
+    function: FnModelFn,
+}
+
+impl FnModel {
+    pub fn new<F>(function: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut RunContext, &'a [&'a dyn Tool]) -> BoxFuture<'a, AgentResult<String>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            function: Box::new(function),
+        }
+    }
+}
+
+#[async_trait]
+impl Model for FnModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        (self.function)(context, tools).await
+    }
+}
+
+/// Which OpenAI API surface is used to offer tools to the model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApiMode {
+    /// The `tools`/`tool_calls` API
+    #[default]
+    Tools,
+    /// The deprecated `functions`/`function_call` API, for endpoints that only support it
+    Functions,
+}
+
+/// The model `OpenAI::embed` uses unless told otherwise
+const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// The most stop sequences the API accepts
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// The range of bias values the API accepts for a token
+const LOGIT_BIAS_RANGE: std::ops::RangeInclusive<i32> = -100..=100;
+
+/// The most times a single request is retried, budget permitting
+const MAX_RETRIES: u32 = 3;
+
+/// The wait before the first retry of a request under the default backoff
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// A token bucket of retries, refilled evenly over each minute
+#[derive(Debug)]
+struct RetryBudget {
+    per_minute: u32,
+    /// Retries left and when they were last topped up
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RetryBudget {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            state: Mutex::new((per_minute as f64, Instant::now())),
+        }
+    }
+
+    /// Take one retry from the budget, or return false if it is spent
+    fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, refilled) = &mut *state;
+        let now = Instant::now();
+        let refill = now.duration_since(*refilled).as_secs_f64() * self.per_minute as f64 / 60.0;
+        *tokens = (*tokens + refill).min(self.per_minute as f64);
+        *refilled = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A client that gives up after the first failure, leaving retries to `with_retries`
+fn client_without_retries(config: OpenAIConfig) -> Client<OpenAIConfig> {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+    Client::with_config(config).with_backoff(backoff)
+}
+
+/// A function that modifies a chat completion request before it is sent
+pub type RequestInterceptor = Box<dyn Fn(&mut CreateChatCompletionRequest) + Send + Sync>;
+
 /// OpenAI model implementation
 pub struct OpenAI {
     client: Client<OpenAIConfig>,
     /// See the [model endpoint compatibility](https://platform.openai.com/docs/models#model-endpoint-compatibility) table for details on which models work with the Chat API.
     model: String,
+    /// The model used to embed text
+    embedding_model: String,
+    /// Sampling temperature, between 0 and 2
+    temperature: Option<f32>,
+    /// Nucleus sampling probability mass, between 0 and 1
+    top_p: Option<f32>,
+    /// Penalty for tokens that already appeared at all, between -2 and 2
+    presence_penalty: Option<f32>,
+    /// Penalty for tokens proportional to how often they appeared, between -2 and 2
+    frequency_penalty: Option<f32>,
+    /// Maximum number of tokens to generate per response
+    max_tokens: Option<u32>,
+    /// Maximum number of tokens to generate per response, including reasoning tokens
+    max_completion_tokens: Option<u32>,
+    /// Seed for best-effort deterministic sampling
+    seed: Option<i64>,
+    /// Sequences at which generation stops
+    stop: Option<Vec<String>>,
+    /// Bias added to the logits of tokens, keyed by token id
+    logit_bias: Option<HashMap<String, i32>>,
+    /// How tools are offered to the model
+    api_mode: ApiMode,
+    /// A function the model must call, instead of choosing for itself
+    forced_function: Option<String>,
+    /// Adjusts each request right before it is sent
+    request_interceptor: Option<RequestInterceptor>,
+    /// Bounds how many requests are in flight at once
+    concurrency_limit: Option<Arc<Semaphore>>,
+    /// Asks the model for text, any JSON object, or JSON matching a schema
+    response_format: Option<ResponseFormat>,
+    /// How many times `generate_response` asks the model to fix output that isn't valid JSON
+    json_retries: usize,
+    /// Retries shared by every request through this model
+    retry_budget: Option<RetryBudget>,
+    /// How long to wait before each retry, if set with `with_backoff`
+    backoff: Option<Mutex<Box<dyn Backoff>>>,
+    /// How long a chat request may take before it is abandoned
+    request_timeout: Option<Duration>,
+}
+
+/// Shows the model's settings; the client, and with it the API key, is left out
+impl std::fmt::Debug for OpenAI {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAI")
+            .field("model", &self.model)
+            .field("embedding_model", &self.embedding_model)
+            .field("temperature", &self.temperature)
+            .field("top_p", &self.top_p)
+            .field("max_tokens", &self.max_tokens)
+            .field("max_completion_tokens", &self.max_completion_tokens)
+            .field("seed", &self.seed)
+            .field("api_mode", &self.api_mode)
+            .field("forced_function", &self.forced_function)
+            .field("json_retries", &self.json_retries)
+            .field("retry_budget", &self.retry_budget)
+            .field("request_timeout", &self.request_timeout)
+            .finish_non_exhaustive()
+    }
 }
 
 impl OpenAI {
     pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
-        let config = OpenAIConfig::new().with_api_key(api_key);
+        Self::with_config(OpenAIConfig::new().with_api_key(api_key), model)
+    }
+
+    /// Create a model from a full client configuration, e.g. to talk to a proxy or gateway
+    pub fn with_config(config: OpenAIConfig, model: impl Into<String>) -> Self {
         let client = Client::with_config(config);
         Self {
             client,
             model: model.into(),
+            embedding_model: DEFAULT_EMBEDDING_MODEL.into(),
+            temperature: Some(0.7),
+            top_p: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            max_tokens: None,
+            max_completion_tokens: None,
+            seed: None,
+            stop: None,
+            logit_bias: None,
+            api_mode: ApiMode::default(),
+            forced_function: None,
+            request_interceptor: None,
+            concurrency_limit: None,
+            response_format: None,
+            json_retries: 0,
+            retry_budget: None,
+            backoff: None,
+            request_timeout: None,
         }
     }
 
-    fn create_messages(&self, context: &RunContext) -> Vec<ChatCompletionRequestMessage> {
-        context
-            .messages
+    /// Send requests to an OpenAI-compatible API at `base_url` instead of api.openai.com
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        let config = self.client.config().clone().with_api_base(base_url);
+        self.client = if self.retries_requests() {
+            client_without_retries(config)
+        } else {
+            Client::with_config(config)
+        };
+        self
+    }
+
+    /// Get the client configuration
+    pub fn config(&self) -> &OpenAIConfig {
+        self.client.config()
+    }
+
+    /// Embed text with `model` instead of `text-embedding-3-small`
+    pub fn with_embedding_model(mut self, model: impl Into<String>) -> Self {
+        self.embedding_model = model.into();
+        self
+    }
+
+    /// Set the sampling temperature
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Only sample from the tokens making up the top `top_p` probability mass
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Penalize tokens that already appeared, nudging the model towards new topics
+    pub fn with_presence_penalty(mut self, presence_penalty: f32) -> Self {
+        self.presence_penalty = Some(presence_penalty);
+        self
+    }
+
+    /// Penalize tokens by how often they already appeared, reducing verbatim repetition
+    pub fn with_frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        self.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+
+    /// Limit the number of tokens generated per response
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Limit the number of tokens generated per response, including reasoning tokens
+    ///
+    /// Takes precedence over `with_max_tokens`. Either limit is sent as `max_completion_tokens`
+    /// to reasoning models (`o1`, `o3`, `o4`), which reject `max_tokens`, and as `max_tokens`
+    /// to all other models.
+    pub fn with_max_completion_tokens(mut self, max_completion_tokens: u32) -> Self {
+        self.max_completion_tokens = Some(max_completion_tokens);
+        self
+    }
+
+    /// Sample deterministically (on a best-effort basis) so repeated requests give the same output
+    pub fn with_seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Stop generating at any of these sequences
+    ///
+    /// The API accepts at most four; more return a `ConfigurationError`.
+    pub fn with_stop(mut self, stop: Vec<String>) -> AgentResult<Self> {
+        if stop.len() > MAX_STOP_SEQUENCES {
+            return Err(AgentError::ConfigurationError(format!(
+                "At most {} stop sequences are allowed, got {}",
+                MAX_STOP_SEQUENCES,
+                stop.len()
+            )));
+        }
+        self.stop = (!stop.is_empty()).then_some(stop);
+        Ok(self)
+    }
+
+    /// Make tokens more or less likely, keyed by token id
+    ///
+    /// Biases must be between -100 (ban the token) and 100 (only pick the token); others
+    /// return a `ConfigurationError`.
+    pub fn with_logit_bias(mut self, logit_bias: HashMap<String, i32>) -> AgentResult<Self> {
+        if let Some((token, bias)) = logit_bias
             .iter()
-            .map(|msg| match msg.role.as_str() {
-                "system" => {
-                    ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
-                        content: ChatCompletionRequestSystemMessageContent::Text(
-                            msg.content.clone(),
-                        ),
-                        name: msg.tool_name.clone(),
-                    })
-                }
-                "assistant" =>
-                {
-                    #[allow(deprecated)]
-                    ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
-                        content: Some(ChatCompletionRequestAssistantMessageContent::Text(
-                            msg.content.clone(),
-                        )),
-                        name: msg.tool_name.clone(),
-                        tool_calls: None,
-                        function_call: None,
-                        audio: None,
-                        refusal: None,
-                    })
-                }
-                "tool" => {
-                    ChatCompletionRequestMessage::Function(ChatCompletionRequestFunctionMessage {
-                        content: Some(msg.content.clone()),
-                        name: msg.tool_name.clone().unwrap_or_default(),
-                    })
-                }
-                _ => ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
-                    content: ChatCompletionRequestUserMessageContent::Text(msg.content.clone()),
-                    name: msg.tool_name.clone(),
-                }),
-            })
-            .collect()
+            .find(|(_, bias)| !LOGIT_BIAS_RANGE.contains(bias))
+        {
+            return Err(AgentError::ConfigurationError(format!(
+                "Logit bias for token {} must be between {} and {}, got {}",
+                token,
+                LOGIT_BIAS_RANGE.start(),
+                LOGIT_BIAS_RANGE.end(),
+                bias
+            )));
+        }
+        self.logit_bias = (!logit_bias.is_empty()).then_some(logit_bias);
+        Ok(self)
+    }
+
+    /// Choose between the tools API and the legacy functions API
+    pub fn with_api_mode(mut self, api_mode: ApiMode) -> Self {
+        self.api_mode = api_mode;
+        self
+    }
+
+    /// Make the model call the function `name` on every turn, rather than choosing itself
+    ///
+    /// Sets `function_call` in the functions API and `tool_choice` in the tools API. Since
+    /// the model can't answer without calling the function, this suits single-step use with
+    /// `generate_step` more than agents, which would loop until they run out of turns.
+    pub fn with_forced_function(mut self, name: impl Into<String>) -> Self {
+        self.forced_function = Some(name.into());
+        self
+    }
+
+    /// Modify every request right before it is sent, e.g. to add metadata the builder doesn't set
+    pub fn with_request_interceptor(mut self, interceptor: RequestInterceptor) -> Self {
+        self.request_interceptor = Some(interceptor);
+        self
+    }
+
+    /// Ask for output in a specific format, e.g. JSON matching a schema
+    ///
+    /// With a JSON format, `generate_response` checks that the final output parses as JSON and
+    /// matches the schema, if there is one. Output that doesn't is an `InvalidInput` error
+    /// unless retries are allowed with `with_json_retries`.
+    pub fn with_response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = Some(response_format);
+        self
+    }
+
+    /// Give up on a chat request that takes longer than `timeout`, retries included
+    ///
+    /// A request that runs out of time fails with a `ModelError`. Time spent waiting under
+    /// the concurrency limit doesn't count. For streamed turns only the wait for the first chunk
+    /// is bounded.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Ask the model up to `retries` times to correct output that isn't valid JSON
+    pub fn with_json_retries(mut self, retries: usize) -> Self {
+        self.json_retries = retries;
+        self
+    }
+
+    /// Send at most `max_concurrency` requests at once, queueing the rest
+    ///
+    /// The limit applies to all calls made through this model, including from every agent
+    /// sharing it. Zero returns a `ConfigurationError`.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> AgentResult<Self> {
+        if max_concurrency == 0 {
+            return Err(AgentError::ConfigurationError(
+                "Max concurrency must be at least 1".into(),
+            ));
+        }
+        self.concurrency_limit = Some(Arc::new(Semaphore::new(max_concurrency)));
+        Ok(self)
+    }
+
+    /// Retry failed requests from a budget of `per_minute` retries shared by all calls
+    ///
+    /// Transient failures such as rate limits and server errors are retried, waiting as set
+    /// with `with_backoff`, while the budget lasts; once it is spent they fail straight away,
+    /// so a batch of calls backs off as a whole under sustained failures. The budget refills
+    /// evenly over each minute. Zero returns a `ConfigurationError`.
+    pub fn with_retry_budget(mut self, per_minute: u32) -> AgentResult<Self> {
+        if per_minute == 0 {
+            return Err(AgentError::ConfigurationError(
+                "Retry budget must be at least 1 per minute".into(),
+            ));
+        }
+        self.client = client_without_retries(self.client.config().clone());
+        self.retry_budget = Some(RetryBudget::new(per_minute));
+        Ok(self)
+    }
+
+    /// Retry transient failures up to three times, waiting between retries as `backoff` decides
+    ///
+    /// Without this, retries wait twice as long each time, starting at 100ms. Under a retry
+    /// budget each retry also draws from the budget; see `with_retry_budget`.
+    pub fn with_backoff(mut self, backoff: Box<dyn Backoff>) -> Self {
+        self.client = client_without_retries(self.client.config().clone());
+        self.backoff = Some(Mutex::new(backoff));
+        self
+    }
+
+    /// Whether `with_retries` retries requests, rather than the client itself
+    fn retries_requests(&self) -> bool {
+        self.retry_budget.is_some() || self.backoff.is_some()
+    }
+
+    /// Build the chat completion request for the current context and tools
+    #[allow(deprecated)]
+    pub fn create_request(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+    ) -> CreateChatCompletionRequest {
+        let mut request = CreateChatCompletionRequest {
+            model: self.model.clone(),
+            messages: self.create_messages(context),
+            temperature: self.temperature,
+            top_p: self.top_p,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            seed: self.seed,
+            stop: self.stop.clone().map(Stop::StringArray),
+            response_format: self.response_format.clone(),
+            logit_bias: self.logit_bias.as_ref().map(|logit_bias| {
+                logit_bias
+                    .iter()
+                    .map(|(token, bias)| (token.clone(), (*bias).into()))
+                    .collect()
+            }),
+            ..Default::default()
+        };
+
+        let max_tokens = self.max_completion_tokens.or(self.max_tokens);
+        if is_reasoning_model(&self.model) {
+            request.max_completion_tokens = max_tokens;
+        } else {
+            request.max_tokens = max_tokens;
+        }
+
+        // The API rejects an empty tool list, as well as a tool choice without tools
+        if tools.is_empty() {
+            return request;
+        }
+        match self.api_mode {
+            ApiMode::Tools => {
+                request.tools = Some(self.create_tools(tools));
+                request.tool_choice = Some(match &self.forced_function {
+                    Some(name) => {
+                        ChatCompletionToolChoiceOption::Named(ChatCompletionNamedToolChoice {
+                            r#type: ChatCompletionToolType::Function,
+                            function: FunctionName { name: name.clone() },
+                        })
+                    }
+                    None => ChatCompletionToolChoiceOption::Auto,
+                });
+            }
+            ApiMode::Functions => {
+                request.functions = Some(self.create_functions(tools));
+                request.function_call = Some(match &self.forced_function {
+                    Some(name) => ChatCompletionFunctionCall::Function { name: name.clone() },
+                    None => ChatCompletionFunctionCall::Auto,
+                });
+            }
+        }
+
+        request
+    }
+
+    fn create_messages(&self, context: &RunContext) -> Vec<ChatCompletionRequestMessage> {
+        request_messages(&context.messages, self.api_mode)
     }
 
     fn create_tools(&self, tools: &[&dyn Tool]) -> Vec<ChatCompletionTool> {
@@ -97,6 +886,239 @@ impl OpenAI {
             })
             .collect()
     }
+
+    #[allow(deprecated)]
+    fn create_functions(&self, tools: &[&dyn Tool]) -> Vec<ChatCompletionFunctions> {
+        tools
+            .iter()
+            .map(|tool| ChatCompletionFunctions {
+                name: tool.name().to_string(),
+                description: Some(tool.description().to_string()),
+                parameters: tool.parameters_schema(),
+            })
+            .collect()
+    }
+
+    /// Generate `n` alternative responses to the same context in a single request
+    ///
+    /// Only the message contents are returned; choices in which the model asks for tools
+    /// come back as empty strings, as no tools are executed here.
+    pub async fn generate_candidates(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+        n: u8,
+    ) -> AgentResult<Vec<String>> {
+        let mut request = self.create_request(context, tools);
+        request.n = Some(n);
+        let (response, _) = self.send_request(request).await?;
+
+        Ok(response
+            .choices
+            .into_iter()
+            .map(|choice| choice.message.content.unwrap_or_default())
+            .collect())
+    }
+
+    /// Embed each input with the embedding model, returning the vectors in input order
+    pub async fn embed(&self, inputs: &[String]) -> AgentResult<Vec<Vec<f32>>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let request = CreateEmbeddingRequest {
+            model: self.embedding_model.clone(),
+            input: EmbeddingInput::StringArray(inputs.to_vec()),
+            ..Default::default()
+        };
+
+        let _permit = self.acquire_permit().await;
+        let mut response = self
+            .with_retries(|| async { self.client.embeddings().create(request.clone()).await })
+            .instrument(tracing::debug_span!("openai_embeddings", model = %self.embedding_model))
+            .await
+            .map_err(|e| AgentError::model_error(format!("Failed to embed inputs: {}", e), e))?;
+
+        if response.data.len() != inputs.len() {
+            return Err(AgentError::ModelError(format!(
+                "Expected {} embeddings, got {}",
+                inputs.len(),
+                response.data.len()
+            )));
+        }
+        response.data.sort_by_key(|embedding| embedding.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect())
+    }
+
+    fn create_request_with_params(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> CreateChatCompletionRequest {
+        let mut request = self.create_request(context, tools);
+        if let Some(temperature) = params.temperature {
+            request.temperature = Some(temperature);
+        }
+        if let Some(response_format) = &params.response_format {
+            request.response_format = Some(response_format.clone());
+        }
+        request
+    }
+
+    fn intercept(&self, request: &mut CreateChatCompletionRequest) {
+        if let Some(interceptor) = &self.request_interceptor {
+            interceptor(request);
+        }
+    }
+
+    /// Describe why `content` doesn't satisfy the JSON response format, if one is configured
+    fn json_output_problem(&self, content: &str) -> Option<String> {
+        let schema = match &self.response_format {
+            None | Some(ResponseFormat::Text) => return None,
+            Some(ResponseFormat::JsonObject) => None,
+            Some(ResponseFormat::JsonSchema { json_schema }) => json_schema.schema.as_ref(),
+        };
+        let value: serde_json::Value = match serde_json::from_str(content) {
+            Ok(value) => value,
+            Err(e) => return Some(format!("output is not valid JSON: {}", e)),
+        };
+        schema.and_then(|schema| schema_violation(&value, schema, "$"))
+    }
+
+    /// Wait for a free slot under the concurrency limit, held until the permit is dropped
+    async fn acquire_permit(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.concurrency_limit {
+            // The semaphore is never closed
+            Some(limit) => Some(
+                limit
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore closed"),
+            ),
+            None => None,
+        }
+    }
+
+    /// Run `request`, failing with a `ModelError` if it outlasts the request timeout
+    async fn within_timeout<T>(
+        &self,
+        request: impl Future<Output = AgentResult<T>>,
+    ) -> AgentResult<T> {
+        match self.request_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, request)
+                .await
+                .unwrap_or_else(|_| Err(AgentError::ModelError("model request timed out".into()))),
+            None => request.await,
+        }
+    }
+
+    /// Make a request, retrying transient failures while the retry budget, if any, lasts
+    async fn with_retries<T, F, Fut>(&self, request: F) -> Result<T, OpenAIError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, OpenAIError>>,
+    {
+        let mut retries = 0;
+        loop {
+            match request().await {
+                Err(error)
+                    if retries < MAX_RETRIES
+                        && crate::error::is_transient(&error)
+                        && self.retries_requests()
+                        && self
+                            .retry_budget
+                            .as_ref()
+                            .is_none_or(RetryBudget::try_acquire) =>
+                {
+                    tracing::warn!("Retrying failed request: {}", error);
+                    retries += 1;
+                    let delay = match &self.backoff {
+                        Some(backoff) => backoff.lock().unwrap().next_delay(retries as usize),
+                        None => ExponentialBackoff::new(RETRY_DELAY).next_delay(retries as usize),
+                    };
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send a chat request, returning the response with the first choice's reasoning, if any
+    async fn send_request(
+        &self,
+        mut request: CreateChatCompletionRequest,
+    ) -> AgentResult<(CreateChatCompletionResponse, Option<String>)> {
+        self.intercept(&mut request);
+        let _permit = self.acquire_permit().await;
+        #[cfg(feature = "otel")]
+        let span = crate::otel::start_chat_span(&request);
+
+        // The typed response drops the non-standard reasoning fields, so read them first
+        let response = self
+            .within_timeout(async {
+                self.with_retries(|| async {
+                    self.client
+                        .chat()
+                        .create_byot::<_, serde_json::Value>(request.clone())
+                        .await
+                })
+                .instrument(tracing::debug_span!("openai_request", model = %self.model))
+                .await
+                .map_err(|e| {
+                    AgentError::model_error(format!("Failed to generate response: {}", e), e)
+                })
+            })
+            .await
+            .and_then(|mut raw| {
+                let reasoning = take_reasoning(&mut raw);
+                let response = serde_json::from_value(raw).map_err(|e| {
+                    AgentError::model_error(format!("Failed to parse response: {}", e), e)
+                })?;
+                Ok((response, reasoning))
+            });
+
+        #[cfg(feature = "otel")]
+        crate::otel::end_chat_span(span, response.as_ref().map(|(response, _)| response));
+        response
+    }
+
+    /// Turn the response message into a step, reading calls from the configured API surface
+    #[allow(deprecated)]
+    fn parse_step(&self, message: ChatCompletionResponseMessage) -> Step {
+        let tool_calls: Vec<ToolCall> = match self.api_mode {
+            ApiMode::Tools => message
+                .tool_calls
+                .unwrap_or_default()
+                .into_iter()
+                .map(|tool_call| ToolCall {
+                    id: tool_call.id,
+                    name: tool_call.function.name,
+                    arguments: tool_call.function.arguments,
+                })
+                .collect(),
+            // Function calls carry no id
+            ApiMode::Functions => message
+                .function_call
+                .into_iter()
+                .map(|function_call| ToolCall {
+                    id: String::new(),
+                    name: function_call.name,
+                    arguments: function_call.arguments,
+                })
+                .collect(),
+        };
+
+        if tool_calls.is_empty() {
+            Step::Message(message.content.unwrap_or_default())
+        } else {
+            Step::ToolCalls(tool_calls)
+        }
+    }
 }
 
 #[async_trait]
@@ -106,45 +1128,556 @@ impl Model for OpenAI {
         context: &mut RunContext,
         tools: &[&dyn Tool],
     ) -> Result<String, AgentError> {
-        let messages = self.create_messages(context);
-        let openai_tools = self.create_tools(tools);
+        let mut retries_left = self.json_retries;
+        loop {
+            let content = generate_with_tools(self, context, tools).await?;
+            let Some(problem) = self.json_output_problem(&content) else {
+                return Ok(content);
+            };
+            if retries_left == 0 {
+                return Err(AgentError::InvalidInput(format!(
+                    "Invalid model output: {}",
+                    problem
+                )));
+            }
+            retries_left -= 1;
+            context.add_message("assistant", content);
+            context.add_message(
+                "user",
+                format!(
+                    "Your last response was rejected because the {}. Reply with only the corrected JSON.",
+                    problem
+                ),
+            );
+        }
+    }
 
-        let request = CreateChatCompletionRequest {
-            model: self.model.clone(),
-            messages,
-            tools: Some(openai_tools),
-            tool_choice: Some(ChatCompletionToolChoiceOption::Auto),
-            temperature: Some(0.7),
-            ..Default::default()
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<Step, AgentError> {
+        self.generate_step_with_params(context, tools, &ModelParams::default())
+            .await
+    }
+
+    async fn generate_step_with_params(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> Result<Step, AgentError> {
+        let request = self.create_request_with_params(context, tools, params);
+        let (response, reasoning) = self.send_request(request).await?;
+        context.reasoning = reasoning;
+        if let Some(usage) = &response.usage {
+            context.tokens_used += u64::from(usage.total_tokens);
+        }
+
+        let message = response
+            .choices
+            .first()
+            .ok_or_else(|| AgentError::ModelError("no choices in response".into()))?
+            .message
+            .clone();
+        if let Some(refusal) = message
+            .refusal
+            .clone()
+            .filter(|refusal| !refusal.is_empty())
+        {
+            return Err(AgentError::Refusal(refusal));
+        }
+
+        Ok(self.parse_step(message))
+    }
+
+    /// Stream the turn's content deltas, then its usage and tool calls once the stream is done
+    ///
+    /// Like other requests, opening the stream is retried under the retry budget and holds a
+    /// slot under the concurrency limit, which is kept until the stream is done. A refusal
+    /// ends the stream with a `Refusal` error. The legacy functions API isn't streamed; its
+    /// turns come back as a single chunk.
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<StepStream> {
+        if self.api_mode == ApiMode::Functions {
+            return Ok(single_chunk(
+                self.generate_step_with_params(context, tools, params)
+                    .await?,
+            ));
+        }
+
+        let mut request = self.create_request_with_params(context, tools, params);
+        request.stream = Some(true);
+        request.stream_options = Some(ChatCompletionStreamOptions {
+            include_usage: true,
+        });
+        self.intercept(&mut request);
+        let permit = self.acquire_permit().await;
+        #[cfg(feature = "otel")]
+        let span = crate::otel::start_chat_span(&request);
+
+        // A failed request surfaces as the stream's first item, so wait for it before retrying
+        let opened = self
+            .within_timeout(async {
+                self.with_retries(|| async {
+                    let mut stream = self.client.chat().create_stream(request.clone()).await?;
+                    match stream.next().await {
+                        Some(Err(e)) => Err(e),
+                        first => Ok(futures::stream::iter(first).chain(stream).boxed()),
+                    }
+                })
+                .instrument(tracing::debug_span!("openai_stream", model = %self.model))
+                .await
+                .map_err(|e| {
+                    AgentError::model_error(format!("Failed to stream response: {}", e), e)
+                })
+            })
+            .await;
+        let stream = match opened {
+            Ok(stream) => stream,
+            Err(error) => {
+                #[cfg(feature = "otel")]
+                crate::otel::end_stream_span(span, Err(&error));
+                return Err(error);
+            }
+        };
+
+        let state = StreamState {
+            stream,
+            tool_calls: ToolCallAccumulator::new(),
+            refusal: String::new(),
+            usage: None,
+            pending: VecDeque::new(),
+            finished: false,
+            permit,
+            #[cfg(feature = "otel")]
+            span: Some(span),
         };
+        let chunks = futures::stream::unfold(state, |mut state| async move {
+            let chunk = state.next_chunk().await?;
+            Some((chunk, state))
+        });
+        Ok(Box::pin(chunks))
+    }
 
-        let response =
-            self.client.chat().create(request).await.map_err(|e| {
-                AgentError::ModelError(format!("Failed to generate response: {}", e))
+    fn preview_request(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> Result<serde_json::Value, AgentError> {
+        let mut request = self.create_request_with_params(context, tools, params);
+        self.intercept(&mut request);
+        Ok(serde_json::to_value(request)?)
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    /// List the ids of the models the API key can use
+    async fn list_models(&self) -> AgentResult<Vec<String>> {
+        let _permit = self.acquire_permit().await;
+        let response = self
+            .with_retries(|| async { self.client.models().list().await })
+            .await
+            .map_err(|e| AgentError::model_error(format!("Failed to list models: {}", e), e))?;
+        Ok(response.data.into_iter().map(|model| model.id).collect())
+    }
+
+    /// Classify text with the moderation endpoint
+    async fn moderate(&self, text: &str) -> AgentResult<ModerationResult> {
+        let request = CreateModerationRequest {
+            input: ModerationInput::String(text.to_string()),
+            model: None,
+        };
+
+        let _permit = self.acquire_permit().await;
+        // Read only the verdict, as the typed response requires every category to be present
+        let response: ModerationResponse = self
+            .with_retries(|| async { self.client.moderations().create_byot(request.clone()).await })
+            .instrument(tracing::debug_span!("openai_moderation"))
+            .await
+            .map_err(|e| AgentError::model_error(format!("Failed to moderate input: {}", e), e))?;
+
+        let moderation =
+            response.results.into_iter().next().ok_or_else(|| {
+                AgentError::ModelError("no results in moderation response".into())
             })?;
+        Ok(ModerationResult {
+            flagged: moderation.flagged,
+            categories: moderation
+                .categories
+                .into_iter()
+                .filter_map(|(category, flagged)| flagged.then_some(category))
+                .collect(),
+        })
+    }
+}
+
+/// Whether the model is a reasoning model, which takes `max_completion_tokens` only
+fn is_reasoning_model(model: &str) -> bool {
+    ["o1", "o3", "o4"]
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+}
+
+/// Repair the JSON mistakes models commonly make in tool arguments: comments, trailing commas
+/// and single-quoted strings
+///
+/// Returns `None` if the text is already valid JSON, or still isn't once repaired.
+pub(crate) fn relax_json(text: &str) -> Option<String> {
+    if serde_json::from_str::<serde_json::Value>(text).is_ok() {
+        return None;
+    }
+
+    let mut repaired = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                let quote = c;
+                repaired.push('"');
+                while let Some(c) = chars.next() {
+                    match c {
+                        _ if c == quote => break,
+                        '\\' => match chars.next() {
+                            Some('\'') => repaired.push('\''),
+                            Some(escaped) => {
+                                repaired.push('\\');
+                                repaired.push(escaped);
+                            }
+                            None => {}
+                        },
+                        // Only reachable inside single quotes
+                        '"' => repaired.push_str("\\\""),
+                        _ => repaired.push(c),
+                    }
+                }
+                repaired.push('"');
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                chars.by_ref().find(|&c| {
+                    let end = previous == '*' && c == '/';
+                    previous = c;
+                    end
+                });
+            }
+            '}' | ']' => {
+                repaired.truncate(repaired.trim_end().len());
+                if repaired.ends_with(',') {
+                    repaired.pop();
+                }
+                repaired.push(c);
+            }
+            _ => repaired.push(c),
+        }
+    }
+
+    serde_json::from_str::<serde_json::Value>(&repaired)
+        .is_ok()
+        .then_some(repaired)
+}
 
-        let message = response.choices[0].message.clone();
+/// Remove the first choice's reasoning from a raw chat response
+///
+/// Providers that return reasoning name the field either `reasoning_content` or `reasoning`.
+fn take_reasoning(raw: &mut serde_json::Value) -> Option<String> {
+    let message = raw.pointer_mut("/choices/0/message")?.as_object_mut()?;
+    ["reasoning_content", "reasoning"]
+        .into_iter()
+        .filter_map(|field| message.remove(field))
+        .find_map(|reasoning| match reasoning {
+            serde_json::Value::String(reasoning) if !reasoning.is_empty() => Some(reasoning),
+            _ => None,
+        })
+}
+
+/// Generate steps until the model produces a message, executing the tools it asks for
+///
+/// Backs `generate_response` for models whose turns come from `generate_step`. With no
+/// approval handler to ask, calls to tools that require approval are denied.
+pub(crate) async fn generate_with_tools(
+    model: &dyn Model,
+    context: &mut RunContext,
+    tools: &[&dyn Tool],
+) -> AgentResult<String> {
+    loop {
+        match model.generate_step(context, tools).await? {
+            Step::Message(content) => return Ok(content),
+            Step::ToolCalls(tool_calls) => {
+                context.add_tool_calls(tool_calls.clone());
+                let mut tool_content = Vec::new();
+                let mut follow_ups = Vec::new();
+                for tool_call in tool_calls {
+                    let tool = find_tool(tools, &tool_call.name)?;
+                    if tool.requires_approval() {
+                        context.add_tool_result(ToolResult {
+                            tool_name: tool_call.name.clone(),
+                            output: TOOL_CALL_DENIED.to_string(),
+                            call_id: Some(tool_call.id.clone()),
+                            success: false,
+                            ..Default::default()
+                        });
+                        continue;
+                    }
+                    let mut result = execute_tool_call(tool, context, &tool_call).await?;
+                    if !result.parts.is_empty() {
+                        tool_content.push(Message::tool_content(
+                            &result.tool_name,
+                            std::mem::take(&mut result.parts),
+                        ));
+                    }
+                    follow_ups.extend(result.follow_up.clone());
+                    context.add_tool_result(result);
+                }
+                context.messages.extend(tool_content);
+                for follow_up in follow_ups {
+                    context.add_message("system", follow_up);
+                }
+            }
+        }
+    }
+}
 
-        if let Some(tool_calls) = message.tool_calls {
-            if let Some(tool_call) = tool_calls.first() {
-                let tool = tools
+/// Build an assistant message, including any tool calls it made
+///
+/// The legacy functions API allows a single call per message, so only the first is sent there.
+#[allow(deprecated)]
+fn assistant_message(msg: &Message, api_mode: ApiMode) -> ChatCompletionRequestMessage {
+    let function_calls = msg.tool_calls.iter().map(|tool_call| FunctionCall {
+        name: tool_call.name.clone(),
+        arguments: tool_call.arguments.clone(),
+    });
+    let (tool_calls, function_call) = match api_mode {
+        _ if msg.tool_calls.is_empty() => (None, None),
+        ApiMode::Tools => (
+            Some(
+                msg.tool_calls
                     .iter()
-                    .find(|t| t.name() == tool_call.function.name)
-                    .ok_or_else(|| {
-                        AgentError::ToolError(format!(
-                            "Tool not found: {}",
-                            tool_call.function.name
-                        ))
-                    })?;
-
-                let result = tool.execute(context, &tool_call.function.arguments).await?;
-                context.add_tool_message(result.tool_name, result.output);
-                self.generate_response(context, tools).await
-            } else {
-                Ok(message.content.unwrap_or_default())
+                    .zip(function_calls)
+                    .map(|(tool_call, function)| ChatCompletionMessageToolCall {
+                        id: tool_call.id.clone(),
+                        r#type: ChatCompletionToolType::Function,
+                        function,
+                    })
+                    .collect(),
+            ),
+            None,
+        ),
+        ApiMode::Functions => (None, function_calls.into_iter().next()),
+    };
+
+    // Messages that only call tools have no content
+    let content = (!msg.content.is_empty() || msg.tool_calls.is_empty())
+        .then(|| ChatCompletionRequestAssistantMessageContent::Text(msg.content.clone()));
+
+    ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+        content,
+        name: msg.tool_name.clone(),
+        tool_calls,
+        function_call,
+        audio: None,
+        refusal: None,
+    })
+}
+
+/// Check a value against the common subset of JSON Schema: `type`, `enum`, `required`,
+/// `properties` and `items`
+///
+/// Returns a description of the first violation found, locating it with a path like
+/// `$.items[0].name`.
+fn schema_violation(
+    value: &serde_json::Value,
+    schema: &serde_json::Value,
+    path: &str,
+) -> Option<String> {
+    use serde_json::Value;
+
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !matches {
+            return Some(format!("value at {} is not of type {}", path, expected));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        return Some(format!(
+            "value at {} is not one of {}",
+            path,
+            Value::from(allowed.clone())
+        ));
+    }
+
+    if let Some(object) = value.as_object() {
+        let required = schema.get("required").and_then(Value::as_array);
+        for key in required.into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                return Some(format!(
+                    "object at {} is missing required field {}",
+                    path, key
+                ));
             }
-        } else {
-            Ok(message.content.unwrap_or_default())
         }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, property_schema) in properties.into_iter().flatten() {
+            if let Some(property) = object.get(key)
+                && let Some(problem) =
+                    schema_violation(property, property_schema, &format!("{}.{}", path, key))
+            {
+                return Some(problem);
+            }
+        }
+    }
+
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        return items.iter().enumerate().find_map(|(index, item)| {
+            schema_violation(item, item_schema, &format!("{}[{}]", path, index))
+        });
     }
+
+    None
+}
+
+/// Reassembles tool calls from the fragments of a streamed response
+///
+/// Streamed tool calls arrive as chunks keyed by `index`: the id and name usually come with
+/// the first chunk, while the arguments are split across many. Push every chunk, then
+/// `finish` once the stream is done to get the complete calls in index order.
+#[derive(Debug, Default)]
+pub struct ToolCallAccumulator {
+    calls: BTreeMap<u32, ToolCall>,
+}
+
+impl ToolCallAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge a chunk into the call at its index
+    pub fn push(&mut self, chunk: &ChatCompletionMessageToolCallChunk) {
+        let call = self.calls.entry(chunk.index).or_insert_with(|| ToolCall {
+            id: String::new(),
+            name: String::new(),
+            arguments: String::new(),
+        });
+
+        if let Some(id) = &chunk.id
+            && call.id.is_empty()
+        {
+            call.id = id.clone();
+        }
+        if let Some(function) = &chunk.function {
+            if let Some(name) = &function.name
+                && call.name.is_empty()
+            {
+                call.name = name.clone();
+            }
+            if let Some(arguments) = &function.arguments {
+                call.arguments.push_str(arguments);
+            }
+        }
+    }
+
+    /// Whether no tool call chunks have been pushed
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Get the complete tool calls, ordered by index
+    pub fn finish(self) -> Vec<ToolCall> {
+        self.calls.into_values().collect()
+    }
+}
+
+/// Build a user message's content, as an array of parts when it carries more than text
+fn user_content(msg: &Message) -> ChatCompletionRequestUserMessageContent {
+    if msg.parts.is_empty() {
+        return ChatCompletionRequestUserMessageContent::Text(msg.content.clone());
+    }
+    let text = (!msg.content.is_empty()).then(|| ContentPart::text(msg.content.clone()));
+    let parts = text
+        .iter()
+        .chain(&msg.parts)
+        .map(|part| match part {
+            ContentPart::Text { text } => ChatCompletionRequestUserMessageContentPart::Text(
+                ChatCompletionRequestMessageContentPartText { text: text.clone() },
+            ),
+            ContentPart::Image { url } => ChatCompletionRequestUserMessageContentPart::ImageUrl(
+                ChatCompletionRequestMessageContentPartImage {
+                    image_url: ImageUrl {
+                        url: url.clone(),
+                        detail: None,
+                    },
+                },
+            ),
+        })
+        .collect();
+    ChatCompletionRequestUserMessageContent::Array(parts)
+}
+
+/// Map messages to the request format of the chat completions API
+///
+/// Tool results are sent as tool messages when they answer a tool call and `api_mode` is
+/// [`ApiMode::Tools`], and as legacy function messages otherwise.
+pub(crate) fn request_messages(
+    messages: &[Message],
+    api_mode: ApiMode,
+) -> Vec<ChatCompletionRequestMessage> {
+    messages
+        .iter()
+        .filter(|msg| !msg.hidden)
+        .map(|msg| match msg.role.as_str() {
+            "system" => ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+                content: ChatCompletionRequestSystemMessageContent::Text(msg.content.clone()),
+                name: msg.tool_name.clone(),
+            }),
+            "developer" => {
+                ChatCompletionRequestMessage::Developer(ChatCompletionRequestDeveloperMessage {
+                    content: ChatCompletionRequestDeveloperMessageContent::Text(
+                        msg.content.clone(),
+                    ),
+                    name: msg.tool_name.clone(),
+                })
+            }
+            "assistant" => assistant_message(msg, api_mode),
+            // Tool results answering a tool call are sent as tool messages
+            "tool" => match &msg.tool_call_id {
+                Some(tool_call_id) if api_mode == ApiMode::Tools && !tool_call_id.is_empty() => {
+                    ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
+                        content: ChatCompletionRequestToolMessageContent::Text(msg.content.clone()),
+                        tool_call_id: tool_call_id.clone(),
+                    })
+                }
+                _ => ChatCompletionRequestMessage::Function(ChatCompletionRequestFunctionMessage {
+                    content: Some(msg.content.clone()),
+                    name: msg.tool_name.clone().unwrap_or_default(),
+                }),
+            },
+            _ => ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+                content: user_content(msg),
+                name: msg.tool_name.clone(),
+            }),
+        })
+        .collect()
 }
@@ -0,0 +1,112 @@
+use async_openai::types::{
+    CompletionUsage, CreateChatCompletionRequest, CreateChatCompletionResponse,
+};
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::{KeyValue, StringValue, Value};
+
+use crate::error::AgentError;
+
+/// Start a span for a chat completion call, following the GenAI semantic conventions
+///
+/// Spans go to the global tracer provider, so nothing is exported until the application
+/// installs one.
+pub(crate) fn start_chat_span(request: &CreateChatCompletionRequest) -> BoxedSpan {
+    let tracer = global::tracer("adk");
+    let mut attributes = vec![
+        KeyValue::new("gen_ai.system", "openai"),
+        KeyValue::new("gen_ai.operation.name", "chat"),
+        KeyValue::new("gen_ai.request.model", request.model.clone()),
+    ];
+    if let Some(temperature) = request.temperature {
+        attributes.push(KeyValue::new(
+            "gen_ai.request.temperature",
+            f64::from(temperature),
+        ));
+    }
+    if let Some(tools) = &request.tools {
+        let names: Vec<StringValue> = tools
+            .iter()
+            .map(|tool| tool.function.name.clone().into())
+            .collect();
+        attributes.push(KeyValue::new(
+            "gen_ai.request.tools",
+            Value::Array(names.into()),
+        ));
+    }
+
+    tracer
+        .span_builder(format!("chat {}", request.model))
+        .with_kind(SpanKind::Client)
+        .with_attributes(attributes)
+        .start(&tracer)
+}
+
+/// Record the response, including token usage, and end the span
+pub(crate) fn end_chat_span(
+    mut span: BoxedSpan,
+    response: Result<&CreateChatCompletionResponse, &AgentError>,
+) {
+    match response {
+        Ok(response) => {
+            span.set_attribute(KeyValue::new("gen_ai.response.id", response.id.clone()));
+            span.set_attribute(KeyValue::new(
+                "gen_ai.response.model",
+                response.model.clone(),
+            ));
+            let finish_reasons: Vec<StringValue> = response
+                .choices
+                .iter()
+                .filter_map(|choice| choice.finish_reason)
+                .map(|reason| {
+                    serde_json::to_value(reason)
+                        .ok()
+                        .and_then(|reason| reason.as_str().map(String::from))
+                        .unwrap_or_default()
+                        .into()
+                })
+                .collect();
+            span.set_attribute(KeyValue::new(
+                "gen_ai.response.finish_reasons",
+                Value::Array(finish_reasons.into()),
+            ));
+            if let Some(usage) = &response.usage {
+                record_usage(&mut span, usage);
+            }
+        }
+        Err(error) => record_error(&mut span, error),
+    }
+    span.end();
+}
+
+/// Record how a streamed chat completion ended, including token usage, and end the span
+pub(crate) fn end_stream_span(
+    mut span: BoxedSpan,
+    outcome: Result<Option<&CompletionUsage>, &AgentError>,
+) {
+    match outcome {
+        Ok(usage) => {
+            if let Some(usage) = usage {
+                record_usage(&mut span, usage);
+            }
+        }
+        Err(error) => record_error(&mut span, error),
+    }
+    span.end();
+}
+
+fn record_usage(span: &mut BoxedSpan, usage: &CompletionUsage) {
+    span.set_attribute(KeyValue::new(
+        "gen_ai.usage.input_tokens",
+        i64::from(usage.prompt_tokens),
+    ));
+    span.set_attribute(KeyValue::new(
+        "gen_ai.usage.output_tokens",
+        i64::from(usage.completion_tokens),
+    ));
+}
+
+fn record_error(span: &mut BoxedSpan, error: &AgentError) {
+    span.set_attribute(KeyValue::new("error.type", format!("{:?}", error.kind())));
+    span.set_status(Status::error(error.to_string()));
+}
@@ -0,0 +1,64 @@
+use crate::error::{AgentError, AgentResult};
+use crate::types::Context;
+
+/// A prompt with `{key}` placeholders filled in from a [`Context`]
+///
+/// String values are inserted as-is and other values as JSON. Braces that don't enclose a
+/// plain key (letters, digits, `_`, `-` or `.`), such as a JSON example, are left untouched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptTemplate {
+    template: String,
+}
+
+impl PromptTemplate {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+        }
+    }
+
+    /// Get the unrendered template
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Substitute every placeholder, failing with a `ContextError` if a key is missing
+    pub fn render(&self, context: &Context) -> AgentResult<String> {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let Some(key) = rest[1..]
+                .split_once('}')
+                .map(|(key, _)| key)
+                .filter(|key| is_key(key))
+            else {
+                rendered.push('{');
+                rest = &rest[1..];
+                continue;
+            };
+
+            let value = context.data.get(key).ok_or_else(|| {
+                AgentError::ContextError(format!("missing template variable: {}", key))
+            })?;
+            match value {
+                serde_json::Value::String(value) => rendered.push_str(value),
+                value => rendered.push_str(&value.to_string()),
+            }
+            rest = &rest[key.len() + 2..];
+        }
+
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
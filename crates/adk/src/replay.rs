@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+
+use crate::error::{AgentError, AgentResult};
+use crate::openai::{
+    Model, ModelParams, ModerationResult, Step, StepChunk, StepStream, generate_with_tools,
+};
+use crate::tool::Tool;
+use crate::types::RunContext;
+
+/// A model that passes calls through to another model and records its turns to a JSON file
+///
+/// The file is rewritten after every turn, so it is complete whenever a run ends. Load it
+/// with [`ReplayModel::from_file`] to replay the run without calling the real model.
+///
+/// `generate_response` is passed through to the wrapped model, and each of its turns is
+/// recorded as a step: the tool calls and any rejected answers it added to the context, then
+/// its response. A replay returns them in the same order.
+pub struct RecordingModel {
+    inner: Arc<dyn Model>,
+    recording: Arc<Recording>,
+}
+
+impl RecordingModel {
+    pub fn new(inner: Arc<dyn Model>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            recording: Arc::new(Recording {
+                path: path.into(),
+                steps: tokio::sync::Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Get the turns recorded so far
+    pub async fn steps(&self) -> Vec<Step> {
+        self.recording.steps.lock().await.clone()
+    }
+
+    async fn record(&self, step: Step) -> AgentResult<()> {
+        self.recording.record(step).await
+    }
+}
+
+/// The turns recorded so far and the file they are written to
+struct Recording {
+    path: PathBuf,
+    steps: tokio::sync::Mutex<Vec<Step>>,
+}
+
+impl Recording {
+    async fn record(&self, step: Step) -> AgentResult<()> {
+        let mut steps = self.steps.lock().await;
+        steps.push(step);
+        let json = serde_json::to_string_pretty(&*steps)?;
+        tokio::fs::write(&self.path, json).await.map_err(|e| {
+            AgentError::InternalError(format!(
+                "Failed to write recording to {}: {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+}
+
+/// A streamed turn being passed through, recorded as the step its chunks add up to once it ends
+struct RecordedStream {
+    stream: StepStream,
+    content: String,
+    recording: Arc<Recording>,
+}
+
+impl RecordedStream {
+    async fn next(state: Option<Self>) -> Option<(AgentResult<StepChunk>, Option<Self>)> {
+        let mut recorded = state?;
+        match recorded.stream.next().await {
+            Some(Ok(chunk)) => {
+                match &chunk {
+                    StepChunk::Content(delta) => recorded.content.push_str(delta),
+                    StepChunk::Usage(_) => {}
+                    // Tool calls are the last chunk, and callers stop reading once they have them
+                    StepChunk::ToolCalls(tool_calls) => {
+                        let step = Step::ToolCalls(tool_calls.clone());
+                        let result = recorded.recording.record(step).await.map(|()| chunk);
+                        return Some((result, None));
+                    }
+                }
+                Some((Ok(chunk), Some(recorded)))
+            }
+            Some(Err(error)) => Some((Err(error), None)),
+            None => {
+                let step = Step::Message(recorded.content);
+                match recorded.recording.record(step).await {
+                    Ok(()) => None,
+                    Err(error) => Some((Err(error), None)),
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Model for RecordingModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> AgentResult<String> {
+        let start = context.messages.len();
+        let response = self.inner.generate_response(context, tools).await;
+        // The turns before the response show in the assistant messages the model added
+        let turns: Vec<Step> = context.messages[start..]
+            .iter()
+            .filter(|message| message.role == "assistant")
+            .map(|message| {
+                if message.tool_calls.is_empty() {
+                    Step::Message(message.content.clone())
+                } else {
+                    Step::ToolCalls(message.tool_calls.clone())
+                }
+            })
+            .collect();
+        for step in turns {
+            self.record(step).await?;
+        }
+        let response = response?;
+        self.record(Step::Message(response.clone())).await?;
+        Ok(response)
+    }
+
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> AgentResult<Step> {
+        self.generate_step_with_params(context, tools, &ModelParams::default())
+            .await
+    }
+
+    async fn generate_step_with_params(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<Step> {
+        let step = self
+            .inner
+            .generate_step_with_params(context, tools, params)
+            .await?;
+        self.record(step.clone()).await?;
+        Ok(step)
+    }
+
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<StepStream> {
+        let stream = self.inner.stream_step(context, tools, params).await?;
+        let recorded = RecordedStream {
+            stream,
+            content: String::new(),
+            recording: self.recording.clone(),
+        };
+        Ok(futures::stream::unfold(Some(recorded), RecordedStream::next).boxed())
+    }
+
+    fn preview_request(
+        &self,
+        context: &RunContext,
+        tools: &[&dyn Tool],
+        params: &ModelParams,
+    ) -> AgentResult<serde_json::Value> {
+        self.inner.preview_request(context, tools, params)
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    async fn list_models(&self) -> AgentResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    async fn moderate(&self, text: &str) -> AgentResult<ModerationResult> {
+        self.inner.moderate(text).await
+    }
+}
+
+/// A model that plays back the turns captured by a [`RecordingModel`], in order
+///
+/// Fails with a `ModelError` once every recorded turn has been used.
+pub struct ReplayModel {
+    steps: Mutex<VecDeque<Step>>,
+}
+
+impl ReplayModel {
+    pub fn new(steps: Vec<Step>) -> Self {
+        Self {
+            steps: Mutex::new(steps.into()),
+        }
+    }
+
+    /// Load a recording written by a [`RecordingModel`]
+    pub fn from_file(path: impl Into<PathBuf>) -> AgentResult<Self> {
+        let path = path.into();
+        let json = std::fs::read_to_string(&path).map_err(|e| {
+            AgentError::ConfigurationError(format!(
+                "Failed to read recording from {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Ok(Self::new(serde_json::from_str(&json)?))
+    }
+
+    /// How many recorded turns haven't been played back yet
+    pub fn remaining(&self) -> usize {
+        self.steps.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl Model for ReplayModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> AgentResult<String> {
+        generate_with_tools(self, context, tools).await
+    }
+
+    async fn generate_step(
+        &self,
+        _context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> AgentResult<Step> {
+        self.steps
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| AgentError::ModelError("no more recorded responses".into()))
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Decides how long to wait before each retry of a failed request
+pub trait Backoff: Send {
+    /// The wait before retry number `attempt`, counting from 1
+    fn next_delay(&mut self, attempt: usize) -> Duration;
+}
+
+/// Waits the same time before every retry
+#[derive(Debug, Clone)]
+pub struct FixedBackoff {
+    delay: Duration,
+}
+
+impl FixedBackoff {
+    pub fn new(delay: Duration) -> Self {
+        Self { delay }
+    }
+}
+
+impl Backoff for FixedBackoff {
+    fn next_delay(&mut self, _attempt: usize) -> Duration {
+        self.delay
+    }
+}
+
+/// Waits `initial` before the first retry and twice as long before each further one
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    initial: Duration,
+    max_delay: Option<Duration>,
+}
+
+impl ExponentialBackoff {
+    pub fn new(initial: Duration) -> Self {
+        Self {
+            initial,
+            max_delay: None,
+        }
+    }
+
+    /// Never wait longer than `max_delay`
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    fn delay(&self, attempt: usize) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(31) as u32;
+        let delay = self.initial.saturating_mul(1 << doublings);
+        match self.max_delay {
+            Some(max_delay) => delay.min(max_delay),
+            None => delay,
+        }
+    }
+}
+
+impl Backoff for ExponentialBackoff {
+    fn next_delay(&mut self, attempt: usize) -> Duration {
+        self.delay(attempt)
+    }
+}
+
+/// Waits a random time between zero and the exponential delay, so clients that failed
+/// together don't all retry at the same moment
+#[derive(Debug, Clone)]
+pub struct JitteredBackoff {
+    exponential: ExponentialBackoff,
+    /// State of the random number generator
+    state: u64,
+}
+
+impl JitteredBackoff {
+    pub fn new(initial: Duration) -> Self {
+        Self {
+            exponential: ExponentialBackoff::new(initial),
+            state: RandomState::new().build_hasher().finish(),
+        }
+    }
+
+    /// Never wait longer than `max_delay`
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.exponential = self.exponential.with_max_delay(max_delay);
+        self
+    }
+
+    /// Seed the random waits, e.g. to make them repeatable in tests
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.state = seed;
+        self
+    }
+
+    /// A random number in `[0, 1)`, from the splitmix64 generator
+    fn next_fraction(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        // The top 53 bits fill an f64's mantissa exactly
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Backoff for JitteredBackoff {
+    fn next_delay(&mut self, attempt: usize) -> Duration {
+        let fraction = self.next_fraction();
+        self.exponential.delay(attempt).mul_f64(fraction)
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::types::RunContext;
+
+/// Storage for conversations that continue across runs, keyed by session id
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Get the session's run context, or `None` for a new session
+    async fn load(&self, session_id: &str) -> Option<RunContext>;
+
+    /// Store the session's run context, replacing any earlier one
+    async fn save(&self, session_id: &str, run_context: RunContext);
+}
+
+/// A session store that keeps sessions in memory for the life of the process
+#[derive(Debug, Default)]
+pub struct InMemorySessionStore {
+    sessions: Mutex<HashMap<String, RunContext>>,
+}
+
+impl InMemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for InMemorySessionStore {
+    async fn load(&self, session_id: &str) -> Option<RunContext> {
+        self.sessions.lock().unwrap().get(session_id).cloned()
+    }
+
+    async fn save(&self, session_id: &str, run_context: RunContext) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), run_context);
+    }
+}
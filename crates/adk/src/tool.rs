@@ -1,7 +1,19 @@
 use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::error::AgentResult;
-use crate::types::RunContext;
+use crate::error::{AgentError, AgentResult};
+use crate::types::{ContentPart, RunContext};
+
+pub mod fs;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 // Re-export the procedural macro
 pub use adk_macros::tool_fn;
@@ -13,6 +25,123 @@ pub struct ToolResult {
     pub tool_name: String,
     /// The output of the tool execution
     pub output: String,
+    /// The id of the tool call this result answers, when executed for a model
+    pub call_id: Option<String>,
+    /// The raw arguments the model sent, when executed for a model
+    pub arguments: Option<String>,
+    /// Whether the tool succeeded; a failed result's output is reported to the model as an error
+    pub success: bool,
+    /// Guidance for the model on what to do next, sent as an instruction after the turn's tool results
+    pub follow_up: Option<String>,
+    /// Images or other content besides the output, sent in a user message after the tool results
+    pub parts: Vec<ContentPart>,
+}
+
+impl Default for ToolResult {
+    fn default() -> Self {
+        Self {
+            tool_name: String::new(),
+            output: String::new(),
+            call_id: None,
+            arguments: None,
+            success: true,
+            follow_up: None,
+            parts: Vec::new(),
+        }
+    }
+}
+
+/// Appended to tool output that was cut short
+pub const TRUNCATION_MARKER: &str = "…[truncated]";
+
+/// Reported to the model in place of the output of a call that wasn't approved
+pub(crate) const TOOL_CALL_DENIED: &str = "tool call denied";
+
+impl ToolResult {
+    /// Cut the output to at most `max_chars` characters, marking it as truncated
+    pub fn truncate_output(&mut self, max_chars: usize) {
+        if let Some((end, _)) = self.output.char_indices().nth(max_chars) {
+            self.output.truncate(end);
+            self.output.push_str(TRUNCATION_MARKER);
+        }
+    }
+}
+
+/// Formats tool output before it's added to the conversation, e.g. as markdown or JSON
+pub trait OutputFormatter: Send + Sync {
+    /// Render the result as the text the model sees
+    fn format(&self, result: &ToolResult) -> String;
+}
+
+/// Passes tool output through unchanged
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityFormatter;
+
+impl OutputFormatter for IdentityFormatter {
+    fn format(&self, result: &ToolResult) -> String {
+        result.output.clone()
+    }
+}
+
+/// A tool call requested by a model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// The id the model assigned to this call
+    pub id: String,
+    /// The name of the tool to call
+    pub name: String,
+    /// The raw JSON arguments for the tool
+    pub arguments: String,
+}
+
+/// Latency of one tool's calls
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolStats {
+    /// How many times the tool was executed, whether or not it succeeded
+    pub count: usize,
+    /// Time spent in all of the calls together
+    pub total: Duration,
+    /// The slowest single call
+    pub max: Duration,
+}
+
+impl ToolStats {
+    /// The average time per call
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.count) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total / count,
+            Err(_) => self.total.div_f64(self.count as f64),
+        }
+    }
+}
+
+/// Per-tool latency, accumulated by an agent as it executes tools
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolMetrics {
+    stats: BTreeMap<String, ToolStats>,
+}
+
+impl ToolMetrics {
+    /// Record one call of the named tool
+    pub fn record(&mut self, tool_name: &str, duration: Duration) {
+        let stats = self.stats.entry(tool_name.to_string()).or_default();
+        stats.count += 1;
+        stats.total += duration;
+        stats.max = stats.max.max(duration);
+    }
+
+    /// Get the stats for a tool, if it has been called
+    pub fn get(&self, tool_name: &str) -> Option<&ToolStats> {
+        self.stats.get(tool_name)
+    }
+
+    /// Iterate over the stats of every tool called, ordered by tool name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ToolStats)> {
+        self.stats
+            .iter()
+            .map(|(name, stats)| (name.as_str(), stats))
+    }
 }
 
 /// Trait for tools that can be used by agents
@@ -27,10 +156,89 @@ pub trait Tool: Send + Sync {
     /// The JSON schema for the tool's parameters
     fn parameters_schema(&self) -> serde_json::Value;
 
+    /// Labels such as `read-only` or `network`, for choosing which tools an agent gets
+    fn tags(&self) -> &[String] {
+        &[]
+    }
+
+    /// The most characters of output to pass on to the model; longer output is truncated
+    fn max_output_chars(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether an agent must get each call approved before running it, e.g. for destructive tools
+    fn requires_approval(&self) -> bool {
+        false
+    }
+
+    /// Validate the parameters before the tool is executed
+    ///
+    /// Override this to reject inputs that are well-formed JSON but break the tool's own rules.
+    async fn validate(&self, _context: &RunContext, _params: &str) -> AgentResult<()> {
+        Ok(())
+    }
+
     /// Execute the tool with the given parameters
     async fn execute(&self, context: &mut RunContext, params: &str) -> AgentResult<ToolResult>;
 }
 
+/// Validate the parameters and, only if they are accepted, execute the tool
+pub async fn validate_and_execute(
+    tool: &dyn Tool,
+    context: &mut RunContext,
+    params: &str,
+) -> AgentResult<ToolResult> {
+    tool.validate(context, params).await?;
+    tool.execute(context, params).await
+}
+
+/// Execute a tool call requested by a model, recording the call id and arguments on the result
+///
+/// The output is truncated to the tool's `max_output_chars`, if it has one.
+pub async fn execute_tool_call(
+    tool: &dyn Tool,
+    context: &mut RunContext,
+    tool_call: &ToolCall,
+) -> AgentResult<ToolResult> {
+    let mut result = validate_and_execute(tool, context, &tool_call.arguments).await?;
+    if let Some(max_chars) = tool.max_output_chars() {
+        result.truncate_output(max_chars);
+    }
+    result.call_id = Some(tool_call.id.clone());
+    result.arguments = Some(tool_call.arguments.clone());
+    Ok(result)
+}
+
+/// Deserialize a tool's JSON arguments into a typed value
+///
+/// Errors are reported as `InvalidInput` so they can be fed back to the model.
+pub fn parse_params<T: DeserializeOwned>(params: &str) -> AgentResult<T> {
+    serde_json::from_str(params)
+        .map_err(|e| AgentError::InvalidInput(format!("Invalid tool arguments: {}", e)))
+}
+
+/// Generate a tool parameter schema from a type deriving `schemars::JsonSchema`
+///
+/// The `$schema` and `title` keys are dropped, as the API doesn't use them.
+#[cfg(feature = "schemars")]
+pub fn schema_for<T: schemars::JsonSchema>() -> serde_json::Value {
+    let mut schema = schemars::schema_for!(T).to_value();
+    if let Some(schema) = schema.as_object_mut() {
+        schema.remove("$schema");
+        schema.remove("title");
+    }
+    schema
+}
+
+/// Find a tool by name among the tools offered to the model
+pub(crate) fn find_tool<'a>(tools: &[&'a dyn Tool], name: &str) -> AgentResult<&'a dyn Tool> {
+    tools
+        .iter()
+        .find(|tool| tool.name() == name)
+        .copied()
+        .ok_or_else(|| AgentError::ToolNotFound(name.to_string()))
+}
+
 type FunctionToolFn = Box<dyn Fn(&mut RunContext, &str) -> AgentResult<ToolResult> + Send + Sync>;
 
 /// A function-based tool implementation
@@ -39,6 +247,8 @@ pub struct FunctionTool {
     description: String,
     parameters_schema: serde_json::Value,
     function: FunctionToolFn,
+    tags: Vec<String>,
+    requires_approval: bool,
 }
 
 impl FunctionTool {
@@ -53,8 +263,43 @@ impl FunctionTool {
             description: description.into(),
             parameters_schema,
             function,
+            tags: Vec::new(),
+            requires_approval: false,
         }
     }
+
+    /// Create a tool whose parameter schema is generated from the type `T`
+    #[cfg(feature = "schemars")]
+    pub fn for_params<T: schemars::JsonSchema>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        function: FunctionToolFn,
+    ) -> Self {
+        Self::new(name, description, schema_for::<T>(), function)
+    }
+
+    /// Label the tool, e.g. as `read-only` or `destructive`
+    pub fn with_tags(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Require each call to be approved before it runs
+    pub fn with_approval_required(mut self) -> Self {
+        self.requires_approval = true;
+        self
+    }
+}
+
+impl std::fmt::Debug for FunctionTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FunctionTool")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("tags", &self.tags)
+            .field("requires_approval", &self.requires_approval)
+            .finish_non_exhaustive()
+    }
 }
 
 #[async_trait]
@@ -71,11 +316,387 @@ impl Tool for FunctionTool {
         self.parameters_schema.clone()
     }
 
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    fn requires_approval(&self) -> bool {
+        self.requires_approval
+    }
+
     async fn execute(&self, context: &mut RunContext, params: &str) -> AgentResult<ToolResult> {
         (self.function)(context, params)
     }
 }
 
+type AsyncFunctionToolFn = Box<
+    dyn for<'a> Fn(&'a mut RunContext, &'a str) -> BoxFuture<'a, AgentResult<ToolResult>>
+        + Send
+        + Sync,
+>;
+
+/// A function-based tool whose body is async, for tools that do I/O
+///
+/// The function returns a boxed future, so bodies are written as
+/// `|context, params| Box::pin(async move { ... })`.
+pub struct AsyncFunctionTool {
+    name: String,
+    description: String,
+    parameters_schema: serde_json::Value,
+    function: AsyncFunctionToolFn,
+    tags: Vec<String>,
+    requires_approval: bool,
+}
+
+impl AsyncFunctionTool {
+    pub fn new<F>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters_schema: serde_json::Value,
+        function: F,
+    ) -> Self
+    where
+        F: for<'a> Fn(&'a mut RunContext, &'a str) -> BoxFuture<'a, AgentResult<ToolResult>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters_schema,
+            function: Box::new(function),
+            tags: Vec::new(),
+            requires_approval: false,
+        }
+    }
+
+    /// Label the tool, e.g. as `read-only` or `destructive`
+    pub fn with_tags(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Require each call to be approved before it runs
+    pub fn with_approval_required(mut self) -> Self {
+        self.requires_approval = true;
+        self
+    }
+}
+
+impl std::fmt::Debug for AsyncFunctionTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncFunctionTool")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("tags", &self.tags)
+            .field("requires_approval", &self.requires_approval)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Tool for AsyncFunctionTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.parameters_schema.clone()
+    }
+
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    fn requires_approval(&self) -> bool {
+        self.requires_approval
+    }
+
+    async fn execute(&self, context: &mut RunContext, params: &str) -> AgentResult<ToolResult> {
+        (self.function)(context, params).await
+    }
+}
+
+/// A reusable bundle of tools to give to several agents
+///
+/// Tool names are unique within a set: adding a different tool under a name that is already
+/// taken returns a `ConfigurationError`. Adding a tool the set already holds does nothing.
+#[derive(Clone, Default)]
+pub struct ToolSet {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a set from tools whose names are all different
+    pub fn from_tools(tools: impl IntoIterator<Item = Arc<dyn Tool>>) -> AgentResult<Self> {
+        tools.into_iter().try_fold(Self::new(), Self::with_tool)
+    }
+
+    /// Add a tool, unless the set already holds it
+    ///
+    /// Returns a `ConfigurationError` if the set has a different tool with the same name.
+    pub fn with_tool(mut self, tool: Arc<dyn Tool>) -> AgentResult<Self> {
+        match self.get(tool.name()) {
+            Some(existing) if Arc::ptr_eq(existing, &tool) => {}
+            Some(_) => {
+                return Err(AgentError::ConfigurationError(format!(
+                    "Duplicate tool name: {}",
+                    tool.name()
+                )));
+            }
+            None => self.tools.push(tool),
+        }
+        Ok(self)
+    }
+
+    /// Combine two sets into their union
+    ///
+    /// Returns a `ConfigurationError` if the sets have different tools with the same name.
+    pub fn merge(self, other: ToolSet) -> AgentResult<Self> {
+        other.tools.into_iter().try_fold(self, Self::with_tool)
+    }
+
+    /// Check whether the set has a tool with this name
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Look up a tool by name
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn Tool>> {
+        self.tools.iter().find(|tool| tool.name() == name)
+    }
+
+    /// Get the tools in the order they were added
+    pub fn tools(&self) -> &[Arc<dyn Tool>] {
+        &self.tools
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+}
+
+impl IntoIterator for ToolSet {
+    type Item = Arc<dyn Tool>;
+    type IntoIter = std::vec::IntoIter<Arc<dyn Tool>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tools.into_iter()
+    }
+}
+
+/// A tool that runs other tools in sequence, feeding each output to the next tool as params
+///
+/// The chain takes the parameters of its first tool and returns the output of its last one.
+pub struct ChainedTool {
+    name: String,
+    description: String,
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ChainedTool {
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        tools: Vec<Arc<dyn Tool>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            tools,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ChainedTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        match self.tools.first() {
+            Some(tool) => tool.parameters_schema(),
+            None => serde_json::json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        }
+    }
+
+    fn requires_approval(&self) -> bool {
+        self.tools.iter().any(|tool| tool.requires_approval())
+    }
+
+    async fn execute(&self, context: &mut RunContext, params: &str) -> AgentResult<ToolResult> {
+        let mut output = params.to_string();
+        for tool in &self.tools {
+            output = validate_and_execute(tool.as_ref(), context, &output)
+                .await?
+                .output;
+        }
+
+        Ok(ToolResult {
+            tool_name: self.name.clone(),
+            output,
+            ..Default::default()
+        })
+    }
+}
+
+/// A tool that remembers another tool's results, keyed by the exact argument string
+///
+/// Only successful results are cached, so errors and failed results are retried on the next
+/// call. Suited to pure tools whose calls are slow or costly, such as web lookups.
+///
+/// Expired results are dropped whenever a new result is cached. Without a TTL or
+/// `with_max_entries`, the cache keeps every distinct call's result.
+pub struct CachedTool {
+    tool: Arc<dyn Tool>,
+    ttl: Option<Duration>,
+    max_entries: Option<usize>,
+    cache: Mutex<HashMap<String, (ToolResult, Instant)>>,
+}
+
+impl CachedTool {
+    pub fn new(tool: Arc<dyn Tool>) -> Self {
+        Self {
+            tool,
+            ttl: None,
+            max_entries: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run the tool again once a cached result is older than `ttl`
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Keep at most `max_entries` results, dropping the oldest to make room; zero caches nothing
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Drop every cached result
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// How many results are cached
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn cached(&self, params: &str) -> Option<ToolResult> {
+        let mut cache = self.cache.lock().unwrap();
+        let (result, cached_at) = cache.get(params)?;
+        if self.ttl.is_some_and(|ttl| cached_at.elapsed() >= ttl) {
+            cache.remove(params);
+            return None;
+        }
+        Some(result.clone())
+    }
+
+    fn store(&self, params: &str, result: ToolResult) {
+        if self.max_entries == Some(0) {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(ttl) = self.ttl {
+            cache.retain(|_, (_, cached_at)| cached_at.elapsed() < ttl);
+        }
+        if let Some(max_entries) = self.max_entries
+            && cache.len() >= max_entries
+            && !cache.contains_key(params)
+            && let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, (_, cached_at))| *cached_at)
+                .map(|(key, _)| key.clone())
+        {
+            cache.remove(&oldest);
+        }
+        cache.insert(params.to_string(), (result, Instant::now()));
+    }
+}
+
+impl std::fmt::Debug for CachedTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachedTool")
+            .field("name", &self.tool.name())
+            .field("ttl", &self.ttl)
+            .field("max_entries", &self.max_entries)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Tool for CachedTool {
+    fn name(&self) -> &str {
+        self.tool.name()
+    }
+
+    fn description(&self) -> &str {
+        self.tool.description()
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.tool.parameters_schema()
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tool.tags()
+    }
+
+    fn max_output_chars(&self) -> Option<usize> {
+        self.tool.max_output_chars()
+    }
+
+    fn requires_approval(&self) -> bool {
+        self.tool.requires_approval()
+    }
+
+    async fn validate(&self, context: &RunContext, params: &str) -> AgentResult<()> {
+        self.tool.validate(context, params).await
+    }
+
+    async fn execute(&self, context: &mut RunContext, params: &str) -> AgentResult<ToolResult> {
+        if let Some(result) = self.cached(params) {
+            return Ok(result);
+        }
+
+        let result = self.tool.execute(context, params).await?;
+        if result.success {
+            self.store(params, result.clone());
+        }
+        Ok(result)
+    }
+}
+
 /// A macro to create a function tool with automatic parameter schema generation
 #[macro_export]
 macro_rules! function_tool {
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::error::{AgentError, AgentResult};
+use crate::tool::{Tool, ToolResult, parse_params};
+use crate::types::RunContext;
+
+/// A tool that reads, writes and lists files, confined to a root directory
+///
+/// Paths are relative to the root. Every path is canonicalized and rejected unless it stays
+/// inside the root, so neither `..` nor symlinks can escape it.
+pub struct FileSystemTool {
+    root: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "operation", rename_all = "lowercase")]
+enum Operation {
+    Read { path: String },
+    Write { path: String, content: String },
+    List { path: Option<String> },
+}
+
+impl FileSystemTool {
+    /// Create a tool confined to `root`, which must be an existing directory
+    pub fn new(root: impl AsRef<Path>) -> AgentResult<Self> {
+        let root = root.as_ref();
+        let root = root.canonicalize().map_err(|e| {
+            AgentError::ConfigurationError(format!("Invalid root {}: {}", root.display(), e))
+        })?;
+        Ok(Self { root })
+    }
+
+    /// Get the canonical root directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve an existing path, rejecting anything outside the root
+    fn resolve(&self, path: &str) -> AgentResult<PathBuf> {
+        let resolved = self
+            .root
+            .join(path)
+            .canonicalize()
+            .map_err(|e| AgentError::ToolError(format!("Invalid path {}: {}", path, e)))?;
+        self.check_inside_root(path, resolved)
+    }
+
+    /// Resolve a path that may not exist yet by resolving its parent directory
+    fn resolve_new(&self, path: &str) -> AgentResult<PathBuf> {
+        let joined = self.root.join(path);
+        // Anything already there may be a symlink pointing elsewhere, and `exists` would
+        // report a dangling one as missing, so only a path with no entry at all is new
+        if joined.symlink_metadata().is_ok() {
+            return self.resolve(path);
+        }
+        let (Some(parent), Some(file_name)) = (joined.parent(), joined.file_name()) else {
+            return Err(AgentError::ToolError(format!("Invalid path {}", path)));
+        };
+        let parent = parent
+            .canonicalize()
+            .map_err(|e| AgentError::ToolError(format!("Invalid path {}: {}", path, e)))?;
+        self.check_inside_root(path, parent.join(file_name))
+    }
+
+    fn check_inside_root(&self, path: &str, resolved: PathBuf) -> AgentResult<PathBuf> {
+        if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else {
+            Err(AgentError::ToolError(format!(
+                "Path {} is outside the root directory",
+                path
+            )))
+        }
+    }
+
+    async fn list(&self, path: &str) -> AgentResult<String> {
+        let dir = self.resolve(path)?;
+        let io_error = |e: std::io::Error| {
+            AgentError::tool_error(format!("Failed to list {}: {}", path, e), e)
+        };
+
+        let mut entries = tokio::fs::read_dir(&dir).await.map_err(io_error)?;
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().await.map_err(io_error)?.is_dir() {
+                name.push('/');
+            }
+            names.push(name);
+        }
+        names.sort();
+        Ok(names.join("\n"))
+    }
+}
+
+#[async_trait]
+impl Tool for FileSystemTool {
+    fn name(&self) -> &str {
+        "file_system"
+    }
+
+    fn description(&self) -> &str {
+        "Reads, writes and lists files in the working directory"
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "operation": {
+                    "type": "string",
+                    "enum": ["read", "write", "list"],
+                    "description": "What to do with the path"
+                },
+                "path": {
+                    "type": "string",
+                    "description": "Path relative to the working directory"
+                },
+                "content": {
+                    "type": "string",
+                    "description": "The file content to write"
+                }
+            },
+            "required": ["operation"]
+        })
+    }
+
+    async fn execute(&self, _context: &mut RunContext, params: &str) -> AgentResult<ToolResult> {
+        let output = match parse_params(params)? {
+            Operation::Read { path } => {
+                let file = self.resolve(&path)?;
+                tokio::fs::read_to_string(&file).await.map_err(|e| {
+                    AgentError::tool_error(format!("Failed to read {}: {}", path, e), e)
+                })?
+            }
+            Operation::Write { path, content } => {
+                let file = self.resolve_new(&path)?;
+                tokio::fs::write(&file, &content).await.map_err(|e| {
+                    AgentError::tool_error(format!("Failed to write {}: {}", path, e), e)
+                })?;
+                format!("Wrote {} bytes to {}", content.len(), path)
+            }
+            Operation::List { path } => self.list(path.as_deref().unwrap_or(".")).await?,
+        };
+
+        Ok(ToolResult {
+            tool_name: self.name().to_string(),
+            output,
+            ..Default::default()
+        })
+    }
+}
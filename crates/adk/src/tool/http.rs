@@ -0,0 +1,155 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::{Map, Value};
+
+use crate::error::{AgentError, AgentResult};
+use crate::tool::{Tool, ToolResult, parse_params};
+use crate::types::RunContext;
+
+pub use reqwest::Method;
+
+/// A tool that calls an HTTP endpoint and returns the response body
+///
+/// Arguments named in the URL template (e.g. `{id}` in `https://api.example.com/users/{id}`)
+/// are filled into the URL, each as a single path segment; a missing one, or a `.` or `..`
+/// that would move the request to another path, is an `InvalidInput` error. The remaining arguments are sent as query parameters for `GET`,
+/// `HEAD` and `DELETE` requests, and as a JSON body otherwise.
+pub struct HttpTool {
+    name: String,
+    description: String,
+    method: Method,
+    url_template: String,
+    parameters_schema: Value,
+    client: Client,
+}
+
+impl HttpTool {
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        method: Method,
+        url_template: impl Into<String>,
+        parameters_schema: Value,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            method,
+            url_template: url_template.into(),
+            parameters_schema,
+            client: Client::new(),
+        }
+    }
+
+    /// Use a preconfigured client, e.g. with default headers or timeouts
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Build the request for the given tool arguments
+    fn build_request(&self, params: &str) -> AgentResult<RequestBuilder> {
+        let mut args: Map<String, Value> = parse_params(params)?;
+
+        let mut url = self.url_template.clone();
+        for name in placeholders(&self.url_template) {
+            let placeholder = format!("{{{}}}", name);
+            // Filled in already, if the template names it more than once
+            if !url.contains(&placeholder) {
+                continue;
+            }
+            let value = args.remove(name).ok_or_else(|| {
+                AgentError::InvalidInput(format!("Missing URL argument: {}", name))
+            })?;
+            let value = value_to_string(&value);
+            if value == "." || value == ".." {
+                return Err(AgentError::InvalidInput(format!(
+                    "Invalid URL argument {}: {:?}",
+                    name, value
+                )));
+            }
+            url = url.replace(&placeholder, &encode_path_segment(&value));
+        }
+
+        let request = self.client.request(self.method.clone(), url);
+        if args.is_empty() {
+            return Ok(request);
+        }
+        if matches!(self.method, Method::GET | Method::HEAD | Method::DELETE) {
+            let query: Vec<(String, String)> = args
+                .iter()
+                .map(|(key, value)| (key.clone(), value_to_string(value)))
+                .collect();
+            Ok(request.query(&query))
+        } else {
+            Ok(request.json(&args))
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for HttpTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters_schema(&self) -> Value {
+        self.parameters_schema.clone()
+    }
+
+    async fn execute(&self, _context: &mut RunContext, params: &str) -> AgentResult<ToolResult> {
+        let response = self
+            .build_request(params)?
+            .send()
+            .await
+            .map_err(|e| AgentError::tool_error(format!("HTTP request failed: {}", e), e))?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(|e| {
+            AgentError::tool_error(format!("Failed to read HTTP response: {}", e), e)
+        })?;
+        if !status.is_success() {
+            return Err(AgentError::ToolError(format!("HTTP {}: {}", status, body)));
+        }
+
+        Ok(ToolResult {
+            tool_name: self.name.clone(),
+            output: body,
+            ..Default::default()
+        })
+    }
+}
+
+/// The names of the `{placeholder}`s in a URL template
+fn placeholders(template: &str) -> Vec<&str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .collect()
+}
+
+/// Strings are used as is, other JSON values in their JSON form
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Percent-encode everything but unreserved characters so a value stays one path segment
+fn encode_path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
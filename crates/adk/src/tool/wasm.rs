@@ -0,0 +1,209 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::error::{AgentError, AgentResult};
+use crate::tool::{Tool, ToolResult};
+use crate::types::RunContext;
+
+/// A tool implemented by a sandboxed WebAssembly module
+///
+/// The module must not import anything and must export:
+///
+/// - `memory`: its linear memory
+/// - `alloc(len: i32) -> i32`: reserve `len` bytes for the host to write the arguments into
+/// - `schema() -> i64`: the JSON parameter schema
+/// - `execute(ptr: i32, len: i32) -> i64`: run the tool on the JSON arguments at `ptr` and
+///   return its output
+///
+/// Strings returned to the host are UTF-8, packed as `(ptr << 32) | len`. Every call runs in a
+/// fresh instance, so no state carries over between calls.
+///
+/// Each call gets a fixed amount of fuel and memory, so a module that loops forever or keeps
+/// allocating fails with a `ToolError` instead of hanging or exhausting the host. Calls run on
+/// tokio's blocking thread pool, off the async workers.
+pub struct WasmTool {
+    name: String,
+    description: String,
+    parameters_schema: Value,
+    engine: Engine,
+    module: Module,
+    limits: Limits,
+}
+
+/// What a single call may use
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    fuel: u64,
+    max_memory: usize,
+}
+
+/// Fuel for a call by default, roughly one unit per WebAssembly instruction
+pub const DEFAULT_FUEL: u64 = 1_000_000_000;
+
+/// Memory a call may use by default, in bytes
+pub const DEFAULT_MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+impl WasmTool {
+    /// Load a tool from a compiled WebAssembly module
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        wasm: &[u8],
+    ) -> AgentResult<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| {
+            AgentError::ConfigurationError(format!("Failed to set up WASM engine: {:#}", e))
+        })?;
+        let module = Module::new(&engine, wasm)
+            .map_err(|e| AgentError::ConfigurationError(format!("Invalid WASM module: {:#}", e)))?;
+
+        let limits = Limits {
+            fuel: DEFAULT_FUEL,
+            max_memory: DEFAULT_MAX_MEMORY,
+        };
+        let schema = limited_store(&engine, limits)
+            .and_then(|mut store| {
+                let (instance, memory) = instantiate(&mut store, &module)?;
+                let schema = instance.get_typed_func::<(), i64>(&mut store, "schema")?;
+                let packed = schema.call(&mut store, ())?;
+                read_string(&store, memory, packed)
+            })
+            .map_err(|e| {
+                AgentError::ConfigurationError(format!("Failed to read WASM tool schema: {:#}", e))
+            })?;
+        let parameters_schema = serde_json::from_str(&schema).map_err(|e| {
+            AgentError::ConfigurationError(format!("Invalid WASM tool schema: {}", e))
+        })?;
+
+        Ok(Self {
+            name: name.into(),
+            description: description.into(),
+            parameters_schema,
+            engine,
+            module,
+            limits,
+        })
+    }
+
+    /// Give each call `fuel` units to run on instead of [`DEFAULT_FUEL`]
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.limits.fuel = fuel;
+        self
+    }
+
+    /// Let each call use up to `bytes` of memory instead of [`DEFAULT_MAX_MEMORY`]
+    pub fn with_max_memory(mut self, bytes: usize) -> Self {
+        self.limits.max_memory = bytes;
+        self
+    }
+
+    /// Load a tool from a `.wasm` file
+    pub fn from_file(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        path: impl AsRef<Path>,
+    ) -> AgentResult<Self> {
+        let path = path.as_ref();
+        let wasm = std::fs::read(path).map_err(|e| {
+            AgentError::ConfigurationError(format!(
+                "Failed to read WASM module from {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::new(name, description, &wasm)
+    }
+}
+
+/// A store that holds a single call to `limits`
+fn limited_store(engine: &Engine, limits: Limits) -> wasmtime::Result<Store<StoreLimits>> {
+    let store_limits = StoreLimitsBuilder::new()
+        .memory_size(limits.max_memory)
+        .build();
+    let mut store = Store::new(engine, store_limits);
+    store.limiter(|store_limits| store_limits);
+    store.set_fuel(limits.fuel)?;
+    Ok(store)
+}
+
+fn instantiate(
+    store: &mut Store<StoreLimits>,
+    module: &Module,
+) -> wasmtime::Result<(Instance, Memory)> {
+    let instance = Instance::new(&mut *store, module, &[])?;
+    let memory = instance
+        .get_memory(&mut *store, "memory")
+        .ok_or_else(|| wasmtime::Error::msg("module doesn't export `memory`"))?;
+    Ok((instance, memory))
+}
+
+/// Run the module's `execute` on `params` in a fresh instance
+fn call(
+    engine: &Engine,
+    module: &Module,
+    limits: Limits,
+    params: &str,
+) -> wasmtime::Result<String> {
+    let mut store = limited_store(engine, limits)?;
+    let (instance, memory) = instantiate(&mut store, module)?;
+    let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+    let execute = instance.get_typed_func::<(i32, i32), i64>(&mut store, "execute")?;
+
+    let len = i32::try_from(params.len())?;
+    let ptr = alloc.call(&mut store, len)?;
+    memory.write(&mut store, ptr as u32 as usize, params.as_bytes())?;
+    let packed = execute.call(&mut store, (ptr, len))?;
+    read_string(&store, memory, packed)
+}
+
+/// Read a UTF-8 string the module packed as `(ptr << 32) | len`
+fn read_string(
+    store: &Store<StoreLimits>,
+    memory: Memory,
+    packed: i64,
+) -> wasmtime::Result<String> {
+    let ptr = (packed as u64 >> 32) as usize;
+    let len = (packed as u64 & 0xffff_ffff) as usize;
+    let bytes = memory
+        .data(store)
+        .get(ptr..ptr + len)
+        .ok_or_else(|| wasmtime::Error::msg("returned string is out of bounds"))?;
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+#[async_trait]
+impl Tool for WasmTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters_schema(&self) -> Value {
+        self.parameters_schema.clone()
+    }
+
+    async fn execute(&self, _context: &mut RunContext, params: &str) -> AgentResult<ToolResult> {
+        // Engines and modules are reference counted, so these clones are cheap
+        let (engine, module, limits) = (self.engine.clone(), self.module.clone(), self.limits);
+        let params = params.to_string();
+        let output = tokio::task::spawn_blocking(move || call(&engine, &module, limits, &params))
+            .await
+            .map_err(|e| AgentError::tool_error(format!("WASM tool {} panicked", self.name), e))?
+            .map_err(|e| {
+                AgentError::tool_error(format!("WASM tool {} failed: {:#}", self.name, e), e)
+            })?;
+
+        Ok(ToolResult {
+            tool_name: self.name.clone(),
+            output,
+            ..Default::default()
+        })
+    }
+}
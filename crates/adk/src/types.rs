@@ -1,5 +1,12 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use crate::error::{AgentError, AgentResult};
+use crate::openai::{ApiMode, request_messages};
+use crate::tool::{ToolCall, ToolResult};
 
 /// Represents a generic context that can be used by agents and tools
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +29,114 @@ impl Context {
         }
         self
     }
+
+    /// Get a value that must be present in the context
+    ///
+    /// Returns a `ContextError` if the key is missing or holds a value of the wrong type.
+    pub fn require<T: DeserializeOwned>(&self, key: &str) -> AgentResult<T> {
+        let value = self.data.get(key).ok_or_else(|| {
+            AgentError::ContextError(format!("missing required context key: {}", key))
+        })?;
+        serde_json::from_value(value.clone()).map_err(|e| {
+            AgentError::ContextError(format!("invalid value for context key {}: {}", key, e))
+        })
+    }
+
+    /// Copy every key of `other` into this context, overwriting values already here
+    ///
+    /// Keys are merged at the top level only; a value that is an object replaces the old one
+    /// rather than being merged into it.
+    pub fn merge(&mut self, other: &Context) {
+        for (key, value) in &other.data {
+            self.data.insert(key.clone(), value.clone());
+        }
+    }
+
+    /// Report the keys whose values differ in `other`, as `(value here, value in other)`
+    ///
+    /// A key added in `other` has no value here, and a removed key has none in `other`.
+    /// Unchanged keys are left out.
+    pub fn diff(
+        &self,
+        other: &Context,
+    ) -> HashMap<String, (Option<serde_json::Value>, Option<serde_json::Value>)> {
+        self.data
+            .keys()
+            .chain(other.data.keys())
+            .filter_map(|key| {
+                let before = self.data.get(key);
+                let after = other.data.get(key);
+                (before != after).then(|| (key.clone(), (before.cloned(), after.cloned())))
+            })
+            .collect()
+    }
+
+    /// Load the typed state stored in the context, or `S::default()` if there is none yet
+    ///
+    /// Changes are written back with `TypedContext::save`. Returns a `ContextError` if the
+    /// stored state doesn't deserialize as `S`.
+    pub fn typed_state<S>(&mut self) -> AgentResult<TypedContext<'_, S>>
+    where
+        S: Serialize + DeserializeOwned + Default,
+    {
+        let state = match self.data.get(TYPED_STATE_KEY) {
+            Some(_) => self.require(TYPED_STATE_KEY)?,
+            None => S::default(),
+        };
+        Ok(TypedContext {
+            context: self,
+            state,
+        })
+    }
+}
+
+/// The context key typed state is stored under
+pub const TYPED_STATE_KEY: &str = "__typed_state";
+
+/// A strongly-typed state struct loaded from a context, for state too large to keep by key
+///
+/// Dereferences to the state. Edits stay local until `save` writes the state back under
+/// [`TYPED_STATE_KEY`].
+pub struct TypedContext<'a, S> {
+    context: &'a mut Context,
+    state: S,
+}
+
+impl<S: Serialize> TypedContext<'_, S> {
+    /// Write the state back to the context
+    ///
+    /// Returns a `ContextError` if the state can't be serialized.
+    pub fn save(self) -> AgentResult<()> {
+        let value = serde_json::to_value(&self.state)
+            .map_err(|e| AgentError::ContextError(format!("invalid typed state: {}", e)))?;
+        self.context.data.insert(TYPED_STATE_KEY.to_string(), value);
+        Ok(())
+    }
+
+    /// Take the state without saving it
+    pub fn into_inner(self) -> S {
+        self.state
+    }
+}
+
+impl<S> Deref for TypedContext<'_, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.state
+    }
+}
+
+impl<S> DerefMut for TypedContext<'_, S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for TypedContext<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TypedContext").field(&self.state).finish()
+    }
 }
 
 /// Represents the context for a single run of an agent
@@ -31,17 +146,134 @@ pub struct RunContext {
     pub context: Context,
     /// Messages exchanged during the run
     pub messages: Vec<Message>,
+    /// Reasoning the model returned with its latest turn, kept apart from the answer
+    pub reasoning: Option<String>,
+    /// Tokens the model reported using so far, prompt and completion together
+    pub tokens_used: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
-    /// The role of the message sender (system, user, assistant, tool)
+    /// The role of the message sender (system, developer, user, assistant, tool)
     pub role: String,
     /// The content of the message
     pub content: String,
     /// Optional name of the tool that generated this message
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_name: Option<String>,
+    /// Optional id of the tool call this message answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Tools an assistant message asked to call, answered by the tool messages that follow it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Kept in the run context but never sent to the model
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+    /// Part of a few-shot example rather than the real conversation
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub example: bool,
+    /// Images and other content sent after the text; only user messages carry them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<ContentPart>,
+}
+
+/// A piece of message content besides plain text, such as an image for a vision model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// A block of text
+    Text { text: String },
+    /// An image, by URL or as a base64 `data:` URL
+    Image { url: String },
+}
+
+impl ContentPart {
+    /// Create a text part
+    pub fn text(text: impl Into<String>) -> Self {
+        ContentPart::Text { text: text.into() }
+    }
+
+    /// Create an image part from a URL
+    pub fn image_url(url: impl Into<String>) -> Self {
+        ContentPart::Image { url: url.into() }
+    }
+
+    /// Create an image part from base64-encoded data of the given media type, e.g. `image/png`
+    pub fn image_base64(media_type: &str, data: &str) -> Self {
+        ContentPart::Image {
+            url: format!("data:{};base64,{}", media_type, data),
+        }
+    }
+}
+
+impl Message {
+    /// Create a system message
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::with_role("system", content)
+    }
+
+    /// Create a developer message, which newer models prefer to system messages
+    pub fn developer(content: impl Into<String>) -> Self {
+        Self::with_role("developer", content)
+    }
+
+    /// Create a user message
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::with_role("user", content)
+    }
+
+    /// Create an assistant message
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::with_role("assistant", content)
+    }
+
+    /// Create an assistant message requesting tool calls
+    pub fn assistant_tool_calls(tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls,
+            ..Self::with_role("assistant", "")
+        }
+    }
+
+    /// Create a user message with the content a tool returned besides its text output
+    ///
+    /// Tool messages can only hold text, so images from a tool are passed on this way.
+    pub fn tool_content(tool_name: &str, parts: Vec<ContentPart>) -> Self {
+        Self {
+            parts,
+            ..Self::user(format!("Content returned by the {} tool:", tool_name))
+        }
+    }
+
+    /// Create a tool message
+    pub fn tool(tool_name: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            tool_name: Some(tool_name.into()),
+            ..Self::with_role("tool", content)
+        }
+    }
+
+    fn with_role(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A plain string is a user message
+impl From<&str> for Message {
+    fn from(content: &str) -> Self {
+        Self::user(content)
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.role, self.content)
+    }
 }
 
 impl RunContext {
@@ -49,14 +281,31 @@ impl RunContext {
         Self {
             context,
             messages: Vec::new(),
+            reasoning: None,
+            tokens_used: 0,
         }
     }
 
+    /// Start building a run context with seeded messages
+    pub fn builder() -> RunContextBuilder {
+        RunContextBuilder::new()
+    }
+
     pub fn add_message(&mut self, role: impl Into<String>, content: impl Into<String>) {
         self.messages.push(Message {
             role: role.into(),
             content: content.into(),
-            tool_name: None,
+            ..Default::default()
+        });
+    }
+
+    /// Add a message for bookkeeping that stays in the context but isn't sent to the model
+    pub fn add_hidden_message(&mut self, role: impl Into<String>, content: impl Into<String>) {
+        self.messages.push(Message {
+            role: role.into(),
+            content: content.into(),
+            hidden: true,
+            ..Default::default()
         });
     }
 
@@ -65,6 +314,163 @@ impl RunContext {
             role: "tool".into(),
             content: content.into(),
             tool_name: Some(tool_name.into()),
+            ..Default::default()
+        });
+    }
+
+    /// The most recent message, if any
+    pub fn last_message(&self) -> Option<&Message> {
+        self.messages.last()
+    }
+
+    /// The most recent user message, if any
+    pub fn last_user_message(&self) -> Option<&Message> {
+        self.last_message_by_role("user")
+    }
+
+    /// The most recent assistant message, if any
+    pub fn last_assistant_message(&self) -> Option<&Message> {
+        self.last_message_by_role("assistant")
+    }
+
+    /// The messages with the given role, e.g. `"tool"`, oldest first
+    pub fn messages_by_role<'a>(&'a self, role: &'a str) -> impl Iterator<Item = &'a Message> {
+        self.messages
+            .iter()
+            .filter(move |message| message.role == role)
+    }
+
+    fn last_message_by_role(&self, role: &str) -> Option<&Message> {
+        self.messages
+            .iter()
+            .rev()
+            .find(|message| message.role == role)
+    }
+
+    /// Export the messages in the JSON shape the OpenAI chat completions API expects
+    ///
+    /// Tool messages carry their `tool_call_id`; tool messages without one fall back to the
+    /// legacy `function` role, just like in requests to the model.
+    pub fn to_openai_messages(&self) -> Vec<serde_json::Value> {
+        request_messages(&self.messages, ApiMode::Tools)
+            .into_iter()
+            .map(|message| {
+                serde_json::to_value(message).expect("request messages serialize to JSON")
+            })
+            .collect()
+    }
+
+    /// Store a value in the shared context data, e.g. to hand it to a later tool
+    ///
+    /// Returns a `ContextError` if the value can't be serialized.
+    pub fn set_data<T: Serialize>(&mut self, key: impl Into<String>, value: T) -> AgentResult<()> {
+        let key = key.into();
+        let value = serde_json::to_value(value).map_err(|e| {
+            AgentError::ContextError(format!("invalid value for context key {}: {}", key, e))
+        })?;
+        self.context.data.insert(key, value);
+        Ok(())
+    }
+
+    /// Get a typed value from the shared context data, or `None` if the key isn't set
+    ///
+    /// Returns a `ContextError` if the key holds a value of the wrong type.
+    pub fn get_data<T: DeserializeOwned>(&self, key: &str) -> AgentResult<Option<T>> {
+        match self.context.data.get(key) {
+            Some(_) => self.context.require(key).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the typed state stored in the shared context data, see [`Context::typed_state`]
+    pub fn typed_state<S>(&mut self) -> AgentResult<TypedContext<'_, S>>
+    where
+        S: Serialize + DeserializeOwned + Default,
+    {
+        self.context.typed_state()
+    }
+
+    /// Add the assistant message requesting `tool_calls`, ahead of their results
+    pub fn add_tool_calls(&mut self, tool_calls: Vec<ToolCall>) {
+        self.messages
+            .push(Message::assistant_tool_calls(tool_calls));
+    }
+
+    /// Add a tool's result as a tool message answering its originating call
+    ///
+    /// Failed results are prefixed with `Error: ` so the model can tell them from normal output.
+    pub fn add_tool_result(&mut self, result: ToolResult) {
+        let content = if result.success {
+            result.output
+        } else {
+            format!("Error: {}", result.output)
+        };
+        self.messages.push(Message {
+            role: "tool".into(),
+            content,
+            tool_name: Some(result.tool_name),
+            tool_call_id: result.call_id,
+            ..Default::default()
         });
     }
 }
+
+/// Builder for assembling a `RunContext` with seeded messages and data
+#[derive(Default, Debug, Clone)]
+pub struct RunContextBuilder {
+    context: Context,
+    messages: Vec<Message>,
+}
+
+impl RunContextBuilder {
+    /// Create an empty run context builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a system message
+    pub fn system(self, content: impl Into<String>) -> Self {
+        self.message(Message::system(content))
+    }
+
+    /// Add a developer message
+    pub fn developer(self, content: impl Into<String>) -> Self {
+        self.message(Message::developer(content))
+    }
+
+    /// Add a user message
+    pub fn user(self, content: impl Into<String>) -> Self {
+        self.message(Message::user(content))
+    }
+
+    /// Add an assistant message
+    pub fn assistant(self, content: impl Into<String>) -> Self {
+        self.message(Message::assistant(content))
+    }
+
+    /// Add a tool message
+    pub fn tool(self, tool_name: impl Into<String>, content: impl Into<String>) -> Self {
+        self.message(Message::tool(tool_name, content))
+    }
+
+    /// Add a value to the context data
+    pub fn data(mut self, key: impl Into<String>, value: impl Serialize) -> Self {
+        self.context = self.context.with_data(key, value);
+        self
+    }
+
+    /// Build the run context
+    pub fn build(self) -> RunContext {
+        RunContext {
+            context: self.context,
+            messages: self.messages,
+            reasoning: None,
+            tokens_used: 0,
+        }
+    }
+
+    fn message(mut self, message: Message) -> Self {
+        self.messages.push(message);
+        self
+    }
+}
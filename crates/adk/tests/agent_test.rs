@@ -1,11 +1,22 @@
 use adk::ToolResult;
-use adk::agent::{Agent, AgentBuilder};
+use adk::agent::{Agent, AgentBuilder, AgentConfig, RunEvent};
 use adk::error::AgentError;
-use adk::openai::Model;
-use adk::tool::Tool;
-use adk::types::{Context, RunContext};
+use adk::logging::LoggingModel;
+use adk::openai::{FnModel, Model, ModelParams, OpenAI, Step, StepChunk, StepStream};
+use adk::prompt::PromptTemplate;
+use adk::replay::ReplayModel;
+use adk::session::{InMemorySessionStore, SessionStore};
+use adk::tool::{
+    AsyncFunctionTool, FunctionTool, OutputFormatter, TRUNCATION_MARKER, Tool, ToolCall, ToolSet,
+    parse_params,
+};
+use adk::types::{Context, Message, RunContext};
 use async_trait::async_trait;
+use futures::StreamExt;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
 
 // Mock model for testing
 #[derive(Debug)]
@@ -32,6 +43,107 @@ impl Model for MockModel {
     }
 }
 
+// Mock model that echoes the system prompt it was given
+#[derive(Debug)]
+struct SystemEchoModel;
+
+#[async_trait]
+impl Model for SystemEchoModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        Ok(context
+            .messages
+            .iter()
+            .find(|m| m.role == "system")
+            .map(|m| m.content.clone())
+            .unwrap_or_default())
+    }
+}
+
+// Mock model that calls a tool once, then answers with the tool's output
+#[derive(Debug)]
+struct ToolCallingModel {
+    tool_name: String,
+    arguments: String,
+}
+
+impl ToolCallingModel {
+    fn new(tool_name: &str) -> Self {
+        Self {
+            tool_name: tool_name.to_string(),
+            arguments: r#"{"x": 1}"#.to_string(),
+        }
+    }
+
+    fn with_arguments(mut self, arguments: &str) -> Self {
+        self.arguments = arguments.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl Model for ToolCallingModel {
+    async fn generate_response(
+        &self,
+        _context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        unreachable!("the agent drives the model through generate_step")
+    }
+
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<Step, AgentError> {
+        match context.messages.last() {
+            Some(message) if message.role == "tool" && !message.content.starts_with("Error") => {
+                Ok(Step::Message(format!("Tool said: {}", message.content)))
+            }
+            _ => Ok(Step::ToolCalls(vec![ToolCall {
+                id: "call_1".to_string(),
+                name: self.tool_name.clone(),
+                arguments: self.arguments.clone(),
+            }])),
+        }
+    }
+}
+
+// Mock model that requests a tool on every turn, recording how many tools each turn offered
+#[derive(Debug, Default)]
+struct LoopingModel {
+    calls: AtomicUsize,
+    tools_offered: std::sync::Mutex<Vec<usize>>,
+}
+
+#[async_trait]
+impl Model for LoopingModel {
+    async fn generate_response(
+        &self,
+        _context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        unreachable!("the agent drives the model through generate_step")
+    }
+
+    async fn generate_step(
+        &self,
+        _context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<Step, AgentError> {
+        self.tools_offered.lock().unwrap().push(tools.len());
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(Step::ToolCalls(vec![ToolCall {
+            id: format!("call_{}", call),
+            name: "test_tool".to_string(),
+            arguments: "{}".to_string(),
+        }]))
+    }
+}
+
 // Mock tool for testing
 #[derive(Debug)]
 struct MockTool {
@@ -72,6 +184,53 @@ impl Tool for MockTool {
         Ok(ToolResult {
             tool_name: self.name.clone(),
             output: "mock output".to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+// Mock tool that rejects its arguments a fixed number of times before succeeding
+#[derive(Debug)]
+struct FlakyTool {
+    failures: usize,
+    calls: AtomicUsize,
+}
+
+impl FlakyTool {
+    fn new(failures: usize) -> Self {
+        Self {
+            failures,
+            calls: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for FlakyTool {
+    fn name(&self) -> &str {
+        "flaky_tool"
+    }
+
+    fn description(&self) -> &str {
+        "Fails before it succeeds"
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({"type": "object", "properties": {}, "required": []})
+    }
+
+    async fn execute(
+        &self,
+        _context: &mut RunContext,
+        _params: &str,
+    ) -> Result<ToolResult, AgentError> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+            return Err(AgentError::InvalidInput("x must be positive".to_string()));
+        }
+        Ok(ToolResult {
+            tool_name: "flaky_tool".to_string(),
+            output: "ok".to_string(),
+            ..Default::default()
         })
     }
 }
@@ -212,3 +371,1639 @@ fn test_agent_builder_fluent_interface() {
     assert_eq!(agent.tools().len(), 1);
     assert_eq!(agent.tools()[0].name(), "test_tool");
 }
+
+#[tokio::test]
+async fn test_agent_run_executes_requested_tool() {
+    let model = Arc::new(ToolCallingModel::new("test_tool"));
+    let tool: Arc<dyn Tool> = Arc::new(MockTool::new("test_tool"));
+    let agent = Agent::new("test_agent", None, model, vec![tool]);
+
+    let result = agent.run("Use the tool", Context::new()).await.unwrap();
+
+    assert_eq!(result, "Tool said: mock output");
+}
+
+#[tokio::test]
+async fn test_agent_run_events_single_tool() {
+    let model = Arc::new(ToolCallingModel::new("test_tool"));
+    let tool: Arc<dyn Tool> = Arc::new(MockTool::new("test_tool"));
+    let agent = Agent::new("test_agent", None, model, vec![tool]);
+
+    let events: Vec<RunEvent> = agent
+        .run_events("Use the tool", Context::new())
+        .collect()
+        .await;
+
+    assert_eq!(events.len(), 6);
+    assert!(matches!(
+        events[0],
+        RunEvent::UsageUpdate { tool_calls: 1, .. }
+    ));
+    match &events[1] {
+        RunEvent::ToolStarted { name, args } => {
+            assert_eq!(name, "test_tool");
+            assert_eq!(args, r#"{"x": 1}"#);
+        }
+        other => panic!("Expected ToolStarted, got {:?}", other),
+    }
+    match &events[2] {
+        RunEvent::ToolCompleted { name, output } => {
+            assert_eq!(name, "test_tool");
+            assert_eq!(output, "mock output");
+        }
+        other => panic!("Expected ToolCompleted, got {:?}", other),
+    }
+    assert!(matches!(
+        events[3],
+        RunEvent::UsageUpdate { tool_calls: 1, .. }
+    ));
+    match &events[4] {
+        RunEvent::MessageDelta { content } => assert_eq!(content, "Tool said: mock output"),
+        other => panic!("Expected MessageDelta, got {:?}", other),
+    }
+    match &events[5] {
+        RunEvent::Completed { text } => assert_eq!(text, "Tool said: mock output"),
+        other => panic!("Expected Completed, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_agent_as_tool_runs_in_isolated_context() {
+    let researcher = AgentBuilder::new("researcher")
+        .model(Arc::new(SystemEchoModel))
+        .instructions_template(PromptTemplate::new("Researching {topic}"))
+        .build()
+        .unwrap();
+    let agent = AgentBuilder::new("lead")
+        .model(Arc::new(
+            ToolCallingModel::new("researcher").with_arguments(r#"{"input": "Find sources"}"#),
+        ))
+        .instructions("You lead the research")
+        .add_tool(Arc::new(researcher.as_tool("Researches a topic").unwrap()))
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new().with_data("topic", "tides"));
+    run_context.add_message("user", "Write a report");
+    let result = agent.run_with_context(&mut run_context).await.unwrap();
+
+    // The sub-agent saw the parent's data, and only its answer came back
+    assert_eq!(result, "Tool said: Researching tides");
+    let contents: Vec<&str> = run_context
+        .messages
+        .iter()
+        .map(|m| m.content.as_str())
+        .collect();
+    assert_eq!(
+        contents,
+        [
+            "You lead the research",
+            "Write a report",
+            "",
+            "Researching tides",
+            "Tool said: Researching tides"
+        ]
+    );
+    assert!(!contents.contains(&"Find sources"));
+    assert_eq!(run_context.messages[3].role, "tool");
+}
+
+#[test]
+fn test_agent_as_tool_requires_valid_tool_name() {
+    let agent = Agent::new(
+        "Research Agent",
+        None,
+        Arc::new(MockModel::new("unused")),
+        vec![],
+    );
+
+    match agent.clone().as_tool("Researches a topic") {
+        Err(AgentError::ConfigurationError(msg)) => {
+            assert_eq!(msg, r#"Invalid tool name: "Research Agent""#)
+        }
+        _ => panic!("Expected ConfigurationError"),
+    }
+    assert!(agent.clone().as_named_tool("", "Researches").is_err());
+    assert!(
+        agent
+            .clone()
+            .as_named_tool("r".repeat(65), "Researches")
+            .is_err()
+    );
+    let tool = agent
+        .as_named_tool("research_agent", "Researches a topic")
+        .unwrap();
+    assert_eq!(tool.name(), "research_agent");
+}
+
+#[tokio::test]
+async fn test_agent_run_events_unknown_tool_fails() {
+    let model = Arc::new(ToolCallingModel::new("missing_tool"));
+    let agent = Agent::new("test_agent", None, model, vec![]);
+
+    let events: Vec<RunEvent> = agent
+        .run_events("Use the tool", Context::new())
+        .collect()
+        .await;
+
+    assert_eq!(events.len(), 2);
+    match &events[1] {
+        RunEvent::Failed {
+            error: AgentError::ToolNotFound(name),
+        } => assert_eq!(name, "missing_tool"),
+        other => panic!("Expected Failed, got {:?}", other),
+    }
+}
+
+// Mock model that calls a tool, then streams its answer in three deltas
+struct StreamingModel;
+
+#[async_trait]
+impl Model for StreamingModel {
+    async fn generate_response(
+        &self,
+        _context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        unreachable!("the agent streams the model's turns")
+    }
+
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        _tools: &[&dyn Tool],
+        _params: &ModelParams,
+    ) -> Result<StepStream, AgentError> {
+        let chunks = if context.messages.last().is_some_and(|m| m.role == "tool") {
+            ["The ", "answer ", "is 42"]
+                .into_iter()
+                .map(|delta| Ok(StepChunk::Content(delta.to_string())))
+                .collect()
+        } else {
+            vec![Ok(StepChunk::ToolCalls(vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "lookup".to_string(),
+                arguments: "{}".to_string(),
+            }]))]
+        };
+        Ok(futures::stream::iter(chunks).boxed())
+    }
+}
+
+#[tokio::test]
+async fn test_agent_run_stream_streams_answer_after_tools() {
+    let executed = Arc::new(AtomicBool::new(false));
+    let tool_executed = executed.clone();
+    let agent = AgentBuilder::new("streaming_agent")
+        .model(Arc::new(StreamingModel))
+        .add_tool(Arc::new(FunctionTool::new(
+            "lookup",
+            "Looks up the answer",
+            serde_json::json!({"type": "object"}),
+            Box::new(move |_, _| {
+                tool_executed.store(true, Ordering::SeqCst);
+                Ok(ToolResult::default())
+            }),
+        )))
+        .build()
+        .unwrap();
+
+    let deltas: Vec<(bool, String)> = agent
+        .run_stream("What is the answer?", Context::new())
+        .map(|delta| (executed.load(Ordering::SeqCst), delta.unwrap()))
+        .collect()
+        .await;
+
+    assert_eq!(
+        deltas,
+        [
+            (true, "The ".to_string()),
+            (true, "answer ".to_string()),
+            (true, "is 42".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_agent_run_stream_ends_with_error() {
+    let model = Arc::new(ToolCallingModel::new("missing_tool"));
+    let agent = Agent::new("test_agent", None, model, vec![]);
+
+    let deltas: Vec<_> = agent
+        .run_stream("Use the tool", Context::new())
+        .collect()
+        .await;
+
+    assert_eq!(deltas.len(), 1);
+    assert!(matches!(&deltas[0], Err(AgentError::ToolNotFound(name)) if name == "missing_tool"));
+}
+
+fn message(role: &str, content: &str) -> Message {
+    Message {
+        role: role.to_string(),
+        content: content.to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_agent_run_with_messages() {
+    let model = Arc::new(MockModel::new("Resumed"));
+    let agent = Agent::new("test_agent", None, model, vec![]);
+
+    let seed = vec![
+        message("user", "Hi"),
+        message("assistant", "Hello!"),
+        message("user", "Where were we?"),
+    ];
+    let result = agent.run_with_messages(seed, Context::new()).await.unwrap();
+
+    assert_eq!(result, "Resumed");
+}
+
+#[tokio::test]
+async fn test_agent_run_with_context_keeps_history() {
+    let model = Arc::new(MockModel::new("Resumed"));
+    let agent = Agent::new("test_agent", None, model, vec![]);
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.messages = vec![
+        message("user", "Hi"),
+        message("assistant", "Hello!"),
+        message("user", "Where were we?"),
+    ];
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let contents: Vec<(&str, &str)> = run_context
+        .messages
+        .iter()
+        .map(|m| (m.role.as_str(), m.content.as_str()))
+        .collect();
+    assert_eq!(
+        contents,
+        vec![
+            ("user", "Hi"),
+            ("assistant", "Hello!"),
+            ("user", "Where were we?"),
+            ("assistant", "Resumed"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_agent_run_with_context_adds_instructions_once() {
+    let model = Arc::new(MockModel::new("Again"));
+    let agent = Agent::new("test_agent", Some("Be brief".to_string()), model, vec![]);
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "First");
+    agent.run_with_context(&mut run_context).await.unwrap();
+    run_context.add_message("user", "Second");
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let system_messages = run_context
+        .messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .count();
+    assert_eq!(system_messages, 1);
+    assert_eq!(run_context.messages[0].content, "Be brief");
+    assert_eq!(run_context.messages.len(), 5);
+}
+
+#[tokio::test]
+async fn test_agent_dynamic_instructions() {
+    let agent = AgentBuilder::new("dynamic_agent")
+        .instructions("Static instructions")
+        .dynamic_instructions(Box::new(|context: &Context| {
+            format!(
+                "You are talking to {}",
+                context.data["user_name"].as_str().unwrap_or("someone")
+            )
+        }))
+        .model(Arc::new(SystemEchoModel))
+        .build()
+        .unwrap();
+
+    let context = Context::new().with_data("user_name", "Ada");
+    let result = agent.run("Hello", context).await.unwrap();
+
+    assert_eq!(result, "You are talking to Ada");
+}
+
+#[tokio::test]
+async fn test_agent_adds_instructions_after_hidden_system_message() {
+    let agent = AgentBuilder::new("instructed_agent")
+        .instructions("Be brief")
+        .add_example("Hi", "Hello")
+        .model(Arc::new(MockModel::new("Done")))
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_hidden_message("system", "internal: session restored");
+    run_context.add_message("user", "Hello");
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let messages: Vec<(String, bool)> = run_context
+        .messages
+        .iter()
+        .map(|m| (m.to_string(), m.hidden))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            ("system: Be brief".to_string(), false),
+            ("user: Hi".to_string(), false),
+            ("assistant: Hello".to_string(), false),
+            ("system: internal: session restored".to_string(), true),
+            ("user: Hello".to_string(), false),
+            ("assistant: Done".to_string(), false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_agent_instructions_template() {
+    let agent = AgentBuilder::new("template_agent")
+        .instructions("Static instructions")
+        .instructions_template(PromptTemplate::new("You are helping {user_name}"))
+        .model(Arc::new(SystemEchoModel))
+        .build()
+        .unwrap();
+
+    let context = Context::new().with_data("user_name", "Ada");
+    assert_eq!(
+        agent.run("Hello", context).await.unwrap(),
+        "You are helping Ada"
+    );
+
+    match agent.run("Hello", Context::new()).await {
+        Err(AgentError::ContextError(msg)) => {
+            assert_eq!(msg, "missing template variable: user_name")
+        }
+        other => panic!("Expected ContextError, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_agent_developer_instructions() {
+    let agent = AgentBuilder::new("developer_agent")
+        .instructions("Be brief")
+        .developer_instructions(true)
+        .model(Arc::new(MockModel::new("Hi")))
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Hello");
+    agent.run_with_context(&mut run_context).await.unwrap();
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let roles: Vec<&str> = run_context
+        .messages
+        .iter()
+        .map(|m| m.role.as_str())
+        .collect();
+    assert_eq!(roles, ["developer", "user", "assistant", "assistant"]);
+    assert_eq!(run_context.messages[0].content, "Be brief");
+}
+
+#[tokio::test]
+async fn test_agent_examples_go_between_instructions_and_input() {
+    let agent = AgentBuilder::new("sentiment_agent")
+        .instructions("Classify the sentiment")
+        .add_example("I love it", "positive")
+        .add_example("It broke on day one", "negative")
+        .model(Arc::new(MockModel::new("neutral")))
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "It arrived on Tuesday");
+    agent.run_with_context(&mut run_context).await.unwrap();
+    run_context.add_message("user", "It's fine");
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let messages: Vec<(&str, &str, bool)> = run_context
+        .messages
+        .iter()
+        .map(|m| (m.role.as_str(), m.content.as_str(), m.example))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            ("system", "Classify the sentiment", false),
+            ("user", "I love it", true),
+            ("assistant", "positive", true),
+            ("user", "It broke on day one", true),
+            ("assistant", "negative", true),
+            ("user", "It arrived on Tuesday", false),
+            ("assistant", "neutral", false),
+            ("user", "It's fine", false),
+            ("assistant", "neutral", false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_agent_static_instructions_without_dynamic() {
+    let agent = AgentBuilder::new("static_agent")
+        .instructions("Static instructions")
+        .model(Arc::new(SystemEchoModel))
+        .build()
+        .unwrap();
+
+    let result = agent.run("Hello", Context::new()).await.unwrap();
+
+    assert_eq!(result, "Static instructions");
+}
+
+#[tokio::test]
+async fn test_agent_tool_error_retry_recovers() {
+    let tool = Arc::new(FlakyTool::new(1));
+    let agent = AgentBuilder::new("retry_agent")
+        .model(Arc::new(ToolCallingModel::new("flaky_tool")))
+        .add_tool(tool.clone())
+        .tool_error_retries(1)
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Use the tool");
+    let result = agent.run_with_context(&mut run_context).await.unwrap();
+
+    assert_eq!(result, "Tool said: ok");
+    assert_eq!(tool.calls.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        run_context.messages[2].content,
+        "Error: Invalid input: x must be positive"
+    );
+    assert_eq!(
+        run_context.messages[2].tool_name,
+        Some("flaky_tool".to_string())
+    );
+    assert_eq!(
+        run_context.messages[2].tool_call_id,
+        run_context.messages[1]
+            .tool_calls
+            .first()
+            .map(|tool_call| tool_call.id.clone())
+    );
+}
+
+#[tokio::test]
+async fn test_agent_tool_error_without_retries_fails() {
+    let tool = Arc::new(FlakyTool::new(1));
+    let agent = AgentBuilder::new("retry_agent")
+        .model(Arc::new(ToolCallingModel::new("flaky_tool")))
+        .add_tool(tool.clone())
+        .build()
+        .unwrap();
+
+    let result = agent.run("Use the tool", Context::new()).await;
+
+    assert!(matches!(result, Err(AgentError::InvalidInput(_))));
+    assert_eq!(tool.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_agent_tool_error_retries_report_unknown_tool() {
+    let agent = AgentBuilder::new("retry_agent")
+        .model(Arc::new(ReplayModel::new(vec![
+            Step::ToolCalls(vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "serch".to_string(),
+                arguments: "{}".to_string(),
+            }]),
+            Step::Message("Sorry, wrong tool".to_string()),
+        ])))
+        .add_tool(Arc::new(MockTool::new("search")))
+        .tool_error_retries(1)
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Search for tides");
+    let result = agent.run_with_context(&mut run_context).await.unwrap();
+
+    assert_eq!(result, "Sorry, wrong tool");
+    let tool_message = &run_context.messages[2];
+    assert_eq!(tool_message.role, "tool");
+    assert_eq!(tool_message.content, "Error: Tool not found: serch");
+    assert_eq!(tool_message.tool_call_id.as_deref(), Some("call_1"));
+}
+
+#[tokio::test]
+async fn test_agent_tool_error_retries_exhausted() {
+    let tool = Arc::new(FlakyTool::new(3));
+    let agent = AgentBuilder::new("retry_agent")
+        .model(Arc::new(ToolCallingModel::new("flaky_tool")))
+        .add_tool(tool.clone())
+        .tool_error_retries(2)
+        .build()
+        .unwrap();
+
+    let result = agent.run("Use the tool", Context::new()).await;
+
+    assert!(matches!(result, Err(AgentError::InvalidInput(_))));
+    assert_eq!(tool.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_agent_clone_runs_independently() {
+    let tool: Arc<dyn Tool> = Arc::new(MockTool::new("test_tool"));
+    let agent = AgentBuilder::new("cloned_agent")
+        .instructions("Be helpful")
+        .model(Arc::new(ToolCallingModel::new("test_tool")))
+        .add_tool(tool.clone())
+        .build()
+        .unwrap();
+    let clone = agent.clone();
+
+    let (first, second) = tokio::join!(
+        agent.run("Use the tool", Context::new()),
+        clone.run("Use the tool", Context::new())
+    );
+
+    assert_eq!(first.unwrap(), "Tool said: mock output");
+    assert_eq!(second.unwrap(), "Tool said: mock output");
+    assert_eq!(clone.name(), agent.name());
+    assert_eq!(clone.instructions(), agent.instructions());
+    assert!(Arc::ptr_eq(&clone.tools()[0], &agent.tools()[0]));
+}
+
+#[tokio::test]
+async fn test_agent_clone_across_tasks() {
+    let agent = AgentBuilder::new("spawned_agent")
+        .model(Arc::new(MockModel::new("from task")))
+        .build()
+        .unwrap();
+
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
+            let agent = agent.clone();
+            tokio::spawn(async move { agent.run("Hello", Context::new()).await })
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(handle.await.unwrap().unwrap(), "from task");
+    }
+}
+
+#[tokio::test]
+async fn test_agent_max_turns_exceeded() {
+    let model = Arc::new(LoopingModel::default());
+    let agent = AgentBuilder::new("looping_agent")
+        .model(model.clone())
+        .add_tool(Arc::new(MockTool::new("test_tool")))
+        .max_turns(3)
+        .build()
+        .unwrap();
+
+    let result = agent.run("Loop forever", Context::new()).await;
+
+    match result {
+        Err(AgentError::ModelError(msg)) => assert_eq!(msg, "max turns exceeded"),
+        other => panic!("Expected ModelError, got {:?}", other),
+    }
+    assert_eq!(model.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_agent_force_final_on_limit_offers_no_tools_last() {
+    let model = Arc::new(LoopingModel::default());
+    let agent = AgentBuilder::new("looping_agent")
+        .model(model.clone())
+        .add_tool(Arc::new(MockTool::new("test_tool")))
+        .max_turns(3)
+        .force_final_on_limit(true)
+        .build()
+        .unwrap();
+
+    // The mock ignores the nudge, so the run still runs out of turns
+    assert!(agent.run("Loop forever", Context::new()).await.is_err());
+    assert_eq!(*model.tools_offered.lock().unwrap(), [1, 1, 0]);
+}
+
+#[test]
+fn test_agent_config_round_trips_through_registries() {
+    let model: Arc<dyn Model> = Arc::new(OpenAI::new("test-key", "gpt-4o"));
+    let models = HashMap::from([("gpt-4o".to_string(), model.clone())]);
+    let tools = ToolSet::from_tools([
+        Arc::new(MockTool::new("search")) as Arc<dyn Tool>,
+        Arc::new(MockTool::new("fetch")),
+        Arc::new(MockTool::new("unused")),
+    ])
+    .unwrap();
+
+    let agent = AgentBuilder::new("researcher")
+        .instructions("Cite your sources")
+        .model(model)
+        .add_tool(tools.get("search").unwrap().clone())
+        .add_tool(tools.get("fetch").unwrap().clone())
+        .temperature(0.2)
+        .max_turns(5)
+        .build()
+        .unwrap();
+
+    let json = serde_json::to_string(&agent.to_config()).unwrap();
+    let config: AgentConfig = serde_json::from_str(&json).unwrap();
+    assert_eq!(config.model.as_deref(), Some("gpt-4o"));
+    assert_eq!(config.tools, ["search", "fetch"]);
+
+    let rebuilt = AgentBuilder::from_config(config, &models, &tools)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(rebuilt.name(), "researcher");
+    assert_eq!(rebuilt.instructions(), Some("Cite your sources"));
+    let tool_names: Vec<&str> = rebuilt.tools().iter().map(|tool| tool.name()).collect();
+    assert_eq!(tool_names, ["search", "fetch"]);
+    assert_eq!(rebuilt.to_config(), agent.to_config());
+}
+
+#[test]
+fn test_agent_from_config_rejects_unknown_tool() {
+    let config: AgentConfig =
+        serde_json::from_value(serde_json::json!({"name": "agent", "tools": ["missing"]})).unwrap();
+
+    match AgentBuilder::from_config(config, &HashMap::new(), &ToolSet::new()) {
+        Err(AgentError::ConfigurationError(msg)) => assert_eq!(msg, "Unknown tool: missing"),
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
+
+#[tokio::test]
+async fn test_agent_max_turns_allows_completion() {
+    let agent = AgentBuilder::new("bounded_agent")
+        .model(Arc::new(ToolCallingModel::new("test_tool")))
+        .add_tool(Arc::new(MockTool::new("test_tool")))
+        .max_turns(2)
+        .build()
+        .unwrap();
+
+    let result = agent.run("Use the tool", Context::new()).await.unwrap();
+
+    assert_eq!(result, "Tool said: mock output");
+}
+
+#[test]
+fn test_agent_tools_manifest() {
+    let agent = AgentBuilder::new("manifest_agent")
+        .model(Arc::new(MockModel::new("unused")))
+        .add_tool(Arc::new(MockTool::new("tool1")))
+        .add_tool(Arc::new(MockTool::new("tool2")))
+        .build()
+        .unwrap();
+
+    let manifest = agent.tools_manifest();
+    let entries = manifest.as_array().unwrap();
+
+    assert_eq!(entries.len(), 2);
+    for (entry, name) in entries.iter().zip(["tool1", "tool2"]) {
+        assert_eq!(entry["name"], name);
+        assert_eq!(entry["description"], "A mock tool for testing");
+        assert_eq!(entry["parameters"]["type"], "object");
+        assert_eq!(entry.as_object().unwrap().len(), 3);
+    }
+}
+
+fn tagged_tool(name: &str, tags: &[&str]) -> Arc<dyn Tool> {
+    let tool = FunctionTool::new(
+        name,
+        "A tagged tool",
+        serde_json::json!({"type": "object"}),
+        Box::new(|_, _| Ok(ToolResult::default())),
+    );
+    Arc::new(tool.with_tags(tags.iter().copied()))
+}
+
+#[test]
+fn test_agent_builder_adds_tools_matching_tags() {
+    let library = vec![
+        tagged_tool("read_file", &["read-only"]),
+        tagged_tool("delete_file", &["destructive"]),
+        tagged_tool("fetch_url", &["read-only", "network"]),
+        tagged_tool("untagged", &[]),
+    ];
+
+    let agent = AgentBuilder::new("least_privilege_agent")
+        .model(Arc::new(MockModel::new("unused")))
+        .add_tools_where(library, |tool| {
+            tool.tags().iter().any(|tag| tag == "read-only")
+        })
+        .build()
+        .unwrap();
+
+    let names: Vec<&str> = agent.tools().iter().map(|tool| tool.name()).collect();
+    assert_eq!(names, ["read_file", "fetch_url"]);
+}
+
+#[test]
+fn test_agent_builder_adds_merged_tool_sets() {
+    let shared = tagged_tool("search", &[]);
+    let research = ToolSet::from_tools([shared.clone(), tagged_tool("summarize", &[])]).unwrap();
+    let files = ToolSet::new()
+        .with_tool(tagged_tool("read_file", &[]))
+        .and_then(|set| set.with_tool(shared))
+        .unwrap();
+
+    let agent = AgentBuilder::new("tool_set_agent")
+        .model(Arc::new(MockModel::new("unused")))
+        .add_tool_set(research.merge(files).unwrap())
+        .build()
+        .unwrap();
+
+    let names: Vec<&str> = agent.tools().iter().map(|tool| tool.name()).collect();
+    assert_eq!(names, ["search", "summarize", "read_file"]);
+}
+
+#[test]
+fn test_tool_set_rejects_different_tools_with_same_name() {
+    let research = ToolSet::from_tools([tagged_tool("search", &[])]).unwrap();
+    let web = ToolSet::from_tools([tagged_tool("search", &["network"])]).unwrap();
+
+    match research.merge(web) {
+        Err(AgentError::ConfigurationError(msg)) => assert_eq!(msg, "Duplicate tool name: search"),
+        _ => panic!("Expected ConfigurationError"),
+    }
+    match ToolSet::from_tools([tagged_tool("fetch", &[]), tagged_tool("fetch", &[])]) {
+        Err(AgentError::ConfigurationError(msg)) => assert_eq!(msg, "Duplicate tool name: fetch"),
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
+
+#[test]
+fn test_agent_tools_manifest_empty() {
+    let agent = Agent::new(
+        "empty_agent",
+        None,
+        Arc::new(MockModel::new("unused")),
+        vec![],
+    );
+
+    assert_eq!(agent.tools_manifest(), serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn test_agent_truncates_long_tool_output() {
+    let tool = FunctionTool::new(
+        "big_tool",
+        "Returns a megabyte of text",
+        serde_json::json!({"type": "object"}),
+        Box::new(|_, _| {
+            Ok(ToolResult {
+                tool_name: "big_tool".to_string(),
+                output: "x".repeat(1_000_000),
+                ..Default::default()
+            })
+        }),
+    );
+    let agent = AgentBuilder::new("truncating_agent")
+        .model(Arc::new(ToolCallingModel::new("big_tool")))
+        .add_tool(Arc::new(tool))
+        .max_tool_output_chars(100)
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Use the tool");
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let expected = format!("{}{}", "x".repeat(100), TRUNCATION_MARKER);
+    assert_eq!(run_context.messages[2].role, "tool");
+    assert_eq!(run_context.messages[2].content, expected);
+}
+
+fn doubling_tool() -> FunctionTool {
+    FunctionTool::new(
+        "double",
+        "Doubles x",
+        serde_json::json!({"type": "object", "properties": {"x": {"type": "integer"}}}),
+        Box::new(|_, params| {
+            let params: serde_json::Value = parse_params(params)?;
+            Ok(ToolResult {
+                tool_name: "double".to_string(),
+                output: (params["x"].as_i64().unwrap_or_default() * 2).to_string(),
+                ..Default::default()
+            })
+        }),
+    )
+}
+
+#[tokio::test]
+async fn test_agent_lenient_tool_args_repairs_arguments() {
+    let agent = AgentBuilder::new("lenient_agent")
+        .model(Arc::new(
+            ToolCallingModel::new("double").with_arguments("{'x': 21, // the input\n}"),
+        ))
+        .add_tool(Arc::new(doubling_tool()))
+        .lenient_tool_args(true)
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        agent.run("Double it", Context::new()).await.unwrap(),
+        "Tool said: 42"
+    );
+}
+
+#[tokio::test]
+async fn test_agent_strict_tool_args_by_default() {
+    let agent = AgentBuilder::new("strict_agent")
+        .model(Arc::new(
+            ToolCallingModel::new("double").with_arguments(r#"{"x": 21,}"#),
+        ))
+        .add_tool(Arc::new(doubling_tool()))
+        .build()
+        .unwrap();
+
+    assert!(matches!(
+        agent.run("Double it", Context::new()).await,
+        Err(AgentError::InvalidInput(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_agent_records_tool_metrics() {
+    let agent = AgentBuilder::new("metrics_agent")
+        .model(Arc::new(ToolCallingModel::new("slow_tool")))
+        .add_tool(Arc::new(AsyncFunctionTool::new(
+            "slow_tool",
+            "Takes a moment",
+            serde_json::json!({"type": "object"}),
+            |_, _| {
+                Box::pin(async {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    Ok(ToolResult::default())
+                })
+            },
+        )))
+        .build()
+        .unwrap();
+
+    for _ in 0..3 {
+        agent.run("Use the tool", Context::new()).await.unwrap();
+    }
+
+    let metrics = agent.tool_metrics();
+    let stats = metrics.get("slow_tool").unwrap();
+    assert_eq!(stats.count, 3);
+    assert!(stats.max >= Duration::from_millis(5));
+    assert!(stats.total >= Duration::from_millis(15));
+    assert!(stats.mean() > Duration::ZERO);
+    assert!(metrics.get("unused_tool").is_none());
+}
+
+fn destructive_tool(calls: Arc<AtomicUsize>) -> FunctionTool {
+    FunctionTool::new(
+        "delete_all",
+        "Deletes everything",
+        serde_json::json!({"type": "object"}),
+        Box::new(move |_, _| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(ToolResult::default())
+        }),
+    )
+    .with_approval_required()
+}
+
+fn delete_all_then_answer() -> Arc<ReplayModel> {
+    Arc::new(ReplayModel::new(vec![
+        Step::ToolCalls(vec![ToolCall {
+            id: "call_1".to_string(),
+            name: "delete_all".to_string(),
+            arguments: "{}".to_string(),
+        }]),
+        Step::Message("Done".to_string()),
+    ]))
+}
+
+#[tokio::test]
+async fn test_agent_appends_tool_follow_up_after_result() {
+    let agent = AgentBuilder::new("follow_up_agent")
+        .model(Arc::new(ReplayModel::new(vec![
+            Step::ToolCalls(vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "search".to_string(),
+                arguments: "{}".to_string(),
+            }]),
+            Step::Message("Summary".to_string()),
+        ])))
+        .add_tool(Arc::new(FunctionTool::new(
+            "search",
+            "Searches the web",
+            serde_json::json!({"type": "object"}),
+            Box::new(|_, _| {
+                Ok(ToolResult {
+                    tool_name: "search".to_string(),
+                    output: "3 pages found".to_string(),
+                    follow_up: Some("Summarize the pages before answering".to_string()),
+                    ..Default::default()
+                })
+            }),
+        )))
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Look this up");
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let tool_message = &run_context.messages[2];
+    assert_eq!(tool_message.role, "tool");
+    assert_eq!(tool_message.content, "3 pages found");
+    let follow_up = &run_context.messages[3];
+    assert_eq!(follow_up.role, "system");
+    assert_eq!(follow_up.content, "Summarize the pages before answering");
+    assert_eq!(run_context.messages[4].content, "Summary");
+}
+
+#[tokio::test]
+async fn test_agent_denied_tool_never_executes() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let agent = AgentBuilder::new("gated_agent")
+        .model(delete_all_then_answer())
+        .add_tool(Arc::new(destructive_tool(calls.clone())))
+        .approval_handler(Arc::new(|tool_call| tool_call.name != "delete_all"))
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Delete everything");
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    let tool_message = &run_context.messages[2];
+    assert_eq!(tool_message.role, "tool");
+    assert_eq!(tool_message.content, "Error: tool call denied");
+    assert_eq!(tool_message.tool_call_id.as_deref(), Some("call_1"));
+}
+
+#[tokio::test]
+async fn test_agent_approved_tool_executes() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let agent = AgentBuilder::new("gated_agent")
+        .model(delete_all_then_answer())
+        .add_tool(Arc::new(destructive_tool(calls.clone())))
+        .approval_handler(Arc::new(|_| true))
+        .build()
+        .unwrap();
+
+    agent
+        .run("Delete everything", Context::new())
+        .await
+        .unwrap();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_agent_denies_approval_without_handler() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let agent = AgentBuilder::new("gated_agent")
+        .model(delete_all_then_answer())
+        .add_tool(Arc::new(destructive_tool(calls.clone())))
+        .build()
+        .unwrap();
+
+    agent
+        .run("Delete everything", Context::new())
+        .await
+        .unwrap();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_generate_response_denies_tool_requiring_approval() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let tool = destructive_tool(calls.clone());
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Delete everything");
+    let response = delete_all_then_answer()
+        .generate_response(&mut run_context, &[&tool])
+        .await
+        .unwrap();
+
+    assert_eq!(response, "Done");
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    let tool_message = &run_context.messages[2];
+    assert_eq!(tool_message.role, "tool");
+    assert_eq!(tool_message.content, "Error: tool call denied");
+    assert_eq!(tool_message.tool_call_id.as_deref(), Some("call_1"));
+}
+
+struct CodeFenceFormatter;
+
+impl OutputFormatter for CodeFenceFormatter {
+    fn format(&self, result: &ToolResult) -> String {
+        format!("```\n{}\n```", result.output)
+    }
+}
+
+#[tokio::test]
+async fn test_agent_output_formatter_formats_tool_messages() {
+    let agent = AgentBuilder::new("formatting_agent")
+        .model(Arc::new(ToolCallingModel::new("test_tool")))
+        .add_tool(Arc::new(MockTool::new("test_tool")))
+        .output_formatter(Arc::new(CodeFenceFormatter))
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Use the tool");
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let tool_message = &run_context.messages[2];
+    assert_eq!(tool_message.role, "tool");
+    assert_eq!(tool_message.content, "```\nmock output\n```");
+}
+
+#[tokio::test]
+async fn test_agent_tool_message_references_call_id() {
+    let agent = AgentBuilder::new("call_id_agent")
+        .model(Arc::new(ToolCallingModel::new("test_tool")))
+        .add_tool(Arc::new(MockTool::new("test_tool")))
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Use the tool");
+    agent.run_with_context(&mut run_context).await.unwrap();
+
+    let call_message = &run_context.messages[1];
+    assert_eq!(call_message.role, "assistant");
+    assert_eq!(call_message.tool_calls.len(), 1);
+    assert_eq!(call_message.tool_calls[0].id, "call_1");
+    assert_eq!(call_message.tool_calls[0].name, "test_tool");
+
+    let tool_message = &run_context.messages[2];
+    assert_eq!(tool_message.role, "tool");
+    assert_eq!(tool_message.tool_name, Some("test_tool".to_string()));
+    assert_eq!(tool_message.tool_call_id, Some("call_1".to_string()));
+}
+
+#[test]
+fn test_agent_builder_rejects_duplicate_tool_names() {
+    let result = AgentBuilder::new("duplicate_agent")
+        .model(Arc::new(MockModel::new("unused")))
+        .add_tool(Arc::new(MockTool::new("search")))
+        .add_tool(Arc::new(MockTool::new("fetch")))
+        .add_tool(Arc::new(MockTool::new("search")))
+        .build();
+
+    match result {
+        Err(AgentError::ConfigurationError(msg)) => assert_eq!(msg, "Duplicate tool name: search"),
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
+
+#[test]
+fn test_agent_builder_rejects_malformed_tool_schema() {
+    let tool = FunctionTool::new(
+        "lookup",
+        "Looks something up",
+        serde_json::json!({
+            "type": "object",
+            "properties": {"query": {"type": "string"}},
+            "required": "query"
+        }),
+        Box::new(|_, _| Ok(ToolResult::default())),
+    );
+    let result = AgentBuilder::new("schema_agent")
+        .model(Arc::new(MockModel::new("unused")))
+        .add_tool(Arc::new(tool))
+        .build();
+
+    match result {
+        Err(AgentError::ConfigurationError(msg)) => assert_eq!(
+            msg,
+            r#"Invalid parameters schema for tool lookup: "required" must be an array of strings"#
+        ),
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
+
+#[test]
+fn test_agent_tool_by_name() {
+    let agent = AgentBuilder::new("lookup_agent")
+        .model(Arc::new(MockModel::new("unused")))
+        .add_tool(Arc::new(MockTool::new("search")))
+        .add_tool(Arc::new(MockTool::new("fetch")))
+        .build()
+        .unwrap();
+
+    assert_eq!(agent.tool_by_name("fetch").unwrap().name(), "fetch");
+    assert_eq!(agent.tool_by_name("search").unwrap().name(), "search");
+    assert!(agent.tool_by_name("missing").is_none());
+}
+
+#[test]
+fn test_agent_debug_shows_name_and_tools() {
+    let agent = AgentBuilder::new("debug_agent")
+        .instructions("Be brief")
+        .model(Arc::new(MockModel::new("unused")))
+        .add_tool(Arc::new(MockTool::new("search")))
+        .add_tool(Arc::new(MockTool::new("fetch")))
+        .build()
+        .unwrap();
+
+    let debug = format!("{:?}", agent);
+    assert!(debug.contains(r#"name: "debug_agent""#), "{}", debug);
+    assert!(debug.contains(r#"tools: ["search", "fetch"]"#), "{}", debug);
+    assert!(debug.contains("Be brief"), "{}", debug);
+}
+
+#[tokio::test]
+async fn test_agent_message_sink_receives_run_messages() {
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+    let agent = AgentBuilder::new("sink_agent")
+        .instructions("Be helpful")
+        .model(Arc::new(ToolCallingModel::new("test_tool")))
+        .add_tool(Arc::new(MockTool::new("test_tool")))
+        .message_sink(sender)
+        .build()
+        .unwrap();
+
+    agent.run("Use the tool", Context::new()).await.unwrap();
+    drop(agent);
+
+    let mut received = Vec::new();
+    while let Some(message) = receiver.recv().await {
+        received.push((message.role, message.content));
+    }
+    let expected: Vec<(String, String)> = vec![
+        ("system".into(), "Be helpful".into()),
+        ("user".into(), "Use the tool".into()),
+        ("assistant".into(), "".into()),
+        ("tool".into(), "mock output".into()),
+        ("assistant".into(), "Tool said: mock output".into()),
+    ];
+    assert_eq!(received, expected);
+}
+
+#[tokio::test]
+async fn test_agent_message_sink_skips_existing_history() {
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+    let agent = AgentBuilder::new("sink_agent")
+        .model(Arc::new(MockModel::new("Hi again")))
+        .message_sink(sender)
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Hello");
+    agent.run_with_context(&mut run_context).await.unwrap();
+    drop(agent);
+
+    let message = receiver.recv().await.unwrap();
+    assert_eq!(message.role, "assistant");
+    assert_eq!(message.content, "Hi again");
+    assert!(receiver.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn test_agent_builder_layers_instruction_segments() {
+    let agent = AgentBuilder::new("layered_agent")
+        .instruction("Follow the base policy.")
+        .instruction("Summarize the ticket.")
+        .instruction("Answer in French.")
+        .model(Arc::new(SystemEchoModel))
+        .build()
+        .unwrap();
+
+    let expected = "Follow the base policy.\n\nSummarize the ticket.\n\nAnswer in French.";
+    assert_eq!(agent.instructions(), Some(expected));
+    let response = agent.run("Hello", Context::new()).await.unwrap();
+    assert_eq!(response, expected);
+}
+
+#[tokio::test]
+async fn test_agent_shutdown_closes_message_sink() {
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+    let mut agent = AgentBuilder::new("sink_agent")
+        .model(Arc::new(MockModel::new("Done")))
+        .message_sink(sender)
+        .build()
+        .unwrap();
+
+    agent.run("Hello", Context::new()).await.unwrap();
+    agent.shutdown().await;
+
+    assert_eq!(receiver.recv().await.unwrap().role, "user");
+    assert_eq!(receiver.recv().await.unwrap().role, "assistant");
+    assert!(receiver.recv().await.is_none());
+    assert_eq!(agent.name(), "sink_agent");
+}
+
+#[tokio::test]
+async fn test_agent_default_context_merged_under_run_context() {
+    let agent = AgentBuilder::new("tenant_agent")
+        .dynamic_instructions(Box::new(|context: &Context| {
+            format!(
+                "{} {} {}",
+                context.require::<String>("endpoint").unwrap(),
+                context.require::<String>("tenant").unwrap(),
+                context.require::<String>("user").unwrap()
+            )
+        }))
+        .default_context(
+            Context::new()
+                .with_data("endpoint", "https://api.example.com")
+                .with_data("tenant", "default"),
+        )
+        .model(Arc::new(SystemEchoModel))
+        .build()
+        .unwrap();
+
+    let context = Context::new()
+        .with_data("tenant", "acme")
+        .with_data("user", "ada");
+    let response = agent.run("Hello", context).await.unwrap();
+
+    assert_eq!(response, "https://api.example.com acme ada");
+}
+
+#[tokio::test]
+async fn test_agent_with_model_swaps_only_the_model() {
+    let agent = AgentBuilder::new("ab_agent")
+        .instructions("Be helpful")
+        .model(Arc::new(MockModel::new("from model A")))
+        .add_tool(Arc::new(MockTool::new("search")))
+        .build()
+        .unwrap();
+
+    let variant = agent.with_model(Arc::new(MockModel::new("from model B")));
+
+    assert_eq!(variant.name(), "ab_agent");
+    assert_eq!(variant.instructions(), Some("Be helpful"));
+    assert!(Arc::ptr_eq(&agent.tools()[0], &variant.tools()[0]));
+    let response = variant.run("Hello", Context::new()).await.unwrap();
+    assert_eq!(response, "from model B");
+    let response = agent.run("Hello", Context::new()).await.unwrap();
+    assert_eq!(response, "from model A");
+}
+
+// Collects formatted tracing output so tests can assert on emitted events
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_agent_traces_tool_execution() {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .without_time()
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let agent = AgentBuilder::new("traced_agent")
+        .model(Arc::new(ToolCallingModel::new("test_tool")))
+        .add_tool(Arc::new(MockTool::new("test_tool")))
+        .build()
+        .unwrap();
+    agent.run("Use the tool", Context::new()).await.unwrap();
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let started = logs
+        .lines()
+        .find(|line| line.contains("tool started"))
+        .expect("tool started event");
+    assert!(
+        started.contains("agent_run{agent=traced_agent}"),
+        "{}",
+        started
+    );
+    assert!(started.contains("tool=test_tool"), "{}", started);
+    assert!(started.contains(r#"arguments="{\"x\": 1}""#), "{}", started);
+    assert!(logs.contains("tool completed"));
+}
+
+// Mock model that echoes the user's input after a delay, tracking how many calls overlap
+#[derive(Default)]
+struct SlowEchoModel {
+    active: AtomicUsize,
+    max_active: AtomicUsize,
+}
+
+#[async_trait]
+impl Model for SlowEchoModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_active.fetch_max(active, Ordering::SeqCst);
+
+        let input = context.messages.last().unwrap().content.clone();
+        // Earlier inputs take longer, so they finish out of order
+        let delay = 40 - 10 * input.len() as u64;
+        tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        let tenant: String = context.context.require("tenant")?;
+        Ok(format!("{}:{}", tenant, input))
+    }
+}
+
+#[tokio::test]
+async fn test_agent_run_batch_preserves_order() {
+    let model = Arc::new(SlowEchoModel::default());
+    let agent = AgentBuilder::new("batch_agent")
+        .model(model.clone())
+        .build()
+        .unwrap();
+
+    let inputs = ["a", "bb", "ccc"]
+        .iter()
+        .map(|input| {
+            let context = Context::new().with_data("tenant", format!("t{}", input.len()));
+            (input.to_string(), context)
+        })
+        .collect();
+    let results = agent.run_batch(inputs, 2).await;
+
+    let outputs: Vec<String> = results.into_iter().map(Result::unwrap).collect();
+    assert_eq!(outputs, vec!["t1:a", "t2:bb", "t3:ccc"]);
+    assert_eq!(model.max_active.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_model_lists_nothing_by_default() {
+    assert!(
+        MockModel::new("unused")
+            .list_models()
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_agent_run_with_deadline_times_out() {
+    let model = FnModel::new(|_, _| {
+        Box::pin(async {
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            Ok("too late".to_string())
+        })
+    });
+    let agent = AgentBuilder::new("deadline_agent")
+        .model(Arc::new(model))
+        .build()
+        .unwrap();
+
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(20);
+    match agent
+        .run_with_deadline("Hello", Context::new(), deadline)
+        .await
+    {
+        Err(AgentError::ModelError(msg)) => assert_eq!(msg, "run deadline exceeded"),
+        other => panic!("Expected ModelError, got {:?}", other),
+    }
+}
+
+// Mock model that reports how many user messages it has seen
+struct TurnCountingModel;
+
+#[async_trait]
+impl Model for TurnCountingModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        let user_messages: Vec<&str> = context
+            .messages
+            .iter()
+            .filter(|m| m.role == "user")
+            .map(|m| m.content.as_str())
+            .collect();
+        Ok(user_messages.join(" + "))
+    }
+}
+
+#[tokio::test]
+async fn test_agent_run_resumable_keeps_context_on_failure() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let tool_calls = calls.clone();
+    let tool = FunctionTool::new(
+        "test_tool",
+        "Fails on its second call",
+        serde_json::json!({"type": "object"}),
+        Box::new(move |_, _| {
+            if tool_calls.fetch_add(1, Ordering::SeqCst) == 1 {
+                return Err(AgentError::ToolError("disk full".to_string()));
+            }
+            Ok(ToolResult {
+                tool_name: "test_tool".to_string(),
+                output: "first turn done".to_string(),
+                ..Default::default()
+            })
+        }),
+    );
+    let agent = AgentBuilder::new("resumable_agent")
+        .model(Arc::new(LoopingModel::default()))
+        .add_tool(Arc::new(tool))
+        .build()
+        .unwrap();
+
+    let failure = agent
+        .run_resumable("Use the tool twice", Context::new())
+        .await
+        .unwrap_err();
+
+    assert!(matches!(failure.error, AgentError::ToolError(ref msg) if msg == "disk full"));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    let contents: Vec<(&str, &str)> = failure
+        .context
+        .messages
+        .iter()
+        .map(|m| (m.role.as_str(), m.content.as_str()))
+        .collect();
+    assert_eq!(
+        contents,
+        [
+            ("user", "Use the tool twice"),
+            ("assistant", ""),
+            ("tool", "first turn done"),
+            ("assistant", ""),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_agent_with_fn_model_echoes_last_user_message() {
+    let model = FnModel::new(|context, _tools| {
+        Box::pin(async move {
+            Ok(context
+                .messages
+                .iter()
+                .rev()
+                .find(|message| message.role == "user")
+                .map(|message| message.content.clone())
+                .unwrap_or_default())
+        })
+    });
+    let agent = AgentBuilder::new("fn_model_agent")
+        .model(Arc::new(model))
+        .build()
+        .unwrap();
+
+    let result = agent.run("Echo me", Context::new()).await.unwrap();
+
+    assert_eq!(result, "Echo me");
+}
+
+#[tokio::test]
+async fn test_agent_run_session_continues_history() {
+    let store = InMemorySessionStore::new();
+    let agent = AgentBuilder::new("session_agent")
+        .instructions("Be helpful")
+        .model(Arc::new(TurnCountingModel))
+        .build()
+        .unwrap();
+
+    let first = agent.run_session("s1", "Hi", &store).await.unwrap();
+    let second = agent.run_session("s1", "Again", &store).await.unwrap();
+    let other = agent.run_session("s2", "Hello", &store).await.unwrap();
+
+    assert_eq!(first, "Hi");
+    assert_eq!(second, "Hi + Again");
+    assert_eq!(other, "Hello");
+
+    let session = store.load("s1").await.unwrap();
+    let roles: Vec<&str> = session.messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(
+        roles,
+        vec!["system", "user", "assistant", "user", "assistant"]
+    );
+}
+
+// Prompts and responses captured by a logging model
+type CallLog = Arc<std::sync::Mutex<Vec<(Vec<String>, String)>>>;
+
+#[tokio::test]
+async fn test_logging_model_captures_prompt_and_response() {
+    let log = CallLog::default();
+    let captured = log.clone();
+    let model = LoggingModel::new(
+        Arc::new(MockModel::new("Logged answer")),
+        Box::new(move |messages, response| {
+            let prompt = messages.iter().map(|m| m.to_string()).collect();
+            let response = match response {
+                Ok(Step::Message(content)) => content.clone(),
+                other => format!("{:?}", other),
+            };
+            captured.lock().unwrap().push((prompt, response));
+        }),
+    );
+    let agent = AgentBuilder::new("logged_agent")
+        .instructions("Be brief")
+        .model(Arc::new(model))
+        .build()
+        .unwrap();
+
+    let response = agent.run("Hello", Context::new()).await.unwrap();
+
+    assert_eq!(response, "Logged answer");
+    let log = log.lock().unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].0, vec!["system: Be brief", "user: Hello"]);
+    assert_eq!(log[0].1, "Logged answer");
+}
+
+#[tokio::test]
+async fn test_logging_model_passes_stream_through() {
+    let log = CallLog::default();
+    let captured = log.clone();
+    let model = LoggingModel::new(
+        Arc::new(StreamingModel),
+        Box::new(move |messages, response| {
+            let prompt = messages.iter().map(|m| m.to_string()).collect();
+            let response = match response {
+                Ok(Step::Message(content)) => content.clone(),
+                Ok(Step::ToolCalls(calls)) => format!("calls {}", calls[0].name),
+                Err(error) => error.to_string(),
+            };
+            captured.lock().unwrap().push((prompt, response));
+        }),
+    );
+    let agent = AgentBuilder::new("logged_agent")
+        .model(Arc::new(model))
+        .add_tool(Arc::new(FunctionTool::new(
+            "lookup",
+            "Looks up the answer",
+            serde_json::json!({"type": "object"}),
+            Box::new(|_, _| Ok(ToolResult::default())),
+        )))
+        .build()
+        .unwrap();
+
+    let deltas: Vec<String> = agent
+        .run_stream("What is the answer?", Context::new())
+        .map(|delta| delta.unwrap())
+        .collect()
+        .await;
+
+    assert_eq!(deltas, ["The ", "answer ", "is 42"]);
+    let log = log.lock().unwrap();
+    let responses: Vec<&str> = log.iter().map(|(_, response)| response.as_str()).collect();
+    assert_eq!(responses, ["calls lookup", "The answer is 42"]);
+    assert_eq!(log[0].0, vec!["user: What is the answer?"]);
+}
+
+async fn respond_with(model: impl Model, input: &str) -> String {
+    let mut context = RunContext::new(Context::new());
+    context.add_message("user", input);
+    model.generate_response(&mut context, &[]).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_shared_and_borrowed_models_implement_model() {
+    let shared: Arc<dyn Model> = Arc::new(MockModel::new("shared"));
+    let borrowed: &dyn Model = &shared;
+
+    assert_eq!(respond_with(shared.clone(), "Hi").await, "shared");
+    assert_eq!(respond_with(&shared, "Hi").await, "shared");
+    assert_eq!(respond_with(borrowed, "Hi").await, "shared");
+
+    // A shared model can itself be wrapped and handed to an agent
+    let agent = Agent::new("wrapped", None, Arc::new(shared), vec![]);
+    assert_eq!(agent.run("Hi", Context::new()).await.unwrap(), "shared");
+}
+
+#[test]
+fn test_agent_dry_run_unsupported_model() {
+    let agent = Agent::new(
+        "dry_agent",
+        None,
+        Arc::new(MockModel::new("unused")),
+        vec![],
+    );
+
+    match agent.dry_run("Hello", Context::new()) {
+        Err(AgentError::ModelError(msg)) => {
+            assert_eq!(msg, "this model doesn't support previewing requests")
+        }
+        other => panic!("Expected ModelError, got {:?}", other),
+    }
+}
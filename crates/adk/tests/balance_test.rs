@@ -0,0 +1,127 @@
+use adk::AgentError;
+use adk::balance::LoadBalancedModel;
+use adk::openai::{Model, OpenAI};
+use adk::types::{Context, RunContext};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn completion(content: &str) -> serde_json::Value {
+    json!({
+        "id": "chatcmpl-123",
+        "object": "chat.completion",
+        "created": 1677652288,
+        "model": "gpt-4",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop"
+        }]
+    })
+}
+
+// One model per key, all talking to the same server
+fn balanced_over_keys(server: &MockServer, keys: &[&str]) -> LoadBalancedModel {
+    let models = keys
+        .iter()
+        .map(|key| {
+            Arc::new(OpenAI::new(*key, "gpt-4").with_base_url(server.uri())) as Arc<dyn Model>
+        })
+        .collect();
+    LoadBalancedModel::new(models).unwrap()
+}
+
+async fn keys_used(server: &MockServer) -> Vec<String> {
+    server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            request.headers["authorization"]
+                .to_str()
+                .unwrap()
+                .trim_start_matches("Bearer ")
+                .to_string()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_load_balanced_model_rotates_keys() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion("ok")))
+        .mount(&server)
+        .await;
+
+    let model = balanced_over_keys(&server, &["key-1", "key-2", "key-3"]);
+    for _ in 0..4 {
+        let mut context = RunContext::new(Context::new());
+        context.add_message("user", "Hello");
+        assert_eq!(
+            model.generate_response(&mut context, &[]).await.unwrap(),
+            "ok"
+        );
+    }
+
+    assert_eq!(
+        keys_used(&server).await,
+        ["key-1", "key-2", "key-3", "key-1"]
+    );
+}
+
+#[tokio::test]
+async fn test_load_balanced_model_skips_rejected_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(header("authorization", "Bearer revoked"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "error": {
+                "message": "Incorrect API key provided",
+                "type": "invalid_request_error",
+                "param": null,
+                "code": "invalid_api_key"
+            }
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion("ok")))
+        .mount(&server)
+        .await;
+
+    let model = balanced_over_keys(&server, &["key-1", "revoked", "key-3"])
+        .with_cooldown(Duration::from_secs(60));
+    let mut results = Vec::new();
+    for _ in 0..5 {
+        let mut context = RunContext::new(Context::new());
+        context.add_message("user", "Hello");
+        results.push(model.generate_response(&mut context, &[]).await);
+    }
+
+    assert!(matches!(
+        results[1],
+        Err(AgentError::ModelErrorWithSource { .. })
+    ));
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 4);
+    assert_eq!(
+        keys_used(&server).await,
+        ["key-1", "revoked", "key-3", "key-1", "key-3"]
+    );
+}
+
+#[test]
+fn test_load_balanced_model_needs_a_model() {
+    match LoadBalancedModel::new(Vec::new()) {
+        Err(AgentError::ConfigurationError(msg)) => {
+            assert_eq!(msg, "Load balancing needs at least one model")
+        }
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
@@ -1,4 +1,7 @@
-use adk::error::{AgentError, AgentResult};
+#![allow(clippy::unnecessary_literal_unwrap)]
+
+use adk::error::{AgentError, AgentResult, ErrorKind};
+use async_openai::error::{ApiError, OpenAIError};
 
 #[test]
 fn test_model_error() {
@@ -6,6 +9,12 @@ fn test_model_error() {
     assert_eq!(error.to_string(), "Model error: Model failed");
 }
 
+#[test]
+fn test_refusal_error() {
+    let error = AgentError::Refusal("I can't help with that".to_string());
+    assert_eq!(error.to_string(), "Model refused: I can't help with that");
+}
+
 #[test]
 fn test_tool_error() {
     let error = AgentError::ToolError("Tool execution failed".to_string());
@@ -15,12 +24,30 @@ fn test_tool_error() {
     );
 }
 
+#[test]
+fn test_tool_not_found_error() {
+    let error = AgentError::ToolNotFound("search".to_string());
+    assert_eq!(error.to_string(), "Tool not found: search");
+}
+
 #[test]
 fn test_invalid_input_error() {
     let error = AgentError::InvalidInput("Invalid parameter".to_string());
     assert_eq!(error.to_string(), "Invalid input: Invalid parameter");
 }
 
+#[test]
+fn test_validation_error() {
+    let error = AgentError::ValidationError {
+        field: "a".to_string(),
+        message: "expected integer".to_string(),
+    };
+    assert_eq!(
+        error.to_string(),
+        "Validation error: field `a` expected integer"
+    );
+}
+
 #[test]
 fn test_context_error() {
     let error = AgentError::ContextError("Context is invalid".to_string());
@@ -138,3 +165,144 @@ fn test_error_variants_exhaustive() {
         assert!(!debug_str.is_empty());
     }
 }
+
+fn api_error(r#type: Option<&str>, code: Option<&str>) -> AgentError {
+    AgentError::OpenAIError(OpenAIError::ApiError(ApiError {
+        message: "request failed".to_string(),
+        r#type: r#type.map(String::from),
+        param: None,
+        code: code.map(String::from),
+    }))
+}
+
+#[test]
+fn test_error_kind_and_retryability() {
+    let json_error = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+    let cases = [
+        (
+            AgentError::ModelError("max turns exceeded".into()),
+            ErrorKind::Model,
+            false,
+        ),
+        (
+            AgentError::ToolError("failed".into()),
+            ErrorKind::Tool,
+            false,
+        ),
+        (
+            AgentError::ToolNotFound("search".into()),
+            ErrorKind::ToolNotFound,
+            false,
+        ),
+        (
+            AgentError::InvalidInput("bad".into()),
+            ErrorKind::InvalidInput,
+            false,
+        ),
+        (
+            AgentError::ValidationError {
+                field: "a".into(),
+                message: "expected integer".into(),
+            },
+            ErrorKind::InvalidInput,
+            false,
+        ),
+        (
+            AgentError::ContextError("missing".into()),
+            ErrorKind::Context,
+            false,
+        ),
+        (
+            AgentError::ConfigurationError("no model".into()),
+            ErrorKind::Configuration,
+            false,
+        ),
+        (
+            AgentError::GuardrailError("flagged".into()),
+            ErrorKind::Guardrail,
+            false,
+        ),
+        (
+            AgentError::SerializationError(json_error),
+            ErrorKind::Serialization,
+            false,
+        ),
+        (
+            AgentError::InternalError("bug".into()),
+            ErrorKind::Internal,
+            false,
+        ),
+    ];
+
+    for (error, kind, retryable) in cases {
+        assert_eq!(error.kind(), kind, "{}", error);
+        assert_eq!(error.is_retryable(), retryable, "{}", error);
+    }
+}
+
+#[test]
+fn test_openai_error_retryability() {
+    let rate_limited = api_error(Some("requests"), Some("rate_limit_exceeded"));
+    assert_eq!(rate_limited.kind(), ErrorKind::Api);
+    assert!(rate_limited.is_retryable());
+    assert!(api_error(Some("server_error"), None).is_retryable());
+    assert!(AgentError::OpenAIError(OpenAIError::StreamError("closed".into())).is_retryable());
+
+    assert!(!api_error(Some("invalid_request_error"), Some("invalid_api_key")).is_retryable());
+    // How the client reports server errors, whose body it doesn't parse
+    assert!(api_error(None, None).is_retryable());
+    assert!(!AgentError::OpenAIError(OpenAIError::InvalidArgument("bad".into())).is_retryable());
+}
+
+#[test]
+fn test_model_error_retryability_follows_its_source() {
+    let wrap = |error: OpenAIError| AgentError::model_error("Failed to generate response", error);
+    let server_error = OpenAIError::ApiError(ApiError {
+        message: "overloaded".to_string(),
+        r#type: None,
+        param: None,
+        code: None,
+    });
+    let rejected_key = OpenAIError::ApiError(ApiError {
+        message: "Incorrect API key provided".to_string(),
+        r#type: Some("invalid_request_error".to_string()),
+        param: None,
+        code: Some("invalid_api_key".to_string()),
+    });
+
+    assert!(wrap(server_error).is_retryable());
+    assert!(!wrap(rejected_key).is_retryable());
+    assert!(!wrap(OpenAIError::InvalidArgument("bad".into())).is_retryable());
+    assert!(
+        !AgentError::model_error("Failed to parse response", "unexpected shape").is_retryable()
+    );
+}
+
+#[test]
+fn test_model_error_keeps_its_source() {
+    let cause = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset");
+    let error = AgentError::model_error("Failed to generate response: connection reset", cause);
+
+    assert_eq!(
+        error.to_string(),
+        "Model error: Failed to generate response: connection reset"
+    );
+    assert_eq!(error.kind(), ErrorKind::Model);
+    assert!(error.is_retryable());
+    let source = std::error::Error::source(&error).unwrap();
+    let cause = source.downcast_ref::<std::io::Error>().unwrap();
+    assert_eq!(cause.kind(), std::io::ErrorKind::ConnectionReset);
+
+    let error = AgentError::tool_error("Failed to read notes.txt", "disk on fire");
+    assert_eq!(error.kind(), ErrorKind::Tool);
+    assert_eq!(
+        std::error::Error::source(&error).unwrap().to_string(),
+        "disk on fire"
+    );
+}
+
+#[test]
+fn test_string_errors_have_no_source() {
+    let error = AgentError::ModelError("no choices in response".into());
+    assert!(std::error::Error::source(&error).is_none());
+}
@@ -0,0 +1,28 @@
+;; A WasmTool that returns its parameters unchanged
+;;
+;; Compile with `wat2wasm echo.wat -o echo.wasm`.
+(module
+  (memory (export "memory") 1)
+
+  (data (i32.const 0) "{\"type\":\"object\",\"properties\":{\"text\":{\"type\":\"string\"}},\"required\":[\"text\"]}")
+
+  ;; Bump allocator starting after the schema
+  (global $next (mut i32) (i32.const 1024))
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+
+  ;; Pack a pointer and length as (ptr << 32) | len
+  (func $pack (param $ptr i32) (param $len i32) (result i64)
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len))))
+
+  (func (export "schema") (result i64)
+    (call $pack (i32.const 0) (i32.const 77)))
+
+  (func (export "execute") (param $ptr i32) (param $len i32) (result i64)
+    (call $pack (local.get $ptr) (local.get $len))))
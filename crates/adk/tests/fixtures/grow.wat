@@ -0,0 +1,20 @@
+;; A WasmTool whose `execute` asks for 1 GiB of memory, trapping if it is refused
+;;
+;; Compile with `wat2wasm grow.wat -o grow.wasm`.
+(module
+  (memory (export "memory") 1)
+
+  (data (i32.const 0) "{\"type\":\"object\"}")
+  (data (i32.const 32) "grown")
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 1024))
+
+  (func (export "schema") (result i64)
+    (i64.const 17))
+
+  (func (export "execute") (param $ptr i32) (param $len i32) (result i64)
+    (if (i32.eq (memory.grow (i32.const 16384)) (i32.const -1))
+      (then (unreachable)))
+    ;; "grown" at 32, packed as (ptr << 32) | len
+    (i64.const 0x0000002000000005)))
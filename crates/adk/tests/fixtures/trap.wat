@@ -0,0 +1,16 @@
+;; A WasmTool whose `execute` always traps
+;;
+;; Compile with `wat2wasm trap.wat -o trap.wasm`.
+(module
+  (memory (export "memory") 1)
+
+  (data (i32.const 0) "{\"type\":\"object\"}")
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 1024))
+
+  (func (export "schema") (result i64)
+    (i64.const 17))
+
+  (func (export "execute") (param $ptr i32) (param $len i32) (result i64)
+    (unreachable)))
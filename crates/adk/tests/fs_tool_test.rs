@@ -0,0 +1,110 @@
+use adk::AgentError;
+use adk::tool::Tool;
+use adk::tool::fs::FileSystemTool;
+use adk::types::{Context, RunContext};
+use std::path::PathBuf;
+
+// Creates `<tmp>/adk-fs-<name>-<pid>/root` with a file inside and a secret file next to it
+fn sandbox(name: &str) -> PathBuf {
+    let base = std::env::temp_dir().join(format!("adk-fs-{}-{}", name, std::process::id()));
+    let root = base.join("root");
+    std::fs::create_dir_all(root.join("notes")).unwrap();
+    std::fs::write(root.join("hello.txt"), "Hello, sandbox!").unwrap();
+    std::fs::write(base.join("secret.txt"), "top secret").unwrap();
+    root
+}
+
+async fn execute(tool: &FileSystemTool, params: &str) -> Result<String, AgentError> {
+    let mut context = RunContext::new(Context::new());
+    tool.execute(&mut context, params)
+        .await
+        .map(|result| result.output)
+}
+
+#[tokio::test]
+async fn test_file_system_tool_reads_inside_root() {
+    let root = sandbox("read");
+    let tool = FileSystemTool::new(&root).unwrap();
+
+    let output = execute(&tool, r#"{"operation": "read", "path": "hello.txt"}"#)
+        .await
+        .unwrap();
+
+    assert_eq!(output, "Hello, sandbox!");
+    std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn test_file_system_tool_rejects_traversal() {
+    let root = sandbox("traversal");
+    let tool = FileSystemTool::new(&root).unwrap();
+
+    for params in [
+        r#"{"operation": "read", "path": "../secret.txt"}"#,
+        r#"{"operation": "read", "path": "notes/../../secret.txt"}"#,
+        r#"{"operation": "write", "path": "../escaped.txt", "content": "x"}"#,
+        r#"{"operation": "list", "path": ".."}"#,
+    ] {
+        match execute(&tool, params).await {
+            Err(AgentError::ToolError(msg)) => {
+                assert!(msg.ends_with("is outside the root directory"), "{}", msg)
+            }
+            other => panic!("Expected ToolError for {}, got {:?}", params, other),
+        }
+    }
+    assert!(!root.parent().unwrap().join("escaped.txt").exists());
+    std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_file_system_tool_refuses_write_through_dangling_symlink() {
+    let root = sandbox("dangling");
+    let outside = root.parent().unwrap().join("planted.txt");
+    std::os::unix::fs::symlink(&outside, root.join("link.txt")).unwrap();
+    let tool = FileSystemTool::new(&root).unwrap();
+
+    let result = execute(
+        &tool,
+        r#"{"operation": "write", "path": "link.txt", "content": "x"}"#,
+    )
+    .await;
+
+    assert!(
+        matches!(result, Err(AgentError::ToolError(_))),
+        "{:?}",
+        result
+    );
+    assert!(!outside.exists());
+    std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn test_file_system_tool_writes_and_lists() {
+    let root = sandbox("write");
+    let tool = FileSystemTool::new(&root).unwrap();
+
+    let output = execute(
+        &tool,
+        r#"{"operation": "write", "path": "notes/todo.txt", "content": "ship it"}"#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(output, "Wrote 7 bytes to notes/todo.txt");
+    assert_eq!(
+        std::fs::read_to_string(root.join("notes/todo.txt")).unwrap(),
+        "ship it"
+    );
+
+    let listing = execute(&tool, r#"{"operation": "list"}"#).await.unwrap();
+    assert_eq!(listing, "hello.txt\nnotes/");
+    std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_file_system_tool_missing_root() {
+    match FileSystemTool::new("/definitely/not/a/real/root") {
+        Err(AgentError::ConfigurationError(msg)) => assert!(msg.starts_with("Invalid root")),
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
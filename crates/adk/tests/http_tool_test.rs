@@ -0,0 +1,165 @@
+#![cfg(feature = "http")]
+
+use adk::AgentError;
+use adk::tool::Tool;
+use adk::tool::http::{HttpTool, Method};
+use adk::types::{Context, RunContext};
+use serde_json::json;
+use wiremock::matchers::{body_json, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn user_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "id": {"type": "string"},
+            "verbose": {"type": "boolean"}
+        },
+        "required": ["id"]
+    })
+}
+
+#[tokio::test]
+async fn test_http_tool_get_returns_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/users/ada%20l"))
+        .and(query_param("verbose", "true"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"name": "Ada"}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let tool = HttpTool::new(
+        "get_user",
+        "Looks up a user",
+        Method::GET,
+        format!("{}/users/{{id}}", server.uri()),
+        user_schema(),
+    );
+    let mut context = RunContext::new(Context::new());
+    let result = tool
+        .execute(&mut context, r#"{"id": "ada l", "verbose": true}"#)
+        .await
+        .unwrap();
+
+    assert_eq!(tool.name(), "get_user");
+    assert_eq!(result.tool_name, "get_user");
+    assert_eq!(result.output, r#"{"name": "Ada"}"#);
+}
+
+#[tokio::test]
+async fn test_http_tool_post_sends_json_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/users"))
+        .and(body_json(json!({"name": "Ada"})))
+        .respond_with(ResponseTemplate::new(201).set_body_string("created"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let tool = HttpTool::new(
+        "create_user",
+        "Creates a user",
+        Method::POST,
+        format!("{}/users", server.uri()),
+        json!({"type": "object", "properties": {"name": {"type": "string"}}}),
+    );
+    let mut context = RunContext::new(Context::new());
+    let result = tool
+        .execute(&mut context, r#"{"name": "Ada"}"#)
+        .await
+        .unwrap();
+
+    assert_eq!(result.output, "created");
+}
+
+#[tokio::test]
+async fn test_http_tool_error_status() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("no such user"))
+        .mount(&server)
+        .await;
+
+    let tool = HttpTool::new(
+        "get_user",
+        "Looks up a user",
+        Method::GET,
+        format!("{}/users/{{id}}", server.uri()),
+        user_schema(),
+    );
+    let mut context = RunContext::new(Context::new());
+    match tool.execute(&mut context, r#"{"id": "42"}"#).await {
+        Err(AgentError::ToolError(msg)) => assert_eq!(msg, "HTTP 404 Not Found: no such user"),
+        other => panic!("Expected ToolError, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_http_tool_network_error() {
+    let tool = HttpTool::new(
+        "get_user",
+        "Looks up a user",
+        Method::GET,
+        "http://127.0.0.1:1/users/{id}",
+        user_schema(),
+    );
+    let mut context = RunContext::new(Context::new());
+    match tool.execute(&mut context, r#"{"id": "42"}"#).await {
+        Err(error @ AgentError::ToolErrorWithSource { .. }) => {
+            assert!(
+                error
+                    .to_string()
+                    .starts_with("Tool execution error: HTTP request failed")
+            );
+            let source = std::error::Error::source(&error).unwrap();
+            assert!(source.is::<reqwest::Error>());
+        }
+        other => panic!("Expected ToolError, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_http_tool_requires_url_arguments() {
+    let server = MockServer::start().await;
+    let tool = HttpTool::new(
+        "get_user",
+        "Looks up a user",
+        Method::GET,
+        format!("{}/users/{{id}}", server.uri()),
+        user_schema(),
+    );
+    let mut context = RunContext::new(Context::new());
+
+    match tool.execute(&mut context, r#"{"verbose": true}"#).await {
+        Err(AgentError::InvalidInput(msg)) => assert_eq!(msg, "Missing URL argument: id"),
+        other => panic!("Expected InvalidInput, got {:?}", other),
+    }
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_http_tool_rejects_dot_segments() {
+    let server = MockServer::start().await;
+    let tool = HttpTool::new(
+        "get_user",
+        "Looks up a user",
+        Method::GET,
+        format!("{}/users/{{id}}/profile", server.uri()),
+        user_schema(),
+    );
+    let mut context = RunContext::new(Context::new());
+
+    for id in [".", ".."] {
+        let params = json!({ "id": id }).to_string();
+        match tool.execute(&mut context, &params).await {
+            Err(AgentError::InvalidInput(msg)) => {
+                assert_eq!(msg, format!("Invalid URL argument id: {:?}", id))
+            }
+            other => panic!("Expected InvalidInput, got {:?}", other),
+        }
+    }
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
@@ -1,3 +1,9 @@
+#![allow(
+    unused_imports,
+    clippy::assertions_on_constants,
+    clippy::unnecessary_literal_unwrap
+)]
+
 use adk::prelude::*;
 use adk::{AgentError, Tool, ToolResult};
 
@@ -40,6 +46,7 @@ fn test_macro_availability() {
         Ok(ToolResult {
             tool_name: "test".to_string(),
             output: "test".to_string(),
+            ..Default::default()
         })
     });
 
@@ -91,6 +98,7 @@ fn test_tool_result_integration() {
     let result = ToolResult {
         tool_name: "integration_test".to_string(),
         output: "test output".to_string(),
+        ..Default::default()
     };
 
     assert_eq!(result.tool_name, "integration_test");
@@ -0,0 +1,1639 @@
+use adk::agent::{AgentBuilder, RunEvent};
+use adk::openai::{ApiMode, Model, ModelParams, OpenAI, Step, StepChunk, ToolCallAccumulator};
+use adk::retry::Backoff;
+use adk::tool::ToolCall;
+use adk::tool::{FunctionTool, Tool, ToolResult};
+use adk::types::{ContentPart, Context, RunContext};
+use adk::{AgentError, function_tool};
+use async_openai::config::{Config, OpenAIConfig};
+use async_openai::types::{
+    ChatCompletionMessageToolCallChunk, ResponseFormat, ResponseFormatJsonSchema,
+};
+use futures::StreamExt;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn echo_tool() -> impl Tool {
+    function_tool!(
+        "echo",
+        "Echoes the input",
+        serde_json::json!({
+            "type": "object",
+            "properties": {"text": {"type": "string"}},
+            "required": ["text"]
+        }),
+        |_context, params| -> Result<ToolResult, AgentError> {
+            Ok(ToolResult {
+                tool_name: "echo".to_string(),
+                output: params.to_string(),
+                ..Default::default()
+            })
+        }
+    )
+}
+
+fn run_context() -> RunContext {
+    let mut context = RunContext::new(Context::new());
+    context.add_message("system", "You are helpful");
+    context.add_message("user", "Hello");
+    context
+}
+
+#[test]
+fn test_create_request_defaults() {
+    let model = OpenAI::new("test-key", "gpt-4");
+    let request = model.create_request(&run_context(), &[]);
+    assert_eq!(request.temperature, Some(0.7));
+
+    let request = serde_json::to_value(request).unwrap();
+    assert_eq!(request["model"], "gpt-4");
+    assert!(request.get("max_tokens").is_none());
+    assert!(request.get("seed").is_none());
+    assert!(request.get("stop").is_none());
+    assert!(request.get("top_p").is_none());
+    assert!(request.get("presence_penalty").is_none());
+    assert!(request.get("frequency_penalty").is_none());
+    assert_eq!(request["messages"].as_array().unwrap().len(), 2);
+    assert_eq!(request["messages"][0]["role"], "system");
+    assert_eq!(request["messages"][1]["role"], "user");
+}
+
+#[test]
+fn test_create_request_maps_developer_messages() {
+    let model = OpenAI::new("test-key", "gpt-4o");
+    let context = RunContext::builder()
+        .developer("Answer in French")
+        .user("Hello")
+        .build();
+
+    let request = serde_json::to_value(model.create_request(&context, &[])).unwrap();
+
+    assert_eq!(
+        request["messages"],
+        json!([
+            {"role": "developer", "content": "Answer in French"},
+            {"role": "user", "content": "Hello"}
+        ])
+    );
+}
+
+#[test]
+fn test_create_request_with_sampling_params() {
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_temperature(0.2)
+        .with_max_tokens(256)
+        .with_top_p(0.9)
+        .with_presence_penalty(0.5)
+        .with_frequency_penalty(-0.5);
+    let request = model.create_request(&run_context(), &[]);
+    assert_eq!(request.temperature, Some(0.2));
+    assert_eq!(request.top_p, Some(0.9));
+    assert_eq!(request.presence_penalty, Some(0.5));
+    assert_eq!(request.frequency_penalty, Some(-0.5));
+
+    let request = serde_json::to_value(request).unwrap();
+    assert_eq!(request["max_tokens"], 256);
+}
+
+#[test]
+fn test_create_request_max_completion_tokens_by_model() {
+    let reasoning = OpenAI::new("test-key", "o1-mini").with_max_completion_tokens(512);
+    let request = serde_json::to_value(reasoning.create_request(&run_context(), &[])).unwrap();
+    assert_eq!(request["max_completion_tokens"], 512);
+    assert!(request.get("max_tokens").is_none());
+
+    let chat = OpenAI::new("test-key", "gpt-4").with_max_completion_tokens(512);
+    let request = serde_json::to_value(chat.create_request(&run_context(), &[])).unwrap();
+    assert_eq!(request["max_tokens"], 512);
+    assert!(request.get("max_completion_tokens").is_none());
+
+    // A plain max_tokens limit is translated for reasoning models too
+    let reasoning = OpenAI::new("test-key", "o3").with_max_tokens(256);
+    let request = serde_json::to_value(reasoning.create_request(&run_context(), &[])).unwrap();
+    assert_eq!(request["max_completion_tokens"], 256);
+    assert!(request.get("max_tokens").is_none());
+}
+
+#[test]
+fn test_create_request_with_seed() {
+    let model = OpenAI::new("test-key", "gpt-4").with_seed(42);
+    let request = model.create_request(&run_context(), &[]);
+    assert_eq!(request.seed, Some(42));
+
+    let request = serde_json::to_value(request).unwrap();
+    assert_eq!(request["seed"], 42);
+}
+
+#[test]
+fn test_create_request_with_stop() {
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_stop(vec!["END".to_string(), "\n\n".to_string()])
+        .unwrap();
+    let request = serde_json::to_value(model.create_request(&run_context(), &[])).unwrap();
+
+    assert_eq!(request["stop"], json!(["END", "\n\n"]));
+}
+
+#[test]
+fn test_with_stop_rejects_too_many_sequences() {
+    let stop = ["a", "b", "c", "d", "e"].map(String::from).to_vec();
+    match OpenAI::new("test-key", "gpt-4").with_stop(stop) {
+        Err(AgentError::ConfigurationError(msg)) => {
+            assert_eq!(msg, "At most 4 stop sequences are allowed, got 5")
+        }
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
+
+#[test]
+fn test_create_request_with_logit_bias() {
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_logit_bias(HashMap::from([
+            ("50256".to_string(), -100),
+            ("1234".to_string(), 5),
+        ]))
+        .unwrap();
+    let request = serde_json::to_value(model.create_request(&run_context(), &[])).unwrap();
+
+    assert_eq!(request["logit_bias"], json!({"50256": -100, "1234": 5}));
+}
+
+#[test]
+fn test_with_logit_bias_rejects_out_of_range_values() {
+    let logit_bias = HashMap::from([("50256".to_string(), 101)]);
+    match OpenAI::new("test-key", "gpt-4").with_logit_bias(logit_bias) {
+        Err(AgentError::ConfigurationError(msg)) => {
+            assert_eq!(
+                msg,
+                "Logit bias for token 50256 must be between -100 and 100, got 101"
+            )
+        }
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
+
+#[test]
+fn test_create_request_skips_hidden_messages() {
+    let mut context = run_context();
+    context.add_hidden_message("assistant", "internal: cache warmed");
+    context.add_message("user", "Still there?");
+
+    let model = OpenAI::new("test-key", "gpt-4");
+    let request = serde_json::to_value(model.create_request(&context, &[])).unwrap();
+
+    let messages = request["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 3);
+    assert!(
+        messages
+            .iter()
+            .all(|m| m["content"] != "internal: cache warmed")
+    );
+    assert_eq!(context.messages.len(), 4);
+    assert!(context.messages[2].hidden);
+}
+
+#[test]
+fn test_create_request_pairs_tool_calls_with_results() {
+    let mut context = run_context();
+    context.add_tool_calls(vec![ToolCall {
+        id: "call_abc".to_string(),
+        name: "echo".to_string(),
+        arguments: r#"{"text":"hi"}"#.to_string(),
+    }]);
+    context.add_tool_result(ToolResult {
+        tool_name: "echo".to_string(),
+        output: "hi".to_string(),
+        call_id: Some("call_abc".to_string()),
+        ..Default::default()
+    });
+
+    let model = OpenAI::new("test-key", "gpt-4");
+    let request = serde_json::to_value(model.create_request(&context, &[])).unwrap();
+
+    let messages = request["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 4);
+    assert_eq!(
+        messages[2],
+        json!({
+            "role": "assistant",
+            "tool_calls": [{
+                "id": "call_abc",
+                "type": "function",
+                "function": {"name": "echo", "arguments": "{\"text\":\"hi\"}"}
+            }]
+        })
+    );
+    assert_eq!(
+        messages[3],
+        json!({"role": "tool", "content": "hi", "tool_call_id": "call_abc"})
+    );
+}
+
+#[test]
+fn test_create_request_with_tools() {
+    let model = OpenAI::new("test-key", "gpt-4").with_temperature(1.0);
+    let tool = echo_tool();
+    let request = serde_json::to_value(model.create_request(&run_context(), &[&tool])).unwrap();
+
+    assert_eq!(request["tool_choice"], "auto");
+    assert_eq!(request["temperature"], 1.0);
+    let tools = request["tools"].as_array().unwrap();
+    assert_eq!(tools.len(), 1);
+    assert_eq!(tools[0]["type"], "function");
+    assert_eq!(tools[0]["function"]["name"], "echo");
+    assert_eq!(tools[0]["function"]["description"], "Echoes the input");
+    assert_eq!(tools[0]["function"]["parameters"]["required"][0], "text");
+}
+
+fn completion_response(message: serde_json::Value) -> serde_json::Value {
+    json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 0,
+        "model": "gpt-4",
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": "stop"
+        }]
+    })
+}
+
+#[test]
+fn test_with_base_url() {
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url("http://localhost:4000/v1");
+
+    assert_eq!(model.config().api_base(), "http://localhost:4000/v1");
+    assert_eq!(
+        model.config().url("/chat/completions"),
+        "http://localhost:4000/v1/chat/completions"
+    );
+}
+
+#[test]
+fn test_with_config() {
+    let config = OpenAIConfig::new()
+        .with_api_key("gateway-key")
+        .with_api_base("https://gateway.example.com/v1");
+    let model = OpenAI::with_config(config, "gpt-4");
+
+    assert_eq!(model.config().api_base(), "https://gateway.example.com/v1");
+    assert_eq!(model.create_request(&run_context(), &[]).model, "gpt-4");
+}
+
+#[tokio::test]
+async fn test_generate_response_uses_base_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(header("authorization", "Bearer test-key"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": "Hello from the gateway"
+            }))),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let mut context = run_context();
+    let response = model.generate_response(&mut context, &[]).await.unwrap();
+
+    assert_eq!(response, "Hello from the gateway");
+}
+
+#[test]
+fn test_create_request_without_tools_omits_tool_choice() {
+    for api_mode in [ApiMode::Tools, ApiMode::Functions] {
+        let model = OpenAI::new("test-key", "gpt-4").with_api_mode(api_mode);
+        let request = serde_json::to_value(model.create_request(&run_context(), &[])).unwrap();
+
+        assert!(request.get("tools").is_none());
+        assert!(request.get("tool_choice").is_none());
+        assert!(request.get("functions").is_none());
+        assert!(request.get("function_call").is_none());
+    }
+}
+
+#[test]
+fn test_create_request_functions_mode() {
+    let model = OpenAI::new("test-key", "gpt-4").with_api_mode(ApiMode::Functions);
+    let tool = echo_tool();
+    let request = serde_json::to_value(model.create_request(&run_context(), &[&tool])).unwrap();
+
+    assert!(request.get("tools").is_none());
+    assert!(request.get("tool_choice").is_none());
+    assert_eq!(request["function_call"], "auto");
+    assert_eq!(request["functions"][0]["name"], "echo");
+    assert_eq!(request["functions"][0]["parameters"]["required"][0], "text");
+}
+
+#[test]
+fn test_create_request_forces_named_function() {
+    let tool = echo_tool();
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_api_mode(ApiMode::Functions)
+        .with_forced_function("echo");
+    let request = serde_json::to_value(model.create_request(&run_context(), &[&tool])).unwrap();
+    assert_eq!(request["function_call"], json!({"name": "echo"}));
+
+    let model = OpenAI::new("test-key", "gpt-4").with_forced_function("echo");
+    let request = serde_json::to_value(model.create_request(&run_context(), &[&tool])).unwrap();
+    assert_eq!(
+        request["tool_choice"],
+        json!({"type": "function", "function": {"name": "echo"}})
+    );
+}
+
+#[tokio::test]
+async fn test_generate_step_tools_mode() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_abc",
+                    "type": "function",
+                    "function": {"name": "echo", "arguments": "{\"text\":\"hi\"}"}
+                }]
+            }))),
+        )
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let tool = echo_tool();
+    let step = model
+        .generate_step(&mut run_context(), &[&tool])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        step,
+        Step::ToolCalls(vec![ToolCall {
+            id: "call_abc".to_string(),
+            name: "echo".to_string(),
+            arguments: r#"{"text":"hi"}"#.to_string(),
+        }])
+    );
+    let request: serde_json::Value = server.received_requests().await.unwrap()[0]
+        .body_json()
+        .unwrap();
+    assert_eq!(request["tools"][0]["function"]["name"], "echo");
+}
+
+#[tokio::test]
+async fn test_generate_step_leaves_tool_calls_to_the_caller() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_abc",
+                    "type": "function",
+                    "function": {"name": "delete_file", "arguments": "{\"path\":\"a.txt\"}"}
+                }]
+            }))),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let tool_calls = calls.clone();
+    let tool = FunctionTool::new(
+        "delete_file",
+        "Deletes a file",
+        json!({"type": "object", "properties": {"path": {"type": "string"}}}),
+        Box::new(move |_, _| {
+            tool_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(ToolResult::default())
+        }),
+    );
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let mut context = run_context();
+
+    let step = model.generate_step(&mut context, &[&tool]).await.unwrap();
+
+    assert!(matches!(&step, Step::ToolCalls(tool_calls) if tool_calls[0].name == "delete_file"));
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    assert_eq!(context.messages.len(), 2);
+}
+
+#[tokio::test]
+async fn test_generate_step_functions_mode() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": null,
+                "function_call": {"name": "echo", "arguments": "{\"text\":\"hi\"}"}
+            }))),
+        )
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_api_mode(ApiMode::Functions);
+    let tool = echo_tool();
+    let step = model
+        .generate_step(&mut run_context(), &[&tool])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        step,
+        Step::ToolCalls(vec![ToolCall {
+            id: String::new(),
+            name: "echo".to_string(),
+            arguments: r#"{"text":"hi"}"#.to_string(),
+        }])
+    );
+    let request: serde_json::Value = server.received_requests().await.unwrap()[0]
+        .body_json()
+        .unwrap();
+    assert_eq!(request["functions"][0]["name"], "echo");
+    assert!(request.get("tools").is_none());
+}
+
+#[test]
+fn test_create_request_tool_message_with_call_id() {
+    let model = OpenAI::new("test-key", "gpt-4");
+    let mut context = run_context();
+    context.add_tool_result(ToolResult {
+        tool_name: "echo".to_string(),
+        output: "hi".to_string(),
+        call_id: Some("call_abc".to_string()),
+        ..Default::default()
+    });
+    context.add_tool_message("echo", "legacy");
+
+    let request = serde_json::to_value(model.create_request(&context, &[])).unwrap();
+
+    assert_eq!(
+        request["messages"][2],
+        json!({"role": "tool", "content": "hi", "tool_call_id": "call_abc"})
+    );
+    assert_eq!(
+        request["messages"][3],
+        json!({"role": "function", "content": "legacy", "name": "echo"})
+    );
+}
+
+#[tokio::test]
+async fn test_generate_candidates_returns_all_choices() {
+    let server = MockServer::start().await;
+    let choices: Vec<serde_json::Value> = ["Red", "Green", "Blue"]
+        .iter()
+        .enumerate()
+        .map(|(index, content)| {
+            json!({
+                "index": index,
+                "message": {"role": "assistant", "content": content},
+                "finish_reason": "stop"
+            })
+        })
+        .collect();
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(body_partial_json(json!({"n": 3})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4",
+            "choices": choices
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let candidates = model
+        .generate_candidates(&run_context(), &[], 3)
+        .await
+        .unwrap();
+
+    assert_eq!(candidates, vec!["Red", "Green", "Blue"]);
+}
+
+#[tokio::test]
+async fn test_generate_step_refusal() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": null,
+                "refusal": "I can't help with that."
+            }))),
+        )
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    match model.generate_response(&mut run_context(), &[]).await {
+        Err(AgentError::Refusal(refusal)) => assert_eq!(refusal, "I can't help with that."),
+        other => panic!("Expected Refusal, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_agents_sharing_model_use_own_temperature() {
+    let server = MockServer::start().await;
+    for (temperature, content) in [(0.5, "careful"), (1.5, "creative")] {
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(json!({"temperature": temperature})))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                    "role": "assistant",
+                    "content": content
+                }))),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let model = Arc::new(OpenAI::new("test-key", "gpt-4").with_base_url(server.uri()));
+    let careful = AgentBuilder::new("careful")
+        .model(model.clone())
+        .temperature(0.5)
+        .build()
+        .unwrap();
+    let creative = AgentBuilder::new("creative")
+        .model(model)
+        .temperature(1.5)
+        .build()
+        .unwrap();
+
+    assert_eq!(careful.run("Hi", Context::new()).await.unwrap(), "careful");
+    assert_eq!(
+        creative.run("Hi", Context::new()).await.unwrap(),
+        "creative"
+    );
+}
+
+#[tokio::test]
+async fn test_generate_step_without_choices() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4",
+            "choices": []
+        })))
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    match model.generate_step(&mut run_context(), &[]).await {
+        Err(AgentError::ModelError(msg)) => assert_eq!(msg, "no choices in response"),
+        other => panic!("Expected ModelError, got {:?}", other),
+    }
+}
+
+fn tool_call_chunk(
+    index: u32,
+    id: Option<&str>,
+    name: Option<&str>,
+    arguments: &str,
+) -> ChatCompletionMessageToolCallChunk {
+    serde_json::from_value(json!({
+        "index": index,
+        "id": id,
+        "type": id.map(|_| "function"),
+        "function": {"name": name, "arguments": arguments}
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_tool_call_accumulator_reassembles_fragments() {
+    let chunks = [
+        tool_call_chunk(0, Some("call_a"), Some("search"), ""),
+        tool_call_chunk(0, None, None, r#"{"que"#),
+        tool_call_chunk(1, Some("call_b"), Some("echo"), r#"{"text""#),
+        tool_call_chunk(0, None, None, r#"ry": "rust"#),
+        tool_call_chunk(1, None, None, r#": "hi"}"#),
+        tool_call_chunk(0, None, None, r#" adk"}"#),
+    ];
+
+    let mut accumulator = ToolCallAccumulator::new();
+    assert!(accumulator.is_empty());
+    for chunk in &chunks {
+        accumulator.push(chunk);
+    }
+    let tool_calls = accumulator.finish();
+
+    assert_eq!(
+        tool_calls,
+        vec![
+            ToolCall {
+                id: "call_a".to_string(),
+                name: "search".to_string(),
+                arguments: r#"{"query": "rust adk"}"#.to_string(),
+            },
+            ToolCall {
+                id: "call_b".to_string(),
+                name: "echo".to_string(),
+                arguments: r#"{"text": "hi"}"#.to_string(),
+            },
+        ]
+    );
+    let arguments: serde_json::Value = serde_json::from_str(&tool_calls[0].arguments).unwrap();
+    assert_eq!(arguments["query"], "rust adk");
+}
+
+#[tokio::test]
+async fn test_generate_response_unknown_tool() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_abc",
+                    "type": "function",
+                    "function": {"name": "made_up", "arguments": "{}"}
+                }]
+            }))),
+        )
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let tool = echo_tool();
+    match model.generate_response(&mut run_context(), &[&tool]).await {
+        Err(AgentError::ToolNotFound(name)) => assert_eq!(name, "made_up"),
+        other => panic!("Expected ToolNotFound, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_request_interceptor_modifies_sent_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(body_partial_json(json!({"user": "test"})))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": "intercepted"
+            }))),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_request_interceptor(Box::new(|request| {
+            request.user = Some("test".to_string());
+        }));
+    let response = model
+        .generate_response(&mut run_context(), &[])
+        .await
+        .unwrap();
+
+    assert_eq!(response, "intercepted");
+}
+
+#[test]
+fn test_agent_dry_run_builds_full_request() {
+    let agent = AgentBuilder::new("dry_agent")
+        .instructions("You are helpful")
+        .model(Arc::new(OpenAI::new("test-key", "gpt-4")))
+        .add_tool(Arc::new(echo_tool()))
+        .temperature(1.5)
+        .build()
+        .unwrap();
+
+    let request = agent.dry_run("Echo hi", Context::new()).unwrap();
+
+    assert_eq!(request["model"], "gpt-4");
+    assert_eq!(request["temperature"], 1.5);
+    assert_eq!(
+        request["messages"],
+        json!([
+            {"role": "system", "content": "You are helpful"},
+            {"role": "user", "content": "Echo hi"}
+        ])
+    );
+    assert_eq!(request["tools"][0]["function"]["name"], "echo");
+}
+
+#[tokio::test]
+async fn test_agent_assistant_prefill_is_sent_last() {
+    let server = MockServer::start().await;
+    mount_reply(&server, r#""answer": 42}"#, 1).await;
+
+    let agent = AgentBuilder::new("json_agent")
+        .model(Arc::new(
+            OpenAI::new("test-key", "gpt-4").with_base_url(server.uri()),
+        ))
+        .assistant_prefill("{")
+        .build()
+        .unwrap();
+
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Answer in JSON");
+    let response = agent.run_with_context(&mut run_context).await.unwrap();
+
+    let request: serde_json::Value = server.received_requests().await.unwrap()[0]
+        .body_json()
+        .unwrap();
+    assert_eq!(
+        request["messages"],
+        json!([
+            {"role": "user", "content": "Answer in JSON"},
+            {"role": "assistant", "content": "{"}
+        ])
+    );
+    assert_eq!(response, r#"{"answer": 42}"#);
+    // The prefill and completion are kept as one answer
+    assert_eq!(run_context.messages.len(), 2);
+    assert_eq!(run_context.messages[1].content, r#"{"answer": 42}"#);
+}
+
+// Serves chat completions slowly over raw TCP, tracking the most requests in flight at once
+async fn start_counting_server(in_flight: Arc<AtomicUsize>, peak: Arc<AtomicUsize>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let in_flight = in_flight.clone();
+            let peak = peak.clone();
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                // Read the headers, then as much body as they announce
+                let body_len = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let content_length = text[..end]
+                            .lines()
+                            .find_map(|line| {
+                                line.to_ascii_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|len| len.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        break end + 4 + content_length;
+                    }
+                };
+                while request.len() < body_len {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                let body = completion_response(json!({"role": "assistant", "content": "done"}));
+                let body = body.to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+    format!("http://{}", address)
+}
+
+#[tokio::test]
+async fn test_max_concurrency_bounds_in_flight_requests() {
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let base_url = start_counting_server(in_flight, peak.clone()).await;
+
+    let model = Arc::new(
+        OpenAI::new("test-key", "gpt-4")
+            .with_base_url(base_url)
+            .with_max_concurrency(2)
+            .unwrap(),
+    );
+    let calls: Vec<_> = (0..6)
+        .map(|_| {
+            let model = model.clone();
+            tokio::spawn(async move {
+                let mut context = run_context();
+                model.generate_response(&mut context, &[]).await
+            })
+        })
+        .collect();
+    for call in calls {
+        assert_eq!(call.await.unwrap().unwrap(), "done");
+    }
+
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_with_max_concurrency_rejects_zero() {
+    match OpenAI::new("test-key", "gpt-4").with_max_concurrency(0) {
+        Err(AgentError::ConfigurationError(msg)) => {
+            assert_eq!(msg, "Max concurrency must be at least 1")
+        }
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
+
+#[tokio::test]
+async fn test_retry_budget_retries_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("overloaded"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_reply(&server, "recovered", 1).await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_retry_budget(10)
+        .unwrap();
+    let mut context = run_context();
+    let response = model.generate_response(&mut context, &[]).await.unwrap();
+    assert_eq!(response, "recovered");
+}
+
+// Records the attempts it was asked about, answering without a wait
+struct RecordingBackoff(Arc<Mutex<Vec<usize>>>);
+
+impl Backoff for RecordingBackoff {
+    fn next_delay(&mut self, attempt: usize) -> Duration {
+        self.0.lock().unwrap().push(attempt);
+        Duration::ZERO
+    }
+}
+
+#[tokio::test]
+async fn test_retries_wait_as_backoff_decides() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("overloaded"))
+        .up_to_n_times(2)
+        .mount(&server)
+        .await;
+    mount_reply(&server, "recovered", 1).await;
+
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_retry_budget(10)
+        .unwrap()
+        .with_backoff(Box::new(RecordingBackoff(attempts.clone())));
+    let mut context = run_context();
+    let response = model.generate_response(&mut context, &[]).await.unwrap();
+
+    assert_eq!(response, "recovered");
+    assert_eq!(*attempts.lock().unwrap(), [1, 2]);
+}
+
+#[tokio::test]
+async fn test_backoff_applies_without_retry_budget() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("overloaded"))
+        .up_to_n_times(2)
+        .mount(&server)
+        .await;
+    mount_reply(&server, "recovered", 1).await;
+
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_backoff(Box::new(RecordingBackoff(attempts.clone())));
+    let mut context = run_context();
+    let response = model.generate_response(&mut context, &[]).await.unwrap();
+
+    assert_eq!(response, "recovered");
+    assert_eq!(*attempts.lock().unwrap(), [1, 2]);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_retry_budget_caps_retries_across_calls() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("overloaded"))
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_retry_budget(3)
+        .unwrap();
+    for _ in 0..5 {
+        let mut context = run_context();
+        assert!(model.generate_response(&mut context, &[]).await.is_err());
+    }
+
+    // One attempt per call, plus the three retries the budget allows between them
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 5 + 3);
+}
+
+#[test]
+fn test_with_retry_budget_rejects_zero() {
+    match OpenAI::new("test-key", "gpt-4").with_retry_budget(0) {
+        Err(AgentError::ConfigurationError(msg)) => {
+            assert_eq!(msg, "Retry budget must be at least 1 per minute")
+        }
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
+
+#[tokio::test]
+async fn test_embed_returns_vectors_in_input_order() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/embeddings"))
+        .and(body_partial_json(json!({
+            "model": "text-embedding-3-small",
+            "input": ["first", "second"]
+        })))
+        // Out of order on purpose: the index decides the position
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "list",
+            "model": "text-embedding-3-small",
+            "data": [
+                {"object": "embedding", "index": 1, "embedding": [0.4, 0.5, 0.6]},
+                {"object": "embedding", "index": 0, "embedding": [0.1, 0.2, 0.3]}
+            ],
+            "usage": {"prompt_tokens": 2, "total_tokens": 2}
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let embeddings = model
+        .embed(&["first".to_string(), "second".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(embeddings, vec![vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6]]);
+}
+
+fn answer_format() -> ResponseFormat {
+    ResponseFormat::JsonSchema {
+        json_schema: ResponseFormatJsonSchema {
+            description: None,
+            name: "answer".to_string(),
+            schema: Some(json!({
+                "type": "object",
+                "properties": {"answer": {"type": "integer"}},
+                "required": ["answer"]
+            })),
+            strict: None,
+        },
+    }
+}
+
+async fn mount_reply(server: &MockServer, content: &str, times: u64) {
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": content
+            }))),
+        )
+        .up_to_n_times(times)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_generate_response_retries_invalid_json_once() {
+    let server = MockServer::start().await;
+    mount_reply(&server, "The answer is 42", 1).await;
+    mount_reply(&server, r#"{"answer": 42}"#, 1).await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_response_format(answer_format())
+        .with_json_retries(2);
+    let mut context = run_context();
+    let response = model.generate_response(&mut context, &[]).await.unwrap();
+
+    assert_eq!(response, r#"{"answer": 42}"#);
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let retry: serde_json::Value = requests[1].body_json().unwrap();
+    assert_eq!(retry["response_format"]["type"], "json_schema");
+    let messages = retry["messages"].as_array().unwrap();
+    assert_eq!(messages[2]["content"], "The answer is 42");
+    assert!(
+        messages[3]["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("Your last response was rejected because the output is not valid JSON")
+    );
+}
+
+#[tokio::test]
+async fn test_generate_response_rejects_schema_mismatch_after_retries() {
+    let server = MockServer::start().await;
+    mount_reply(&server, r#"{"answer": "forty-two"}"#, 2).await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_response_format(answer_format())
+        .with_json_retries(1);
+    let mut context = run_context();
+
+    match model.generate_response(&mut context, &[]).await {
+        Err(AgentError::InvalidInput(msg)) => assert_eq!(
+            msg,
+            "Invalid model output: value at $.answer is not of type integer"
+        ),
+        other => panic!("Expected InvalidInput, got {:?}", other),
+    }
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_list_models() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/models"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "list",
+            "data": [
+                {"id": "gpt-4o", "object": "model", "created": 0, "owned_by": "openai"},
+                {"id": "gpt-4o-mini", "object": "model", "created": 0, "owned_by": "openai"},
+                {"id": "o3-mini", "object": "model", "created": 0, "owned_by": "openai"}
+            ]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4o").with_base_url(server.uri());
+
+    assert_eq!(model.model_name(), Some("gpt-4o"));
+    assert_eq!(
+        model.list_models().await.unwrap(),
+        ["gpt-4o", "gpt-4o-mini", "o3-mini"]
+    );
+}
+
+#[tokio::test]
+async fn test_reasoning_is_captured_apart_from_content() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "o3-mini",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "4",
+                    "reasoning_content": "2 + 2 is 4."
+                },
+                "finish_reason": "stop"
+            }]
+        })))
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "o3-mini").with_base_url(server.uri());
+    let mut context = RunContext::new(Context::new());
+    context.add_message("user", "What is 2 + 2?");
+
+    let response = model.generate_response(&mut context, &[]).await.unwrap();
+
+    assert_eq!(response, "4");
+    assert_eq!(context.reasoning.as_deref(), Some("2 + 2 is 4."));
+}
+
+#[tokio::test]
+async fn test_generate_response_records_tool_call_turn() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_abc",
+                    "type": "function",
+                    "function": {"name": "echo", "arguments": "{\"text\":\"hi\"}"}
+                }]
+            }))),
+        )
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    mount_reply(&server, "Done", 1).await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let tool = echo_tool();
+    let mut context = run_context();
+    let response = model
+        .generate_response(&mut context, &[&tool])
+        .await
+        .unwrap();
+
+    assert_eq!(response, "Done");
+    let roles: Vec<&str> = context.messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, ["system", "user", "assistant", "tool"]);
+    assert_eq!(context.messages[2].tool_calls[0].id, "call_abc");
+    assert_eq!(
+        context.messages[3].tool_call_id.as_deref(),
+        Some("call_abc")
+    );
+
+    // The follow-up request replays the call before its result
+    let request: serde_json::Value = server.received_requests().await.unwrap()[1]
+        .body_json()
+        .unwrap();
+    assert_eq!(request["messages"][2]["tool_calls"][0]["id"], "call_abc");
+    assert_eq!(request["messages"][3]["tool_call_id"], "call_abc");
+}
+
+#[tokio::test]
+async fn test_agent_sends_tool_image_as_image_content() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(completion_response(json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_abc",
+                    "type": "function",
+                    "function": {"name": "screenshot", "arguments": "{}"}
+                }]
+            }))),
+        )
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    mount_reply(&server, "A login form", 1).await;
+
+    let agent = AgentBuilder::new("vision_agent")
+        .model(Arc::new(
+            OpenAI::new("test-key", "gpt-4o").with_base_url(server.uri()),
+        ))
+        .add_tool(Arc::new(FunctionTool::new(
+            "screenshot",
+            "Takes a screenshot",
+            json!({"type": "object"}),
+            Box::new(|_, _| {
+                Ok(ToolResult {
+                    tool_name: "screenshot".to_string(),
+                    output: "Screenshot taken".to_string(),
+                    parts: vec![ContentPart::image_base64("image/png", "iVBORw0KGgo=")],
+                    ..Default::default()
+                })
+            }),
+        )))
+        .build()
+        .unwrap();
+    let answer = agent
+        .run("What is on screen?", Context::new())
+        .await
+        .unwrap();
+    assert_eq!(answer, "A login form");
+
+    let request: serde_json::Value = server.received_requests().await.unwrap()[1]
+        .body_json()
+        .unwrap();
+    let messages = request["messages"].as_array().unwrap();
+    let tool_message = &messages[messages.len() - 2];
+    assert_eq!(tool_message["role"], "tool");
+    assert_eq!(tool_message["content"], "Screenshot taken");
+    let image_message = &messages[messages.len() - 1];
+    assert_eq!(image_message["role"], "user");
+    assert_eq!(image_message["content"][1]["type"], "image_url");
+    assert_eq!(
+        image_message["content"][1]["image_url"]["url"],
+        "data:image/png;base64,iVBORw0KGgo="
+    );
+}
+
+#[tokio::test]
+async fn test_agent_run_events_report_usage_after_each_turn() {
+    let server = MockServer::start().await;
+    let mut tool_turn = completion_response(json!({
+        "role": "assistant",
+        "content": null,
+        "tool_calls": [{
+            "id": "call_abc",
+            "type": "function",
+            "function": {"name": "echo", "arguments": "{\"text\":\"hi\"}"}
+        }]
+    }));
+    tool_turn["usage"] = json!({"prompt_tokens": 20, "completion_tokens": 10, "total_tokens": 30});
+    let mut answer_turn = completion_response(json!({"role": "assistant", "content": "Done"}));
+    answer_turn["usage"] = json!({"prompt_tokens": 45, "completion_tokens": 5, "total_tokens": 50});
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(tool_turn))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(answer_turn))
+        .mount(&server)
+        .await;
+
+    let agent = AgentBuilder::new("usage_agent")
+        .model(Arc::new(
+            OpenAI::new("test-key", "gpt-4").with_base_url(server.uri()),
+        ))
+        .add_tool(Arc::new(echo_tool()))
+        .build()
+        .unwrap();
+    let updates: Vec<(u64, usize)> = agent
+        .run_events("Echo hi", Context::new())
+        .filter_map(|event| async move {
+            match event {
+                RunEvent::UsageUpdate { tokens, tool_calls } => Some((tokens, tool_calls)),
+                _ => None,
+            }
+        })
+        .collect()
+        .await;
+
+    assert_eq!(updates, [(30, 1), (80, 1)]);
+}
+
+#[tokio::test]
+async fn test_request_timeout_abandons_slow_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(completion_response(
+                    json!({"role": "assistant", "content": "Too late"}),
+                ))
+                .set_delay(Duration::from_secs(5)),
+        )
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_request_timeout(Duration::from_millis(100));
+    let mut context = run_context();
+    match model.generate_response(&mut context, &[]).await {
+        Err(AgentError::ModelError(msg)) => assert_eq!(msg, "model request timed out"),
+        other => panic!("Expected ModelError, got {:?}", other),
+    }
+}
+
+async fn mount_flagged_moderation(server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/moderations"))
+        .and(body_partial_json(json!({"input": "something hateful"})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "modr-1",
+            "model": "omni-moderation-latest",
+            "results": [{
+                "flagged": true,
+                "categories": {"harassment": true, "hate": true, "violence": false}
+            }]
+        })))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_moderate_reports_flagged_categories() {
+    let server = MockServer::start().await;
+    mount_flagged_moderation(&server).await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let moderation = model.moderate("something hateful").await.unwrap();
+
+    assert!(moderation.flagged);
+    assert_eq!(moderation.categories, ["harassment", "hate"]);
+}
+
+#[tokio::test]
+async fn test_moderate_input_blocks_flagged_run() {
+    let server = MockServer::start().await;
+    mount_flagged_moderation(&server).await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let agent = AgentBuilder::new("moderated_agent")
+        .model(Arc::new(model))
+        .moderate_input(true)
+        .build()
+        .unwrap();
+
+    match agent.run("something hateful", Context::new()).await {
+        Err(AgentError::GuardrailError(message)) => {
+            assert_eq!(message, "input flagged by moderation (harassment, hate)")
+        }
+        other => panic!("Expected GuardrailError, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_moderate_input_screens_visible_user_message() {
+    let server = MockServer::start().await;
+    mount_flagged_moderation(&server).await;
+    Mock::given(method("POST"))
+        .and(path("/moderations"))
+        .and(body_partial_json(json!({"input": "Hello"})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "modr-2",
+            "model": "omni-moderation-latest",
+            "results": [{"flagged": false, "categories": {"hate": false}}]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_reply(&server, "Hi there", 1).await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let agent = AgentBuilder::new("moderated_agent")
+        .model(Arc::new(model))
+        .moderate_input(true)
+        .build()
+        .unwrap();
+    let mut run_context = RunContext::new(Context::new());
+    run_context.add_message("user", "Hello");
+    run_context.add_hidden_message("user", "something hateful");
+
+    let response = agent.run_with_context(&mut run_context).await.unwrap();
+
+    assert_eq!(response, "Hi there");
+}
+
+#[test]
+fn test_openai_debug_hides_api_key() {
+    let model = OpenAI::new("sk-secret", "gpt-4o").with_temperature(0.5);
+
+    let debug = format!("{:?}", model);
+    assert!(debug.contains(r#"model: "gpt-4o""#), "{}", debug);
+    assert!(debug.contains("temperature: Some(0.5)"), "{}", debug);
+    assert!(!debug.contains("sk-secret"), "{}", debug);
+}
+
+fn sse_body(deltas: &[serde_json::Value]) -> String {
+    let mut body = String::new();
+    for delta in deltas {
+        let chunk = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "gpt-4",
+            "choices": [{"index": 0, "delta": delta, "finish_reason": null}]
+        });
+        body.push_str(&format!("data: {}\n\n", chunk));
+    }
+    body.push_str("data: [DONE]\n\n");
+    body
+}
+
+async fn stream_chunks(deltas: &[serde_json::Value]) -> Vec<StepChunk> {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(body_partial_json(json!({"stream": true})))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(sse_body(deltas), "text/event-stream"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let tool = echo_tool();
+    model
+        .stream_step(&mut run_context(), &[&tool], &ModelParams::default())
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn test_stream_step_yields_content_deltas() {
+    let chunks = stream_chunks(&[
+        json!({"role": "assistant", "content": ""}),
+        json!({"content": "Hel"}),
+        json!({"content": "lo"}),
+        json!({"content": "!"}),
+    ])
+    .await;
+
+    assert_eq!(
+        chunks,
+        ["Hel", "lo", "!"].map(|delta| StepChunk::Content(delta.to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_stream_step_reassembles_tool_calls() {
+    let chunks = stream_chunks(&[
+        json!({"tool_calls": [{
+            "index": 0,
+            "id": "call_abc",
+            "type": "function",
+            "function": {"name": "echo", "arguments": "{\"te"}
+        }]}),
+        json!({"tool_calls": [{"index": 0, "function": {"arguments": "xt\":\"hi\"}"}}]}),
+    ])
+    .await;
+
+    assert_eq!(
+        chunks,
+        [StepChunk::ToolCalls(vec![ToolCall {
+            id: "call_abc".to_string(),
+            name: "echo".to_string(),
+            arguments: r#"{"text":"hi"}"#.to_string(),
+        }])]
+    );
+}
+
+async fn mount_stream(server: &MockServer, body: String) {
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(body_partial_json(json!({"stream": true})))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_stream_step_reports_usage_before_tool_calls() {
+    let server = MockServer::start().await;
+    let mut body = sse_body(&[json!({"tool_calls": [{
+        "index": 0,
+        "id": "call_abc",
+        "type": "function",
+        "function": {"name": "echo", "arguments": "{}"}
+    }]})]);
+    let usage = json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "gpt-4",
+        "choices": [],
+        "usage": {"prompt_tokens": 30, "completion_tokens": 12, "total_tokens": 42}
+    });
+    body.insert_str(0, &format!("data: {}\n\n", usage));
+    mount_stream(&server, body).await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let tool = echo_tool();
+    let chunks: Vec<StepChunk> = model
+        .stream_step(&mut run_context(), &[&tool], &ModelParams::default())
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0], StepChunk::Usage(42));
+    assert!(matches!(chunks[1], StepChunk::ToolCalls(_)));
+    let request: serde_json::Value = server.received_requests().await.unwrap()[0]
+        .body_json()
+        .unwrap();
+    assert_eq!(request["stream_options"]["include_usage"], true);
+}
+
+#[tokio::test]
+async fn test_stream_step_ends_with_refusal() {
+    let server = MockServer::start().await;
+    mount_stream(
+        &server,
+        sse_body(&[
+            json!({"role": "assistant", "refusal": "I can't"}),
+            json!({"refusal": " help with that."}),
+        ]),
+    )
+    .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let results: Vec<_> = model
+        .stream_step(&mut run_context(), &[], &ModelParams::default())
+        .await
+        .unwrap()
+        .collect()
+        .await;
+
+    assert_eq!(results.len(), 1);
+    match &results[0] {
+        Err(AgentError::Refusal(refusal)) => assert_eq!(refusal, "I can't help with that."),
+        other => panic!("Expected Refusal, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_stream_step_retries_failed_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("overloaded"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_stream(&server, sse_body(&[json!({"content": "recovered"})])).await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_retry_budget(10)
+        .unwrap();
+    let chunks: Vec<StepChunk> = model
+        .stream_step(&mut run_context(), &[], &ModelParams::default())
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert_eq!(chunks, [StepChunk::Content("recovered".to_string())]);
+}
+
+#[tokio::test]
+async fn test_stream_step_holds_concurrency_slot_until_done() {
+    let server = MockServer::start().await;
+    mount_stream(&server, sse_body(&[json!({"content": "Hi"})])).await;
+
+    let model = OpenAI::new("test-key", "gpt-4")
+        .with_base_url(server.uri())
+        .with_max_concurrency(1)
+        .unwrap();
+    let first = model
+        .stream_step(&mut run_context(), &[], &ModelParams::default())
+        .await
+        .unwrap();
+
+    // The slot is still taken while the first stream is unread
+    let mut context = run_context();
+    let params = ModelParams::default();
+    let blocked = model.stream_step(&mut context, &[], &params);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), blocked)
+            .await
+            .is_err()
+    );
+
+    let chunks: Vec<_> = first.collect().await;
+    assert_eq!(chunks.len(), 1);
+    let second = model.stream_step(&mut context, &[], &params).await.unwrap();
+    assert_eq!(second.count().await, 1);
+}
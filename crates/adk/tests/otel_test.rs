@@ -0,0 +1,117 @@
+#![cfg(feature = "otel")]
+
+use adk::Model;
+use adk::openai::{ModelParams, OpenAI};
+use adk::types::{Context, RunContext};
+use futures::StreamExt;
+use opentelemetry::{KeyValue, Value};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn attribute(attributes: &[KeyValue], key: &str) -> Option<Value> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.key.as_str() == key)
+        .map(|attribute| attribute.value.clone())
+}
+
+#[tokio::test]
+async fn test_model_calls_emit_gen_ai_spans() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    opentelemetry::global::set_tracer_provider(provider);
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4-0613",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi!"},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15}
+        })))
+        .mount(&server)
+        .await;
+
+    let model = OpenAI::new("test-key", "gpt-4").with_base_url(server.uri());
+    let mut context = RunContext::new(Context::new());
+    context.add_message("user", "Hello");
+    model.generate_response(&mut context, &[]).await.unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    assert_eq!(spans.len(), 1);
+    let span = &spans[0];
+    assert_eq!(span.name, "chat gpt-4");
+    let attributes = &span.attributes;
+    assert_eq!(
+        attribute(attributes, "gen_ai.system"),
+        Some("openai".into())
+    );
+    assert_eq!(
+        attribute(attributes, "gen_ai.request.model"),
+        Some("gpt-4".into())
+    );
+    assert_eq!(
+        attribute(attributes, "gen_ai.response.model"),
+        Some("gpt-4-0613".into())
+    );
+    assert_eq!(
+        attribute(attributes, "gen_ai.usage.input_tokens"),
+        Some(12.into())
+    );
+    assert_eq!(
+        attribute(attributes, "gen_ai.usage.output_tokens"),
+        Some(3.into())
+    );
+
+    // A streamed turn gets a span too, ended once the stream is done
+    exporter.reset();
+    let body = [
+        json!({"choices": [{"index": 0, "delta": {"content": "Hi!"}, "finish_reason": null}]}),
+        json!({
+            "choices": [],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15}
+        }),
+    ]
+    .iter()
+    .map(|chunk| {
+        let mut chunk = chunk.clone();
+        chunk["id"] = json!("chatcmpl-2");
+        chunk["object"] = json!("chat.completion.chunk");
+        chunk["created"] = json!(0);
+        chunk["model"] = json!("gpt-4-0613");
+        format!("data: {}\n\n", chunk)
+    })
+    .collect::<String>()
+        + "data: [DONE]\n\n";
+    server.reset().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+        .mount(&server)
+        .await;
+
+    let stream = model
+        .stream_step(&mut context, &[], &ModelParams::default())
+        .await
+        .unwrap();
+    assert!(exporter.get_finished_spans().unwrap().is_empty());
+    assert_eq!(stream.count().await, 2);
+
+    let spans = exporter.get_finished_spans().unwrap();
+    assert_eq!(spans.len(), 1);
+    assert_eq!(
+        attribute(&spans[0].attributes, "gen_ai.usage.output_tokens"),
+        Some(3.into())
+    );
+}
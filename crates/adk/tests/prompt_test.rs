@@ -0,0 +1,30 @@
+use adk::AgentError;
+use adk::prompt::PromptTemplate;
+use adk::types::Context;
+
+#[test]
+fn test_prompt_template_substitutes_context_values() {
+    let context = Context::new()
+        .with_data("name", "Ada")
+        .with_data("visits", 3)
+        .with_data("tags", vec!["math", "engines"]);
+    let template = PromptTemplate::new(
+        "Greet {name} (visit #{visits}, tags {tags}). Reply as {\"greeting\": \"...\"}.",
+    );
+
+    assert_eq!(
+        template.render(&context).unwrap(),
+        "Greet Ada (visit #3, tags [\"math\",\"engines\"]). Reply as {\"greeting\": \"...\"}."
+    );
+}
+
+#[test]
+fn test_prompt_template_missing_key() {
+    let template = PromptTemplate::new("Hello {name}, welcome to {place}");
+    let context = Context::new().with_data("name", "Ada");
+
+    match template.render(&context) {
+        Err(AgentError::ContextError(msg)) => assert_eq!(msg, "missing template variable: place"),
+        other => panic!("Expected ContextError, got {:?}", other),
+    }
+}
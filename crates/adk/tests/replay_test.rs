@@ -0,0 +1,245 @@
+use adk::agent::AgentBuilder;
+use adk::error::AgentError;
+use adk::openai::{Model, ModelParams, Step, StepChunk, StepStream};
+use adk::replay::{RecordingModel, ReplayModel};
+use adk::tool::{FunctionTool, Tool, ToolCall, ToolResult, execute_tool_call};
+use adk::types::{Context, RunContext};
+use adk::{Agent, function_tool};
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Mock model that calls the weather tool once, then answers with its output
+#[derive(Default)]
+struct WeatherModel {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl Model for WeatherModel {
+    async fn generate_response(
+        &self,
+        _context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        unreachable!("the agent drives the model through generate_step")
+    }
+
+    async fn generate_step(
+        &self,
+        context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<Step, AgentError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        match context.messages.last() {
+            Some(message) if message.role == "tool" => {
+                Ok(Step::Message(format!("It is {}.", message.content)))
+            }
+            _ => Ok(Step::ToolCalls(vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "weather".to_string(),
+                arguments: r#"{"city": "Oslo"}"#.to_string(),
+            }])),
+        }
+    }
+}
+
+// Mock model that streams the same turns as WeatherModel, its answer in two deltas
+struct StreamingWeatherModel;
+
+#[async_trait]
+impl Model for StreamingWeatherModel {
+    async fn generate_response(
+        &self,
+        _context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        unreachable!("the agent streams the model's turns")
+    }
+
+    async fn stream_step(
+        &self,
+        context: &mut RunContext,
+        _tools: &[&dyn Tool],
+        _params: &ModelParams,
+    ) -> Result<StepStream, AgentError> {
+        let chunks = match context.messages.last() {
+            Some(message) if message.role == "tool" => vec![
+                StepChunk::Content("It is ".to_string()),
+                StepChunk::Content(format!("{}.", message.content)),
+            ],
+            _ => vec![StepChunk::ToolCalls(vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "weather".to_string(),
+                arguments: r#"{"city": "Oslo"}"#.to_string(),
+            }])],
+        };
+        Ok(futures::stream::iter(chunks.into_iter().map(Ok)).boxed())
+    }
+}
+
+// Mock model that only answers through generate_response, calling the weather tool itself
+struct SelfDrivenWeatherModel;
+
+#[async_trait]
+impl Model for SelfDrivenWeatherModel {
+    async fn generate_response(
+        &self,
+        context: &mut RunContext,
+        tools: &[&dyn Tool],
+    ) -> Result<String, AgentError> {
+        let tool_call = ToolCall {
+            id: "call_1".to_string(),
+            name: "weather".to_string(),
+            arguments: r#"{"city": "Oslo"}"#.to_string(),
+        };
+        context.add_tool_calls(vec![tool_call.clone()]);
+        let result = execute_tool_call(tools[0], context, &tool_call).await?;
+        let answer = format!("It is {}.", result.output);
+        context.add_tool_result(result);
+        Ok(answer)
+    }
+
+    async fn generate_step(
+        &self,
+        _context: &mut RunContext,
+        _tools: &[&dyn Tool],
+    ) -> Result<Step, AgentError> {
+        unreachable!("the recorder passes generate_response through")
+    }
+}
+
+fn weather_tool() -> FunctionTool {
+    function_tool!("weather", "Gets the weather", |_context, _params| {
+        Ok(ToolResult {
+            tool_name: "weather".to_string(),
+            output: "sunny".to_string(),
+            ..Default::default()
+        })
+    })
+}
+
+fn weather_agent(model: Arc<dyn Model>) -> Agent {
+    AgentBuilder::new("weather_agent")
+        .model(model)
+        .add_tool(Arc::new(weather_tool()))
+        .build()
+        .unwrap()
+}
+
+fn recording_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("adk-{}-{}.json", name, std::process::id()))
+}
+
+#[tokio::test]
+async fn test_record_then_replay() {
+    let path = recording_path("record_then_replay");
+    let real_model = Arc::new(WeatherModel::default());
+    let recorder = Arc::new(RecordingModel::new(real_model.clone(), &path));
+
+    let mut recorded = RunContext::new(Context::new());
+    recorded.add_message("user", "Weather in Oslo?");
+    let recorded_response = weather_agent(recorder.clone())
+        .run_with_context(&mut recorded)
+        .await
+        .unwrap();
+    assert_eq!(recorded_response, "It is sunny.");
+    assert_eq!(recorder.steps().await.len(), 2);
+
+    let replay = Arc::new(ReplayModel::from_file(&path).unwrap());
+    let mut replayed = RunContext::new(Context::new());
+    replayed.add_message("user", "Weather in Oslo?");
+    let replayed_response = weather_agent(replay.clone())
+        .run_with_context(&mut replayed)
+        .await
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed_response, recorded_response);
+    assert_eq!(
+        format!("{:?}", replayed.messages),
+        format!("{:?}", recorded.messages)
+    );
+    assert_eq!(real_model.calls.load(Ordering::SeqCst), 2);
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_record_generate_response_with_tool_call() {
+    let path = recording_path("record_generate_response");
+    let recorder = RecordingModel::new(Arc::new(SelfDrivenWeatherModel), &path);
+    let weather = weather_tool();
+
+    let mut recorded = RunContext::new(Context::new());
+    recorded.add_message("user", "Weather in Oslo?");
+    let recorded_response = recorder
+        .generate_response(&mut recorded, &[&weather])
+        .await
+        .unwrap();
+    assert_eq!(recorded_response, "It is sunny.");
+    let steps = recorder.steps().await;
+    assert!(matches!(&steps[0], Step::ToolCalls(calls) if calls[0].name == "weather"));
+    assert_eq!(steps[1], Step::Message("It is sunny.".to_string()));
+
+    let replay = ReplayModel::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut replayed = RunContext::new(Context::new());
+    replayed.add_message("user", "Weather in Oslo?");
+    let replayed_response = replay
+        .generate_response(&mut replayed, &[&weather])
+        .await
+        .unwrap();
+
+    assert_eq!(replayed_response, recorded_response);
+    assert_eq!(
+        format!("{:?}", replayed.messages),
+        format!("{:?}", recorded.messages)
+    );
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_record_streamed_run() {
+    let path = recording_path("record_streamed_run");
+    let recorder = Arc::new(RecordingModel::new(Arc::new(StreamingWeatherModel), &path));
+
+    let deltas: Vec<String> = weather_agent(recorder.clone())
+        .run_stream("Weather in Oslo?", Context::new())
+        .map(|delta| delta.unwrap())
+        .collect()
+        .await;
+
+    let replay = ReplayModel::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(deltas, ["It is ", "sunny."]);
+    let steps = recorder.steps().await;
+    assert!(matches!(&steps[0], Step::ToolCalls(calls) if calls[0].name == "weather"));
+    assert_eq!(steps[1], Step::Message("It is sunny.".to_string()));
+    assert_eq!(replay.remaining(), 2);
+}
+
+#[tokio::test]
+async fn test_replay_exhausted() {
+    let replay = ReplayModel::new(vec![Step::Message("only once".to_string())]);
+    let mut context = RunContext::new(Context::new());
+
+    assert_eq!(
+        replay.generate_response(&mut context, &[]).await.unwrap(),
+        "only once"
+    );
+    match replay.generate_response(&mut context, &[]).await {
+        Err(AgentError::ModelError(msg)) => assert_eq!(msg, "no more recorded responses"),
+        other => panic!("Expected ModelError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_replay_missing_file() {
+    match ReplayModel::from_file(recording_path("missing")) {
+        Err(AgentError::ConfigurationError(msg)) => {
+            assert!(msg.starts_with("Failed to read recording"))
+        }
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
@@ -0,0 +1,54 @@
+use adk::retry::{Backoff, ExponentialBackoff, FixedBackoff, JitteredBackoff};
+use std::time::Duration;
+
+fn delays(backoff: &mut dyn Backoff, attempts: usize) -> Vec<Duration> {
+    (1..=attempts)
+        .map(|attempt| backoff.next_delay(attempt))
+        .collect()
+}
+
+fn millis(delays: &[u64]) -> Vec<Duration> {
+    delays.iter().copied().map(Duration::from_millis).collect()
+}
+
+#[test]
+fn test_fixed_backoff_waits_the_same_each_time() {
+    let mut backoff = FixedBackoff::new(Duration::from_millis(250));
+    assert_eq!(delays(&mut backoff, 4), millis(&[250, 250, 250, 250]));
+}
+
+#[test]
+fn test_exponential_backoff_doubles_each_time() {
+    let mut backoff = ExponentialBackoff::new(Duration::from_millis(100));
+    assert_eq!(delays(&mut backoff, 4), millis(&[100, 200, 400, 800]));
+}
+
+#[test]
+fn test_exponential_backoff_caps_at_max_delay() {
+    let mut backoff = ExponentialBackoff::new(Duration::from_millis(100))
+        .with_max_delay(Duration::from_millis(300));
+    assert_eq!(delays(&mut backoff, 4), millis(&[100, 200, 300, 300]));
+}
+
+#[test]
+fn test_jittered_backoff_stays_under_exponential_delay() {
+    let mut backoff = JitteredBackoff::new(Duration::from_millis(100)).with_seed(42);
+    let jittered = delays(&mut backoff, 4);
+
+    for (delay, cap) in jittered.iter().zip(millis(&[100, 200, 400, 800])) {
+        assert!(*delay < cap, "{:?} should be under {:?}", delay, cap);
+    }
+    // Not every wait is the same fraction of its cap
+    assert!(jittered.windows(2).any(|pair| pair[1] != pair[0] * 2));
+}
+
+#[test]
+fn test_jittered_backoff_is_repeatable_with_a_seed() {
+    let mut first = JitteredBackoff::new(Duration::from_millis(100)).with_seed(7);
+    let mut second = JitteredBackoff::new(Duration::from_millis(100)).with_seed(7);
+    let mut other = JitteredBackoff::new(Duration::from_millis(100)).with_seed(8);
+
+    let expected = delays(&mut first, 4);
+    assert_eq!(delays(&mut second, 4), expected);
+    assert_ne!(delays(&mut other, 4), expected);
+}
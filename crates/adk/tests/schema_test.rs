@@ -0,0 +1,207 @@
+#![cfg(feature = "schemars")]
+
+use adk::agent::AgentBuilder;
+use adk::openai::OpenAI;
+use adk::tool::{FunctionTool, Tool, ToolResult, parse_params, schema_for};
+use adk::types::{Context, RunContext};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Parameters for looking up the weather
+#[derive(Deserialize, JsonSchema)]
+#[allow(dead_code)]
+struct WeatherParams {
+    /// The city to look up
+    city: String,
+    /// How many days to forecast
+    days: Option<u32>,
+}
+
+#[test]
+fn test_schema_for_derives_properties_and_required() {
+    let schema = schema_for::<WeatherParams>();
+
+    assert_eq!(schema["type"], "object");
+    assert_eq!(schema["properties"]["city"]["type"], "string");
+    assert_eq!(
+        schema["properties"]["city"]["description"],
+        "The city to look up"
+    );
+    assert!(schema["properties"]["days"].is_object());
+    assert_eq!(schema["required"], json!(["city"]));
+    assert!(schema.get("$schema").is_none());
+    assert!(schema.get("title").is_none());
+}
+
+#[tokio::test]
+async fn test_function_tool_for_params() {
+    let tool = FunctionTool::for_params::<WeatherParams>(
+        "weather",
+        "Looks up the weather",
+        Box::new(|_, params| {
+            let params: WeatherParams = parse_params(params)?;
+            Ok(ToolResult {
+                tool_name: "weather".to_string(),
+                output: format!("Sunny in {}", params.city),
+                ..Default::default()
+            })
+        }),
+    );
+    assert_eq!(tool.parameters_schema(), schema_for::<WeatherParams>());
+
+    let mut context = RunContext::new(Context::new());
+    let result = tool
+        .execute(&mut context, r#"{"city": "Paris"}"#)
+        .await
+        .unwrap();
+    assert_eq!(result.output, "Sunny in Paris");
+}
+
+/// A forecast the agent answers with
+#[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+struct Forecast {
+    city: String,
+    temperature: i32,
+}
+
+async fn mount_replies(server: &MockServer, replies: &[&str]) {
+    for reply in replies {
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "model": "gpt-4o",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": reply},
+                    "finish_reason": "stop"
+                }]
+            })))
+            .up_to_n_times(1)
+            .mount(server)
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn test_run_typed_parses_answer_into_type() {
+    let server = MockServer::start().await;
+    mount_replies(&server, &[r#"{"city": "Paris", "temperature": 21}"#]).await;
+
+    let agent = AgentBuilder::new("forecaster")
+        .model(Arc::new(
+            OpenAI::new("test-key", "gpt-4o").with_base_url(server.uri()),
+        ))
+        .build()
+        .unwrap();
+    let forecast: Forecast = agent
+        .run_typed("Weather in Paris?", Context::new())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        forecast,
+        Forecast {
+            city: "Paris".to_string(),
+            temperature: 21
+        }
+    );
+    let request: serde_json::Value = server.received_requests().await.unwrap()[0]
+        .body_json()
+        .unwrap();
+    let response_format = &request["response_format"];
+    assert_eq!(response_format["type"], "json_schema");
+    assert_eq!(response_format["json_schema"]["name"], "Forecast");
+    assert_eq!(
+        response_format["json_schema"]["schema"],
+        schema_for::<Forecast>()
+    );
+}
+
+#[tokio::test]
+async fn test_run_typed_retries_unparsable_answer() {
+    let server = MockServer::start().await;
+    mount_replies(
+        &server,
+        &[
+            "It is 21 degrees",
+            r#"{"city": "Paris", "temperature": 21}"#,
+        ],
+    )
+    .await;
+
+    let agent = AgentBuilder::new("forecaster")
+        .model(Arc::new(
+            OpenAI::new("test-key", "gpt-4o").with_base_url(server.uri()),
+        ))
+        .build()
+        .unwrap();
+    let forecast: Forecast = agent
+        .run_typed("Weather in Paris?", Context::new())
+        .await
+        .unwrap();
+
+    assert_eq!(forecast.temperature, 21);
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let retry: serde_json::Value = requests[1].body_json().unwrap();
+    let messages = retry["messages"].as_array().unwrap();
+    assert_eq!(messages[messages.len() - 2]["content"], "It is 21 degrees");
+    assert!(
+        messages[messages.len() - 1]["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("Your last response could not be parsed")
+    );
+}
+
+#[tokio::test]
+async fn test_run_typed_moderates_only_the_input() {
+    let server = MockServer::start().await;
+    mount_replies(
+        &server,
+        &[
+            "It is 21 degrees",
+            r#"{"city": "Paris", "temperature": 21}"#,
+        ],
+    )
+    .await;
+    Mock::given(method("POST"))
+        .and(path("/moderations"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "modr-1",
+            "model": "omni-moderation-latest",
+            "results": [{"flagged": false, "categories": {"hate": false}}]
+        })))
+        .mount(&server)
+        .await;
+
+    let agent = AgentBuilder::new("forecaster")
+        .model(Arc::new(
+            OpenAI::new("test-key", "gpt-4o").with_base_url(server.uri()),
+        ))
+        .moderate_input(true)
+        .build()
+        .unwrap();
+    let forecast: Forecast = agent
+        .run_typed("Weather in Paris?", Context::new())
+        .await
+        .unwrap();
+
+    assert_eq!(forecast.temperature, 21);
+    let moderated: Vec<serde_json::Value> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| request.url.path() == "/moderations")
+        .map(|request| request.body_json::<serde_json::Value>().unwrap()["input"].clone())
+        .collect();
+    assert_eq!(moderated, [json!("Weather in Paris?")]);
+}
@@ -1,7 +1,13 @@
-use adk::tool::{FunctionTool, Tool, ToolResult};
+use adk::tool::{
+    AsyncFunctionTool, CachedTool, ChainedTool, FunctionTool, TRUNCATION_MARKER, Tool, ToolCall,
+    ToolResult, execute_tool_call, parse_params, validate_and_execute,
+};
 use adk::types::{Context, RunContext};
 use adk::{AgentError, function_tool};
 use async_trait::async_trait;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
 
 // Simple test tool
 #[derive(Debug)]
@@ -38,6 +44,7 @@ impl Tool for TestTool {
         Ok(ToolResult {
             tool_name: self.name().to_string(),
             output: format!("Processed: {}", input),
+            ..Default::default()
         })
     }
 }
@@ -96,6 +103,7 @@ fn test_tool_result_creation() {
     let result = ToolResult {
         tool_name: "my_tool".to_string(),
         output: "some output".to_string(),
+        ..Default::default()
     };
 
     assert_eq!(result.tool_name, "my_tool");
@@ -107,6 +115,7 @@ fn test_tool_result_clone() {
     let result = ToolResult {
         tool_name: "my_tool".to_string(),
         output: "some output".to_string(),
+        ..Default::default()
     };
 
     let cloned = result.clone();
@@ -120,6 +129,7 @@ fn test_function_tool_creation() {
         Ok(ToolResult {
             tool_name: "echo_tool".to_string(),
             output: format!("Echo: {}", params),
+            ..Default::default()
         })
     });
 
@@ -127,6 +137,18 @@ fn test_function_tool_creation() {
     assert_eq!(tool.description(), "Echoes the input");
 }
 
+#[test]
+fn test_function_tool_debug() {
+    let tool = function_tool!("echo_tool", "Echoes the input", |_context, _params| {
+        Ok(ToolResult::default())
+    });
+
+    assert_eq!(
+        format!("{:?}", tool),
+        r#"FunctionTool { name: "echo_tool", description: "Echoes the input", tags: [], requires_approval: false, .. }"#
+    );
+}
+
 #[tokio::test]
 async fn test_function_tool_execution() {
     let tool = function_tool!("double_tool", "Doubles a number", |_context, params| {
@@ -134,6 +156,7 @@ async fn test_function_tool_execution() {
         Ok(ToolResult {
             tool_name: "double_tool".to_string(),
             output: (num * 2).to_string(),
+            ..Default::default()
         })
     });
 
@@ -164,6 +187,7 @@ fn test_function_tool_with_schema() {
             Ok(ToolResult {
                 tool_name: "square_tool".to_string(),
                 output: (value * value).to_string(),
+                ..Default::default()
             })
         }
     );
@@ -193,6 +217,7 @@ async fn test_function_tool_with_schema_execution() {
             Ok(ToolResult {
                 tool_name: "square_tool".to_string(),
                 output: (value * value).to_string(),
+                ..Default::default()
             })
         }
     );
@@ -217,6 +242,7 @@ fn test_function_tool_new() {
             Ok(ToolResult {
                 tool_name: "custom_tool".to_string(),
                 output: "custom output".to_string(),
+                ..Default::default()
             })
         }),
     );
@@ -239,6 +265,7 @@ async fn test_function_tool_new_execution() {
             Ok(ToolResult {
                 tool_name: "custom_tool".to_string(),
                 output: format!("Received: {}", params),
+                ..Default::default()
             })
         }),
     );
@@ -249,3 +276,379 @@ async fn test_function_tool_new_execution() {
     assert_eq!(result.tool_name, "custom_tool");
     assert_eq!(result.output, "Received: test input");
 }
+
+// Tool that only accepts non-negative numbers
+#[derive(Debug, Default)]
+struct NonNegativeTool {
+    executed: AtomicBool,
+}
+
+#[async_trait]
+impl Tool for NonNegativeTool {
+    fn name(&self) -> &str {
+        "non_negative_tool"
+    }
+
+    fn description(&self) -> &str {
+        "Accepts only non-negative numbers"
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "value": {"type": "number"}
+            },
+            "required": ["value"]
+        })
+    }
+
+    async fn validate(&self, _context: &RunContext, params: &str) -> Result<(), AgentError> {
+        let params: serde_json::Value = serde_json::from_str(params)?;
+        if params["value"].as_f64().unwrap_or(0.0) < 0.0 {
+            return Err(AgentError::InvalidInput(
+                "value must not be negative".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn execute(
+        &self,
+        _context: &mut RunContext,
+        params: &str,
+    ) -> Result<ToolResult, AgentError> {
+        self.executed.store(true, Ordering::SeqCst);
+        Ok(ToolResult {
+            tool_name: self.name().to_string(),
+            output: params.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_tool_default_validate_accepts() {
+    let tool = TestTool;
+    let context = RunContext::new(Context::new());
+
+    assert!(tool.validate(&context, "anything").await.is_ok());
+}
+
+#[tokio::test]
+async fn test_validate_rejects_before_execute() {
+    let tool = NonNegativeTool::default();
+    let mut context = RunContext::new(Context::new());
+
+    let result = validate_and_execute(&tool, &mut context, r#"{"value": -1}"#).await;
+
+    match result {
+        Err(AgentError::InvalidInput(msg)) => assert_eq!(msg, "value must not be negative"),
+        _ => panic!("Expected InvalidInput"),
+    }
+    assert!(!tool.executed.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_validate_accepts_then_executes() {
+    let tool = NonNegativeTool::default();
+    let mut context = RunContext::new(Context::new());
+
+    let result = validate_and_execute(&tool, &mut context, r#"{"value": 3}"#)
+        .await
+        .unwrap();
+
+    assert_eq!(result.output, r#"{"value": 3}"#);
+    assert!(tool.executed.load(Ordering::SeqCst));
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AddParams {
+    a: i64,
+    b: i64,
+}
+
+#[test]
+fn test_parse_params() {
+    let params: AddParams = parse_params(r#"{"a":1,"b":2}"#).unwrap();
+
+    assert_eq!(params.a, 1);
+    assert_eq!(params.b, 2);
+}
+
+#[test]
+fn test_parse_params_missing_field() {
+    let result = parse_params::<AddParams>(r#"{"a":1}"#);
+
+    match result {
+        Err(AgentError::InvalidInput(msg)) => {
+            assert!(msg.starts_with("Invalid tool arguments"));
+            assert!(msg.contains("missing field `b`"));
+        }
+        _ => panic!("Expected InvalidInput"),
+    }
+}
+
+#[test]
+fn test_parse_params_invalid_json() {
+    let result = parse_params::<AddParams>("not json");
+
+    assert!(matches!(result, Err(AgentError::InvalidInput(_))));
+}
+
+#[tokio::test]
+async fn test_execute_tool_call_records_call() {
+    let tool = TestTool;
+    let mut context = RunContext::new(Context::new());
+    let tool_call = ToolCall {
+        id: "call_42".to_string(),
+        name: "test_tool".to_string(),
+        arguments: r#"{"input": "hi"}"#.to_string(),
+    };
+
+    let result = execute_tool_call(&tool, &mut context, &tool_call)
+        .await
+        .unwrap();
+
+    assert_eq!(result.output, "Processed: hi");
+    assert_eq!(result.call_id, Some("call_42".to_string()));
+    assert_eq!(result.arguments, Some(r#"{"input": "hi"}"#.to_string()));
+}
+
+#[tokio::test]
+async fn test_direct_execution_has_no_call() {
+    let tool = TestTool;
+    let mut context = RunContext::new(Context::new());
+
+    let result = tool
+        .execute(&mut context, r#"{"input": "hi"}"#)
+        .await
+        .unwrap();
+
+    assert_eq!(result.call_id, None);
+    assert_eq!(result.arguments, None);
+}
+
+#[tokio::test]
+async fn test_tools_share_typed_context_data() {
+    let writer = function_tool!("writer", "Stores a counter", |context, _params| {
+        context.set_data("counter", vec![1u32, 2, 3])?;
+        Ok(ToolResult {
+            tool_name: "writer".to_string(),
+            output: "stored".to_string(),
+            ..Default::default()
+        })
+    });
+    let reader = function_tool!("reader", "Sums the counter", |context, _params| {
+        let counter: Vec<u32> = context.get_data("counter")?.unwrap_or_default();
+        Ok(ToolResult {
+            tool_name: "reader".to_string(),
+            output: counter.iter().sum::<u32>().to_string(),
+            ..Default::default()
+        })
+    });
+
+    let mut context = RunContext::new(Context::new());
+    writer.execute(&mut context, "{}").await.unwrap();
+    let result = reader.execute(&mut context, "{}").await.unwrap();
+
+    assert_eq!(result.output, "6");
+}
+
+#[tokio::test]
+async fn test_async_function_tool_execution() {
+    let tool = AsyncFunctionTool::new(
+        "slow_double",
+        "Doubles a number after a short wait",
+        serde_json::json!({"type": "object", "properties": {}}),
+        |context, params| {
+            Box::pin(async move {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                let num: i32 = params.parse().unwrap_or(0);
+                context.set_data("last_input", num)?;
+                Ok(ToolResult {
+                    tool_name: "slow_double".to_string(),
+                    output: (num * 2).to_string(),
+                    ..Default::default()
+                })
+            })
+        },
+    );
+
+    let mut context = RunContext::new(Context::new());
+    let result = tool.execute(&mut context, "21").await.unwrap();
+
+    assert_eq!(tool.name(), "slow_double");
+    assert_eq!(result.output, "42");
+    assert_eq!(context.get_data::<i32>("last_input").unwrap(), Some(21));
+}
+
+#[tokio::test]
+async fn test_chained_tool_feeds_outputs_forward() {
+    let fetch = function_tool!(
+        "fetch",
+        "Fetches a page",
+        serde_json::json!({
+            "type": "object",
+            "properties": {"url": {"type": "string"}},
+            "required": ["url"]
+        }),
+        |_context, params| {
+            Ok(ToolResult {
+                tool_name: "fetch".to_string(),
+                output: format!("page for {}", params),
+                ..Default::default()
+            })
+        }
+    );
+    let summarize = function_tool!("summarize", "Summarizes text", |context, params| {
+        context.set_data("summarize_input", params)?;
+        Ok(ToolResult {
+            tool_name: "summarize".to_string(),
+            output: params.to_uppercase(),
+            ..Default::default()
+        })
+    });
+    let chain = ChainedTool::new(
+        "fetch_and_summarize",
+        "Fetches a page and summarizes it",
+        vec![Arc::new(fetch), Arc::new(summarize)],
+    );
+
+    let mut context = RunContext::new(Context::new());
+    let params = r#"{"url":"a.com"}"#;
+    let result = chain.execute(&mut context, params).await.unwrap();
+
+    assert_eq!(chain.parameters_schema()["required"][0], "url");
+    assert_eq!(
+        context.get_data::<String>("summarize_input").unwrap(),
+        Some(format!("page for {}", params))
+    );
+    assert_eq!(result.tool_name, "fetch_and_summarize");
+    assert_eq!(result.output, r#"PAGE FOR {"URL":"A.COM"}"#);
+}
+
+// Counts its calls and echoes the params back
+fn counting_tool(calls: Arc<AtomicUsize>) -> FunctionTool {
+    function_tool!("lookup", "Looks something up", move |_context, params| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Ok(ToolResult {
+            tool_name: "lookup".into(),
+            output: format!("result for {}", params),
+            ..Default::default()
+        })
+    })
+}
+
+#[tokio::test]
+async fn test_cached_tool_runs_once_per_arguments() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let tool = CachedTool::new(Arc::new(counting_tool(calls.clone())));
+    let mut context = RunContext::new(Context::new());
+
+    let first = tool.execute(&mut context, r#"{"q":"rust"}"#).await.unwrap();
+    let second = tool.execute(&mut context, r#"{"q":"rust"}"#).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(first.output, second.output);
+    assert_eq!(tool.name(), "lookup");
+
+    tool.execute(&mut context, r#"{"q":"go"}"#).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_cached_tool_expires_results_after_ttl() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let tool =
+        CachedTool::new(Arc::new(counting_tool(calls.clone()))).with_ttl(Duration::from_millis(20));
+    let mut context = RunContext::new(Context::new());
+
+    tool.execute(&mut context, "{}").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    tool.execute(&mut context, "{}").await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_cached_tool_drops_expired_results_when_caching() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let tool =
+        CachedTool::new(Arc::new(counting_tool(calls.clone()))).with_ttl(Duration::from_millis(20));
+    let mut context = RunContext::new(Context::new());
+
+    tool.execute(&mut context, r#"{"q":"rust"}"#).await.unwrap();
+    tool.execute(&mut context, r#"{"q":"go"}"#).await.unwrap();
+    assert_eq!(tool.len(), 2);
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    tool.execute(&mut context, r#"{"q":"zig"}"#).await.unwrap();
+    assert_eq!(tool.len(), 1);
+}
+
+#[tokio::test]
+async fn test_cached_tool_evicts_oldest_beyond_max_entries() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let tool = CachedTool::new(Arc::new(counting_tool(calls.clone()))).with_max_entries(2);
+    let mut context = RunContext::new(Context::new());
+
+    for q in ["rust", "go", "zig"] {
+        let params = format!(r#"{{"q":"{}"}}"#, q);
+        tool.execute(&mut context, &params).await.unwrap();
+    }
+    assert_eq!(tool.len(), 2);
+
+    // The newest results are still cached, the oldest has to run again
+    tool.execute(&mut context, r#"{"q":"zig"}"#).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    tool.execute(&mut context, r#"{"q":"rust"}"#).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+}
+
+// Tool whose output is far longer than it allows
+struct VerboseTool;
+
+#[async_trait]
+impl Tool for VerboseTool {
+    fn name(&self) -> &str {
+        "verbose_tool"
+    }
+
+    fn description(&self) -> &str {
+        "Returns a lot of text"
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({"type": "object"})
+    }
+
+    fn max_output_chars(&self) -> Option<usize> {
+        Some(5)
+    }
+
+    async fn execute(
+        &self,
+        _context: &mut RunContext,
+        _params: &str,
+    ) -> Result<ToolResult, AgentError> {
+        Ok(ToolResult {
+            tool_name: self.name().to_string(),
+            output: "éééééééééé".to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_execute_tool_call_truncates_to_tool_limit() {
+    let mut context = RunContext::new(Context::new());
+    let tool_call = ToolCall {
+        id: "call_1".to_string(),
+        name: "verbose_tool".to_string(),
+        arguments: "{}".to_string(),
+    };
+
+    let result = execute_tool_call(&VerboseTool, &mut context, &tool_call)
+        .await
+        .unwrap();
+
+    assert_eq!(result.output, format!("ééééé{}", TRUNCATION_MARKER));
+}
@@ -1,4 +1,7 @@
-use adk::types::{Context, Message, RunContext};
+use adk::error::AgentError;
+use adk::tool::ToolResult;
+use adk::types::{Context, Message, RunContext, RunContextBuilder, TYPED_STATE_KEY};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 #[test]
@@ -38,6 +41,7 @@ fn test_message_creation() {
         role: "user".to_string(),
         content: "Hello".to_string(),
         tool_name: None,
+        ..Default::default()
     };
 
     assert_eq!(message.role, "user");
@@ -51,6 +55,7 @@ fn test_message_with_tool_name() {
         role: "tool".to_string(),
         content: "Tool output".to_string(),
         tool_name: Some("calculator".to_string()),
+        ..Default::default()
     };
 
     assert_eq!(message.role, "tool");
@@ -64,6 +69,7 @@ fn test_message_serialization() {
         role: "assistant".to_string(),
         content: "How can I help?".to_string(),
         tool_name: None,
+        ..Default::default()
     };
 
     let serialized = serde_json::to_string(&message).unwrap();
@@ -146,6 +152,120 @@ fn test_run_context_message_ordering() {
     assert_eq!(run_context.messages[3].role, "assistant");
 }
 
+#[test]
+fn test_run_context_message_accessors() {
+    let mut run_context = RunContext::builder()
+        .system("You are helpful")
+        .user("What's 2+2?")
+        .assistant("Let me check")
+        .tool("calculator", "4")
+        .assistant("The answer is 4")
+        .user("And 3+3?")
+        .tool("calculator", "6")
+        .build();
+
+    assert_eq!(run_context.last_message().unwrap().content, "6");
+    assert_eq!(run_context.last_user_message().unwrap().content, "And 3+3?");
+    assert_eq!(
+        run_context.last_assistant_message().unwrap().content,
+        "The answer is 4"
+    );
+    let tool_outputs: Vec<_> = run_context
+        .messages_by_role("tool")
+        .map(|message| message.content.as_str())
+        .collect();
+    assert_eq!(tool_outputs, ["4", "6"]);
+    assert_eq!(run_context.messages_by_role("developer").count(), 0);
+
+    run_context.messages.clear();
+    assert!(run_context.last_message().is_none());
+    assert!(run_context.last_user_message().is_none());
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Cart {
+    items: Vec<String>,
+    total_cents: u64,
+}
+
+#[test]
+fn test_typed_state_persists_saved_changes() {
+    let mut run_context = RunContext::new(Context::new());
+
+    let mut cart = run_context.typed_state::<Cart>().unwrap();
+    assert_eq!(*cart, Cart::default());
+    cart.items.push("book".to_string());
+    cart.total_cents += 1299;
+    cart.save().unwrap();
+
+    // Edits that aren't saved are dropped
+    let mut cart = run_context.typed_state::<Cart>().unwrap();
+    cart.items.clear();
+    drop(cart);
+
+    let cart = run_context.typed_state::<Cart>().unwrap().into_inner();
+    assert_eq!(
+        cart,
+        Cart {
+            items: vec!["book".to_string()],
+            total_cents: 1299,
+        }
+    );
+    assert_eq!(
+        run_context.context.data[TYPED_STATE_KEY],
+        json!({"items": ["book"], "total_cents": 1299})
+    );
+}
+
+#[test]
+fn test_typed_state_rejects_mismatched_state() {
+    let mut context = Context::new().with_data(TYPED_STATE_KEY, "not a cart");
+
+    match context.typed_state::<Cart>() {
+        Err(AgentError::ContextError(msg)) => {
+            assert!(msg.starts_with("invalid value for context key __typed_state"))
+        }
+        other => panic!("Expected ContextError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_context_merge_prefers_other() {
+    let mut context = Context::new()
+        .with_data("model", "gpt-4")
+        .with_data("user", "alice");
+    let request = Context::new()
+        .with_data("user", "bob")
+        .with_data("locale", "en");
+
+    context.merge(&request);
+
+    assert_eq!(context.data.len(), 3);
+    assert_eq!(context.data["model"], "gpt-4");
+    assert_eq!(context.data["user"], "bob");
+    assert_eq!(context.data["locale"], "en");
+}
+
+#[test]
+fn test_context_diff_reports_added_changed_and_removed_keys() {
+    let before = Context::new()
+        .with_data("model", "gpt-4")
+        .with_data("user", "alice")
+        .with_data("draft", true);
+    let after = Context::new()
+        .with_data("model", "gpt-4")
+        .with_data("user", "bob")
+        .with_data("locale", "en");
+
+    let diff = before.diff(&after);
+
+    assert_eq!(diff.len(), 3);
+    assert_eq!(diff["user"], (Some(json!("alice")), Some(json!("bob"))));
+    assert_eq!(diff["locale"], (None, Some(json!("en"))));
+    assert_eq!(diff["draft"], (Some(json!(true)), None));
+    assert!(before.diff(&before).is_empty());
+}
+
 #[test]
 fn test_context_default() {
     let context = Context::default();
@@ -171,3 +291,186 @@ fn test_run_context_clone() {
     assert_eq!(run_context.messages.len(), cloned.messages.len());
     assert_eq!(run_context.messages[0].content, cloned.messages[0].content);
 }
+
+#[test]
+fn test_context_require_present() {
+    let context = Context::new()
+        .with_data("auth_token", "secret")
+        .with_data("retries", 3);
+
+    let token: String = context.require("auth_token").unwrap();
+    let retries: u32 = context.require("retries").unwrap();
+
+    assert_eq!(token, "secret");
+    assert_eq!(retries, 3);
+}
+
+#[test]
+fn test_context_require_missing() {
+    let context = Context::new();
+
+    match context.require::<String>("auth_token") {
+        Err(AgentError::ContextError(msg)) => {
+            assert_eq!(msg, "missing required context key: auth_token")
+        }
+        _ => panic!("Expected ContextError"),
+    }
+}
+
+#[test]
+fn test_context_require_wrong_type() {
+    let context = Context::new().with_data("auth_token", 42);
+
+    match context.require::<String>("auth_token") {
+        Err(AgentError::ContextError(msg)) => {
+            assert!(msg.starts_with("invalid value for context key auth_token"))
+        }
+        _ => panic!("Expected ContextError"),
+    }
+}
+
+#[test]
+fn test_run_context_builder() {
+    let run_context = RunContext::builder()
+        .system("You are helpful")
+        .user("What's 2+2?")
+        .tool("calculator", "4")
+        .assistant("The answer is 4")
+        .data("session_id", "abc123")
+        .build();
+
+    let messages: Vec<(&str, &str, Option<&str>)> = run_context
+        .messages
+        .iter()
+        .map(|m| (m.role.as_str(), m.content.as_str(), m.tool_name.as_deref()))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            ("system", "You are helpful", None),
+            ("user", "What's 2+2?", None),
+            ("tool", "4", Some("calculator")),
+            ("assistant", "The answer is 4", None),
+        ]
+    );
+    assert_eq!(run_context.context.data["session_id"], json!("abc123"));
+}
+
+#[test]
+fn test_run_context_builder_empty() {
+    let run_context = RunContextBuilder::new().build();
+
+    assert!(run_context.messages.is_empty());
+    assert!(run_context.context.data.is_empty());
+}
+
+#[test]
+fn test_run_context_add_tool_result() {
+    let mut run_context = RunContext::new(Context::new());
+
+    run_context.add_tool_result(adk::ToolResult {
+        tool_name: "calculator".to_string(),
+        output: "42".to_string(),
+        call_id: Some("call_1".to_string()),
+        arguments: Some("{}".to_string()),
+        success: true,
+        follow_up: None,
+        parts: Vec::new(),
+    });
+
+    let message = &run_context.messages[0];
+    assert_eq!(message.role, "tool");
+    assert_eq!(message.content, "42");
+    assert_eq!(message.tool_name, Some("calculator".to_string()));
+    assert_eq!(message.tool_call_id, Some("call_1".to_string()));
+}
+
+#[test]
+fn test_run_context_get_data_missing_and_wrong_type() {
+    let mut context = RunContext::new(Context::new());
+    assert_eq!(context.get_data::<u32>("missing").unwrap(), None);
+
+    context.set_data("name", "adk").unwrap();
+    match context.get_data::<u32>("name") {
+        Err(AgentError::ContextError(msg)) => {
+            assert!(msg.starts_with("invalid value for context key name"))
+        }
+        other => panic!("Expected ContextError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_run_context_to_openai_messages() {
+    let mut context = RunContext::builder()
+        .system("You are helpful")
+        .user("What's the weather?")
+        .build();
+    context.add_tool_result(ToolResult {
+        tool_name: "weather".to_string(),
+        output: "Sunny".to_string(),
+        call_id: Some("call_1".to_string()),
+        ..Default::default()
+    });
+    context.add_message("assistant", "It's sunny.");
+
+    assert_eq!(
+        json!(context.to_openai_messages()),
+        json!([
+            {"role": "system", "content": "You are helpful"},
+            {"role": "user", "content": "What's the weather?"},
+            {"role": "tool", "content": "Sunny", "tool_call_id": "call_1"},
+            {"role": "assistant", "content": "It's sunny."}
+        ])
+    );
+}
+
+#[test]
+fn test_run_context_add_failed_tool_result() {
+    let mut context = RunContext::new(Context::new());
+    context.add_tool_result(ToolResult {
+        tool_name: "calculator".to_string(),
+        output: "division by zero".to_string(),
+        call_id: Some("call_1".to_string()),
+        success: false,
+        ..Default::default()
+    });
+
+    let message = &context.messages[0];
+    assert_eq!(message.role, "tool");
+    assert_eq!(message.content, "Error: division by zero");
+    assert_eq!(message.tool_call_id, Some("call_1".to_string()));
+    assert!(ToolResult::default().success);
+}
+
+#[test]
+fn test_message_constructors() {
+    let system = Message::system("Be brief");
+    assert_eq!(
+        (system.role.as_str(), system.content.as_str()),
+        ("system", "Be brief")
+    );
+    assert_eq!(system.tool_name, None);
+
+    let user = Message::user("Hi");
+    assert_eq!((user.role.as_str(), user.content.as_str()), ("user", "Hi"));
+
+    let assistant = Message::assistant("Hello!");
+    assert_eq!(assistant.role, "assistant");
+    assert_eq!(assistant.content, "Hello!");
+
+    let tool = Message::tool("calculator", "42");
+    assert_eq!(tool.role, "tool");
+    assert_eq!(tool.content, "42");
+    assert_eq!(tool.tool_name, Some("calculator".to_string()));
+    assert_eq!(tool.tool_call_id, None);
+
+    let from_str: Message = "Hi".into();
+    assert_eq!(from_str.role, "user");
+    assert_eq!(from_str.content, "Hi");
+}
+
+#[test]
+fn test_message_display() {
+    assert_eq!(Message::user("Hi there").to_string(), "user: Hi there");
+    assert_eq!(Message::tool("calculator", "42").to_string(), "tool: 42");
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "wasm")]
+
+use adk::AgentError;
+use adk::tool::Tool;
+use adk::tool::wasm::WasmTool;
+use adk::types::{Context, RunContext};
+use serde_json::json;
+
+fn fixture(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[tokio::test]
+async fn test_wasm_tool_echoes_input() {
+    let tool = WasmTool::from_file("echo", "Echoes the input", fixture("echo.wasm")).unwrap();
+    assert_eq!(
+        tool.parameters_schema(),
+        json!({
+            "type": "object",
+            "properties": {"text": {"type": "string"}},
+            "required": ["text"]
+        })
+    );
+
+    let mut context = RunContext::new(Context::new());
+    let result = tool
+        .execute(&mut context, r#"{"text": "hello wasm"}"#)
+        .await
+        .unwrap();
+
+    assert_eq!(result.tool_name, "echo");
+    assert_eq!(result.output, r#"{"text": "hello wasm"}"#);
+}
+
+#[tokio::test]
+async fn test_wasm_tool_maps_trap_to_tool_error() {
+    let tool = WasmTool::from_file("trap", "Always traps", fixture("trap.wasm")).unwrap();
+
+    let mut context = RunContext::new(Context::new());
+    match tool.execute(&mut context, "{}").await {
+        Err(AgentError::ToolErrorWithSource { message, .. }) => {
+            assert!(message.starts_with("WASM tool trap failed"))
+        }
+        other => panic!("Expected ToolError, got {:?}", other.map(|r| r.output)),
+    }
+}
+
+#[tokio::test]
+async fn test_wasm_tool_stops_endless_loop() {
+    let tool = WasmTool::from_file("loop", "Never returns", fixture("loop.wasm"))
+        .unwrap()
+        .with_fuel(1_000_000);
+
+    let mut context = RunContext::new(Context::new());
+    match tool.execute(&mut context, "{}").await {
+        Err(AgentError::ToolErrorWithSource { message, .. }) => {
+            assert!(message.starts_with("WASM tool loop failed"), "{}", message);
+            assert!(message.contains("fuel"), "{}", message);
+        }
+        other => panic!("Expected ToolError, got {:?}", other.map(|r| r.output)),
+    }
+}
+
+#[tokio::test]
+async fn test_wasm_tool_caps_memory() {
+    let load = || WasmTool::from_file("grow", "Grows memory by 1 GiB", fixture("grow.wasm"));
+    let mut context = RunContext::new(Context::new());
+
+    let limited = load().unwrap();
+    match limited.execute(&mut context, "{}").await {
+        Err(AgentError::ToolErrorWithSource { message, .. }) => {
+            assert!(message.starts_with("WASM tool grow failed"), "{}", message)
+        }
+        other => panic!("Expected ToolError, got {:?}", other.map(|r| r.output)),
+    }
+
+    let roomy = load().unwrap().with_max_memory(2 * 1024 * 1024 * 1024);
+    let result = roomy.execute(&mut context, "{}").await.unwrap();
+    assert_eq!(result.output, "grown");
+}
+
+#[test]
+fn test_wasm_tool_rejects_invalid_module() {
+    match WasmTool::new("broken", "Not WASM", b"not a module") {
+        Err(AgentError::ConfigurationError(msg)) => assert!(msg.starts_with("Invalid WASM module")),
+        _ => panic!("Expected ConfigurationError"),
+    }
+}
@@ -70,6 +70,7 @@ impl Tool for CalculatorTool {
         Ok(ToolResult {
             tool_name: self.name().to_string(),
             output: result.to_string(),
+            ..Default::default()
         })
     }
 }
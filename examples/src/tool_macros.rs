@@ -55,6 +55,7 @@ fn create_manual_calculator_tool() -> adk::tool::FunctionTool {
             Ok(ToolResult {
                 tool_name: "manual_calculator".to_string(),
                 output: result,
+                ..Default::default()
             })
         }
     )
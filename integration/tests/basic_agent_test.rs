@@ -66,6 +66,7 @@ impl Tool for CalculatorTool {
         Ok(ToolResult {
             tool_name: self.name().to_string(),
             output: result.to_string(),
+            ..Default::default()
         })
     }
 }
@@ -163,6 +164,7 @@ fn test_tool_result_creation() {
     let result = ToolResult {
         tool_name: "test_tool".to_string(),
         output: "test output".to_string(),
+        ..Default::default()
     };
 
     assert_eq!(result.tool_name, "test_tool");
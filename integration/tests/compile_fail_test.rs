@@ -0,0 +1,5 @@
+#[test]
+fn test_tool_fn_compile_errors() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
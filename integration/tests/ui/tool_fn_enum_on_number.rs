@@ -0,0 +1,8 @@
+use adk::tool::tool_fn;
+
+#[tool_fn(name = "scale", description = "Scales a number")]
+fn scale(_context: &mut adk::types::RunContext, #[param(enum = ["2", "10"])] factor: i32) -> String {
+    (factor * 2).to_string()
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use adk::tool::tool_fn;
+
+#[tool_fn(name = "add", description = "Adds two numbers")]
+fn add(_context: &adk::types::RunContext, a: f64, b: f64) -> String {
+    (a + b).to_string()
+}
+
+fn main() {}